@@ -0,0 +1,240 @@
+//! Full device backup image, streamed over PE in chunks.
+//!
+//! The image carries every backed-up PE resource as the body a PE Set of that
+//! resource takes, so a restore is an upload of all of them. Resources kept
+//! per bank go in once for every bank, tagged with it; shared ones carry bank 0:
+//!
+//! ```text
+//! ['P' 'B'][version][entry count lo][entry count hi]
+//! [bank][resource][len lo][len hi][body]   × entry count
+//! [crc16 lo][crc16 hi]               CRC-16/CCITT-FALSE over all of the above
+//! ```
+//!
+//! The image is cut into `CHUNK_SIZE` byte chunk bodies regardless of entry
+//! boundaries. `Writer` builds the chunks on the fly and `Reader` parses them
+//! back one byte at a time, so neither side holds more than one chunk and one
+//! entry body.
+
+use heapless::Vec;
+
+use crate::eeprom::crc16_update;
+
+/// Backup image layout version. Bump when the image layout changes.
+pub const BACKUP_VERSION: u8 = 2;
+
+/// Image bytes per PE chunk.
+pub const CHUNK_SIZE: usize = crate::MAX_PRESET_SIZE;
+
+const MAGIC: [u8; 2] = *b"PB";
+const HEADER_SIZE: usize = 5; // magic, version, entry count
+const ENTRY_HEADER_SIZE: usize = 4; // bank, resource, length
+const CRC_SIZE: usize = 2;
+const CRC_INIT: u16 = 0xFFFF;
+
+/// Size of an image with `entries` entries whose bodies add up to `bodies`.
+pub fn image_size(entries: u16, bodies: usize) -> usize {
+    HEADER_SIZE + entries as usize * ENTRY_HEADER_SIZE + bodies + CRC_SIZE
+}
+
+/// Number of chunks an image of `size` bytes is sent in.
+pub fn chunk_count(size: usize) -> u16 {
+    size.div_ceil(CHUNK_SIZE) as u16
+}
+
+/// Header written ahead of each entry body.
+pub fn entry_header(bank: u8, resource: u8, len: usize) -> [u8; ENTRY_HEADER_SIZE] {
+    [bank, resource, len as u8, (len >> 8) as u8]
+}
+
+/// A restored entry: bank, resource and Set body.
+pub type Entry<'a> = (u8, u8, &'a [u8]);
+
+/// Builds an image and cuts it into chunks.
+pub struct Writer {
+    chunk: Vec<u8, CHUNK_SIZE>,
+    crc: u16,
+}
+
+impl Writer {
+    /// Start an image of `entries` entries.
+    pub fn new(entries: u16) -> Self {
+        let mut writer = Self {
+            chunk: Vec::new(),
+            crc: CRC_INIT,
+        };
+        let [lo, hi] = entries.to_le_bytes();
+        let header = [MAGIC[0], MAGIC[1], BACKUP_VERSION, lo, hi];
+        writer.write(&mut &header[..]);
+        writer
+    }
+
+    /// Append image bytes from `data`. Returns a chunk once one is full, with
+    /// `data` advanced past what went into it; call again until None.
+    pub fn write(&mut self, data: &mut &[u8]) -> Option<Vec<u8, CHUNK_SIZE>> {
+        let take = data.len().min(CHUNK_SIZE - self.chunk.len());
+        let (head, rest) = data.split_at(take);
+        self.crc = crc16_update(self.crc, head);
+        self.chunk.extend_from_slice(head).ok();
+        *data = rest;
+        (self.chunk.len() == CHUNK_SIZE).then(|| core::mem::take(&mut self.chunk))
+    }
+
+    /// Append the checksum and return the chunks still to send.
+    pub fn finish(mut self) -> Vec<Vec<u8, CHUNK_SIZE>, 2> {
+        let crc = self.crc.to_le_bytes();
+        let mut data = &crc[..];
+        let mut chunks = Vec::new();
+        while let Some(chunk) = self.write(&mut data) {
+            chunks.push(chunk).ok();
+        }
+        if !self.chunk.is_empty() {
+            chunks.push(self.chunk).ok();
+        }
+        chunks
+    }
+}
+
+/// Why a restore image was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestoreError {
+    /// Chunk out of order, or the chunk count changed.
+    Chunk,
+    /// Bad magic or version, oversized entry, or data past the end.
+    Format,
+    /// Checksum mismatch.
+    Checksum,
+    /// Image ended early.
+    Truncated,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Header,
+    EntryHeader,
+    Body,
+    Crc,
+    Done,
+}
+
+/// Parses a restore image as its chunks arrive.
+pub struct Reader {
+    chunks: u16,
+    next_chunk: u16,
+    field: Field,
+    /// Bytes of the current field.
+    buf: Vec<u8, CHUNK_SIZE>,
+    /// `buf` holds a field that was handed out and is cleared on the next byte.
+    consumed: bool,
+    entries_left: u16,
+    bank: u8,
+    resource: u8,
+    len: usize,
+    crc: u16,
+}
+
+impl Reader {
+    /// Start reading an image sent in `chunks` chunks.
+    pub fn new(chunks: u16) -> Self {
+        Self {
+            chunks,
+            next_chunk: 1,
+            field: Field::Header,
+            buf: Vec::new(),
+            consumed: false,
+            entries_left: 0,
+            bank: 0,
+            resource: 0,
+            len: 0,
+            crc: CRC_INIT,
+        }
+    }
+
+    /// Check that chunk `chunk` of `chunks` is the next one.
+    pub fn start_chunk(&mut self, chunk: u16, chunks: u16) -> Result<(), RestoreError> {
+        if chunk != self.next_chunk || chunks != self.chunks {
+            return Err(RestoreError::Chunk);
+        }
+        self.next_chunk += 1;
+        Ok(())
+    }
+
+    /// Feed the next image byte. Returns an entry once its last byte is in.
+    pub fn push(&mut self, byte: u8) -> Result<Option<Entry<'_>>, RestoreError> {
+        if core::mem::take(&mut self.consumed) {
+            self.buf.clear();
+        }
+        if self.field != Field::Crc {
+            self.crc = crc16_update(self.crc, &[byte]);
+        }
+        match self.field {
+            Field::Header => {
+                self.buf.push(byte).ok();
+                if self.buf.len() == HEADER_SIZE {
+                    if self.buf[..2] != MAGIC || self.buf[2] != BACKUP_VERSION {
+                        return Err(RestoreError::Format);
+                    }
+                    self.entries_left = u16::from_le_bytes([self.buf[3], self.buf[4]]);
+                    self.next_entry();
+                }
+            }
+            Field::EntryHeader => {
+                self.buf.push(byte).ok();
+                if self.buf.len() == ENTRY_HEADER_SIZE {
+                    self.bank = self.buf[0];
+                    self.resource = self.buf[1];
+                    self.len = self.buf[2] as usize | (self.buf[3] as usize) << 8;
+                    if self.len > crate::MAX_PRESET_SIZE {
+                        return Err(RestoreError::Format);
+                    }
+                    self.buf.clear();
+                    self.field = Field::Body;
+                    if self.len == 0 {
+                        return Ok(Some(self.entry()));
+                    }
+                }
+            }
+            Field::Body => {
+                self.buf.push(byte).ok();
+                if self.buf.len() == self.len {
+                    return Ok(Some(self.entry()));
+                }
+            }
+            Field::Crc => {
+                self.buf.push(byte).ok();
+                if self.buf.len() == CRC_SIZE {
+                    if u16::from_le_bytes([self.buf[0], self.buf[1]]) != self.crc {
+                        return Err(RestoreError::Checksum);
+                    }
+                    self.field = Field::Done;
+                }
+            }
+            Field::Done => return Err(RestoreError::Format),
+        }
+        Ok(None)
+    }
+
+    /// Check that the whole image has been read.
+    pub fn finish(&self) -> Result<(), RestoreError> {
+        if self.field == Field::Done && self.next_chunk > self.chunks {
+            Ok(())
+        } else {
+            Err(RestoreError::Truncated)
+        }
+    }
+
+    /// Hand out the completed entry body and move on.
+    fn entry(&mut self) -> Entry<'_> {
+        self.entries_left -= 1;
+        self.next_entry();
+        (self.bank, self.resource, &self.buf)
+    }
+
+    fn next_entry(&mut self) {
+        self.consumed = true;
+        self.field = if self.entries_left > 0 {
+            Field::EntryHeader
+        } else {
+            Field::Crc
+        };
+    }
+}
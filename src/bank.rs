@@ -0,0 +1,60 @@
+//! Preset banks: several sets of presets stored side by side (one per band,
+//! say), of which one is active.
+//!
+//! A bank holds presets, their extensions and the setlist songs (resources
+//! `0x00..0x5F`) along with its active song; profiles, settings and the
+//! global config are shared by all banks. PE uploads and Gets address the
+//! active bank; backups cover every bank. Each bank keeps its own runtime
+//! state: switching saves the EEPROM image of the old bank to flash and puts
+//! the new bank's on the EEPROM, then the device reboots into it.
+
+use heapless::Vec;
+use midi_controller::config::Label;
+use serde::{Deserialize, Serialize};
+
+/// Number of banks. Bank 0 is where presets lived before banks existed.
+pub const MAX_BANKS: usize = 8;
+
+/// PE resource ID of the bank names (`Banks`, postcard).
+pub const BANKS_RESOURCE: u8 = 0x69;
+
+/// PE resource ID of the active bank (one byte). A Set switches banks.
+pub const BANK_SELECT_RESOURCE: u8 = 0x6A;
+
+/// Bank names and the active bank.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Banks {
+    /// Name per bank. Empty = unused (bank 0 is always in use).
+    pub names: Vec<Label, MAX_BANKS>,
+    /// Active bank, stored apart from the names.
+    #[serde(skip)]
+    pub active: u8,
+}
+
+impl Banks {
+    pub const fn new() -> Self {
+        Self {
+            names: Vec::new(),
+            active: 0,
+        }
+    }
+
+    /// Name of `bank`; empty if it has none.
+    pub fn name(&self, bank: u8) -> &str {
+        self.names.get(bank as usize).map_or("", |n| n.as_str())
+    }
+
+    /// Whether `bank` can be selected from the device: bank 0, a named bank,
+    /// or the active one.
+    pub fn is_used(&self, bank: u8) -> bool {
+        bank == 0 || bank == self.active || !self.name(bank).is_empty()
+    }
+
+    /// The next bank in use after `bank`, wrapping around.
+    pub fn next(&self, bank: u8) -> u8 {
+        (1..=MAX_BANKS as u8)
+            .map(|step| (bank + step) % MAX_BANKS as u8)
+            .find(|&b| self.is_used(b))
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,311 @@
+//! Per-port MIDI clock output: each port (DIN, USB) can divide or multiply the
+//! 24 ppqn clock, swing it, and opt out of Start/Stop/Continue.
+//!
+//! Used for the internal clock and for forwarded external clock alike. Output
+//! ticks are queued with a due time so multiplied and swung ticks land between
+//! source ticks; the caller sends whatever `poll` returns.
+
+use heapless::Deque;
+use midi_controller::routing::MidiPort;
+use serde::{Deserialize, Serialize};
+
+/// Number of clock output ports.
+pub const CLOCK_PORTS: usize = 2;
+
+/// Index of the DIN port in `Settings::clock`.
+pub const PORT_DIN: usize = 0;
+
+/// Index of the USB port in `Settings::clock`.
+pub const PORT_USB: usize = 1;
+
+/// Largest clock multiplier.
+pub const MAX_MULTIPLY: u8 = 4;
+
+/// Largest swing offset (75% swing).
+pub const MAX_SWING: u8 = 25;
+
+/// Output ticks waiting for their due time, per port.
+const MAX_QUEUED: usize = 16;
+
+/// Clock ticks per eighth note at 24 ppqn.
+const TICKS_PER_EIGHTH: u32 = 12;
+
+/// Source ticks slower than this (below 10 BPM) restart the interval estimate.
+const MAX_EXTERNAL_INTERVAL_US: u32 = 250_000;
+
+const fn port_flag(port: usize) -> MidiPort {
+    if port == PORT_DIN {
+        MidiPort::DIN
+    } else {
+        MidiPort::USB
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ClockRate {
+    #[default]
+    Normal,
+    /// One output tick per N source ticks (2 = half time).
+    Divide(u8),
+    /// N output ticks per source tick (up to `MAX_MULTIPLY`).
+    Multiply(u8),
+}
+
+/// Clock output settings of one port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortClock {
+    pub enabled: bool,
+    pub rate: ClockRate,
+    /// Swing offset in percent of an eighth note (0 = straight, 16 ≈ triplet
+    /// feel, up to `MAX_SWING`): off-beat sixteenths start this much later.
+    pub swing: u8,
+    /// Send Start/Stop/Continue.
+    pub transport: bool,
+}
+
+impl PortClock {
+    pub const fn new() -> Self {
+        Self {
+            enabled: true,
+            rate: ClockRate::Normal,
+            swing: 0,
+            transport: true,
+        }
+    }
+}
+
+impl Default for PortClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Delay of output tick `index` (at `interval_us` spacing) caused by swing.
+/// The first half of each eighth is stretched and the second compressed, so
+/// ticks stay in order.
+pub fn swing_offset_us(index: u32, interval_us: u32, swing: u8) -> u32 {
+    let swing = swing.min(MAX_SWING) as u64;
+    if swing == 0 {
+        return 0;
+    }
+    let t = interval_us as u64;
+    let p = (index % TICKS_PER_EIGHTH) as u64;
+    let half = TICKS_PER_EIGHTH as u64 / 2;
+    let eighth = t * TICKS_PER_EIGHTH as u64;
+    let split = eighth * (50 + swing) / 100;
+    let at = if p < half {
+        p * split / half
+    } else {
+        split + (p - half) * (eighth - split) / half
+    };
+    (at - p * t) as u32
+}
+
+#[derive(Debug, Clone)]
+struct PortState {
+    source_ticks: u32,
+    out_ticks: u32,
+    /// Due times (µs) of queued output ticks, in order.
+    queue: Deque<u32, MAX_QUEUED>,
+}
+
+impl PortState {
+    const fn new() -> Self {
+        Self {
+            source_ticks: 0,
+            out_ticks: 0,
+            queue: Deque::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.source_ticks = 0;
+        self.out_ticks = 0;
+        self.queue.clear();
+    }
+}
+
+/// Shapes one clock source into the per-port output streams.
+#[derive(Debug, Clone)]
+pub struct ClockShaper {
+    ports: [PortState; CLOCK_PORTS],
+    /// Time of the last external tick, for measuring its interval.
+    last_tick_us: Option<u32>,
+    /// Last measured external interval (0 = unknown), reused for the first
+    /// tick after Start/Stop.
+    external_interval_us: u32,
+}
+
+impl Default for ClockShaper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClockShaper {
+    pub const fn new() -> Self {
+        Self {
+            ports: [PortState::new(), PortState::new()],
+            last_tick_us: None,
+            external_interval_us: 0,
+        }
+    }
+
+    /// A source clock tick at `now_us` for the ports in `dest`; `interval_us`
+    /// is the spacing of source ticks (0 = unknown: no swing, and a multiplied
+    /// port sends a single tick instead of a burst).
+    pub fn tick(
+        &mut self,
+        config: &[PortClock; CLOCK_PORTS],
+        dest: MidiPort,
+        now_us: u32,
+        interval_us: u32,
+    ) {
+        for (i, (cfg, port)) in config.iter().zip(self.ports.iter_mut()).enumerate() {
+            if !cfg.enabled || !dest.contains(port_flag(i)) {
+                continue;
+            }
+            let k = port.source_ticks;
+            port.source_ticks = port.source_ticks.wrapping_add(1);
+            let (count, out_interval) = match cfg.rate {
+                ClockRate::Normal => (1, interval_us),
+                ClockRate::Divide(d) => {
+                    let d = d.max(1) as u32;
+                    if k % d != 0 {
+                        continue;
+                    }
+                    (1, interval_us.saturating_mul(d))
+                }
+                ClockRate::Multiply(_) if interval_us == 0 => (1, 0),
+                ClockRate::Multiply(m) => {
+                    let m = m.clamp(1, MAX_MULTIPLY) as u32;
+                    (m, interval_us / m)
+                }
+            };
+            for j in 0..count {
+                let swing = swing_offset_us(port.out_ticks, out_interval, cfg.swing);
+                port.out_ticks = port.out_ticks.wrapping_add(1);
+                let due = now_us.wrapping_add(j * out_interval).wrapping_add(swing);
+                // A full queue drops the tick rather than blocking the clock
+                port.queue.push_back(due).ok();
+            }
+        }
+    }
+
+    /// An external clock tick (0xF8) forwarded to `dest`; the interval is
+    /// measured from the previous one. The first tick after boot or Start/Stop
+    /// reuses the last measured interval.
+    pub fn external_tick(
+        &mut self,
+        config: &[PortClock; CLOCK_PORTS],
+        dest: MidiPort,
+        now_us: u32,
+    ) {
+        let measured = self
+            .last_tick_us
+            .map(|last| now_us.wrapping_sub(last))
+            .filter(|i| *i <= MAX_EXTERNAL_INTERVAL_US);
+        if let Some(interval) = measured {
+            self.external_interval_us = interval;
+        }
+        self.last_tick_us = Some(now_us);
+        self.tick(config, dest, now_us, self.external_interval_us);
+    }
+
+    /// Start (0xFA), Stop (0xFC) or Continue (0xFB) for the ports in `dest`.
+    /// Returns the ports that should receive it. Start restarts the division
+    /// and swing grid; Start and Stop drop queued ticks.
+    pub fn transport(
+        &mut self,
+        config: &[PortClock; CLOCK_PORTS],
+        dest: MidiPort,
+        status: u8,
+    ) -> MidiPort {
+        match status {
+            0xFA => {
+                self.ports.iter_mut().for_each(PortState::reset);
+                self.last_tick_us = None;
+            }
+            0xFC => {
+                self.ports.iter_mut().for_each(|p| p.queue.clear());
+                self.last_tick_us = None;
+            }
+            _ => {}
+        }
+        let mut out = MidiPort::empty();
+        for (i, cfg) in config.iter().enumerate() {
+            if cfg.enabled && cfg.transport && dest.contains(port_flag(i)) {
+                out |= port_flag(i);
+            }
+        }
+        out
+    }
+
+    /// Ports with a clock tick due at `now_us`. Call until it returns empty.
+    pub fn poll(&mut self, now_us: u32) -> MidiPort {
+        let mut out = MidiPort::empty();
+        for (i, port) in self.ports.iter_mut().enumerate() {
+            if let Some(&due) = port.queue.front() {
+                if is_due(due, now_us) {
+                    port.queue.pop_front();
+                    out |= port_flag(i);
+                }
+            }
+        }
+        out
+    }
+
+    /// Time until the earliest queued output tick (0 = due now).
+    pub fn next_due_in(&self, now_us: u32) -> Option<u32> {
+        self.ports
+            .iter()
+            .filter_map(|p| p.queue.front().copied())
+            .map(|due| {
+                if is_due(due, now_us) {
+                    0
+                } else {
+                    due.wrapping_sub(now_us)
+                }
+            })
+            .min()
+    }
+}
+
+/// Picks the clock that drives tempo-synced features (looper bar sync, LFO):
+/// incoming clock while it arrives, the internal clock otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct ClockSource {
+    /// Time of the last external tick (ms), while external clock arrives.
+    last_external_ms: Option<u32>,
+}
+
+impl ClockSource {
+    pub const fn new() -> Self {
+        Self {
+            last_external_ms: None,
+        }
+    }
+
+    /// An incoming clock tick (0xF8). External clock always drives.
+    pub fn external_tick(&mut self, now_ms: u32) -> bool {
+        self.last_external_ms = Some(now_ms);
+        true
+    }
+
+    /// An internal clock tick. Returns true if it drives, i.e. no external
+    /// clock arrived within the slowest external tick interval.
+    pub fn internal_tick(&mut self, now_ms: u32) -> bool {
+        if let Some(last) = self.last_external_ms {
+            if now_ms.wrapping_sub(last) <= MAX_EXTERNAL_INTERVAL_US / 1000 {
+                return false;
+            }
+            self.last_external_ms = None;
+        }
+        true
+    }
+}
+
+/// Wrapping-safe `now >= due`.
+fn is_due(due: u32, now_us: u32) -> bool {
+    now_us.wrapping_sub(due) <= u32::MAX / 2
+}
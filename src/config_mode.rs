@@ -8,7 +8,13 @@
 //! - Encoder turns show raw direction + mapped value
 //! - Expression pedals show raw ADC value (for calibration)
 //! - Idle shows firmware version, preset count, global config summary
+//! - A Vol encoder button click toggles between the idle and storage health screens
+//! - Holding the Vol encoder button alone (>1 second) saves the active preset's
+//!   current toggle states and encoder values as its defaults
+//! - A Gain encoder button click steps through the preset banks; the bank shown
+//!   on exit is switched to
 
+use crate::bank::Banks;
 use crate::events::{Edge, InputEvent, Pulse};
 use core::fmt::Write;
 use heapless::String;
@@ -16,11 +22,25 @@ use heapless::String;
 /// How long both encoder buttons must be held to enter/exit config mode (in ms).
 const ENTRY_HOLD_MS: u32 = 1000;
 
+/// How long the Vol encoder button alone must be held to take a snapshot (in ms).
+const SNAPSHOT_HOLD_MS: u32 = 1000;
+
 /// Display event generated by config mode for the display task.
 #[derive(Debug, Clone)]
 pub enum ConfigDisplayEvent {
     /// Show the idle/info screen (firmware version, preset count, etc.)
     Info(InfoScreen),
+    /// Show the storage health screen (flash usage, wear, last error).
+    Storage,
+    /// The active preset's current state was taken as its defaults.
+    Snapshot,
+    /// Show the bank selected for switching.
+    Bank {
+        bank: u8,
+        name: String<24>,
+        /// Already the active bank.
+        active: bool,
+    },
     /// Show button press feedback.
     ButtonPress {
         button: &'static str,
@@ -75,6 +95,20 @@ pub struct ConfigMode {
     gain_held: bool,
     /// Suppress entry/exit retriggering until both buttons are released.
     suppress_until_release: bool,
+    /// Vol button pressed without Gain: a click, not an entry/exit hold.
+    vol_click: bool,
+    /// When the Vol button was pressed.
+    vol_pressed_at: u32,
+    /// Snapshot requested with a Vol hold, until taken.
+    snapshot: bool,
+    /// Storage health screen shown instead of the info screen.
+    storage_screen: bool,
+    /// Gain button pressed without Vol.
+    gain_click: bool,
+    /// Bank picked with Gain clicks, switched to on exit.
+    bank: Option<u8>,
+    /// Bank to switch to, until taken.
+    bank_switch: Option<u8>,
 }
 
 impl Default for ConfigMode {
@@ -91,6 +125,13 @@ impl ConfigMode {
             vol_held: false,
             gain_held: false,
             suppress_until_release: false,
+            vol_click: false,
+            vol_pressed_at: 0,
+            snapshot: false,
+            storage_screen: false,
+            gain_click: false,
+            bank: None,
+            bank_switch: None,
         }
     }
 
@@ -99,6 +140,16 @@ impl ConfigMode {
         self.active
     }
 
+    /// Bank picked in config mode, once it has been exited.
+    pub fn take_bank_switch(&mut self) -> Option<u8> {
+        self.bank_switch.take()
+    }
+
+    /// Whether a snapshot of the active preset's state was requested.
+    pub fn take_snapshot(&mut self) -> bool {
+        core::mem::take(&mut self.snapshot)
+    }
+
     /// Returns true if both encoder buttons are held and we're waiting for the hold threshold.
     pub fn is_holding(&self) -> bool {
         self.both_held_since.is_some()
@@ -119,17 +170,37 @@ impl ConfigMode {
         let mut display_events: heapless::Vec<ConfigDisplayEvent, 4> = heapless::Vec::new();
 
         // Track encoder button state from events.
+        let mut clicked = false;
+        let mut vol_hold = false;
+        let mut gain_clicked = false;
         for event in events {
             match event {
-                InputEvent::VolButton(Edge::Activate) => self.vol_held = true,
+                InputEvent::VolButton(Edge::Activate) => {
+                    self.vol_held = true;
+                    self.vol_click = !self.gain_held;
+                    self.vol_pressed_at = now_ms;
+                    self.gain_click = false;
+                }
                 InputEvent::VolButton(Edge::Deactivate) => {
                     self.vol_held = false;
                     self.suppress_until_release = false;
+                    if core::mem::take(&mut self.vol_click) {
+                        if now_ms.wrapping_sub(self.vol_pressed_at) >= SNAPSHOT_HOLD_MS {
+                            vol_hold = true;
+                        } else {
+                            clicked = true;
+                        }
+                    }
+                }
+                InputEvent::GainButton(Edge::Activate) => {
+                    self.gain_held = true;
+                    self.gain_click = !self.vol_held;
+                    self.vol_click = false;
                 }
-                InputEvent::GainButton(Edge::Activate) => self.gain_held = true,
                 InputEvent::GainButton(Edge::Deactivate) => {
                     self.gain_held = false;
                     self.suppress_until_release = false;
+                    gain_clicked |= core::mem::take(&mut self.gain_click);
                 }
                 _ => {}
             }
@@ -148,21 +219,13 @@ impl ConfigMode {
                         self.suppress_until_release = true;
 
                         if self.active {
+                            self.storage_screen = false;
+                            self.bank = None;
                             display_events.push(ConfigDisplayEvent::Entered).ok();
-                            display_events
-                                .push(ConfigDisplayEvent::Info(InfoScreen {
-                                    firmware_version: context.firmware_version,
-                                    git_hash: context.git_hash,
-                                    preset_count: context.preset_count,
-                                    din_enabled: context.din_enabled,
-                                    midi_clock: context.midi_clock,
-                                    bpm: context.bpm,
-                                    din_to_usb_thru: context.din_to_usb_thru,
-                                    usb_to_din_thru: context.usb_to_din_thru,
-                                    usb_to_usb_thru: context.usb_to_usb_thru,
-                                }))
-                                .ok();
+                            display_events.push(info_screen(context)).ok();
                         } else {
+                            self.bank_switch =
+                                self.bank.take().filter(|&b| b != context.banks.active);
                             display_events.push(ConfigDisplayEvent::Exited).ok();
                         }
                         return display_events;
@@ -178,6 +241,36 @@ impl ConfigMode {
             return display_events;
         }
 
+        if clicked {
+            self.storage_screen = !self.storage_screen;
+            let screen = if self.storage_screen {
+                ConfigDisplayEvent::Storage
+            } else {
+                info_screen(context)
+            };
+            display_events.push(screen).ok();
+        }
+
+        if vol_hold {
+            self.snapshot = true;
+            display_events.push(ConfigDisplayEvent::Snapshot).ok();
+        }
+
+        if gain_clicked {
+            let banks = context.banks;
+            let bank = banks.next(self.bank.unwrap_or(banks.active));
+            self.bank = Some(bank);
+            let mut name: String<24> = String::new();
+            write!(name, "{}", banks.name(bank)).ok();
+            display_events
+                .push(ConfigDisplayEvent::Bank {
+                    bank,
+                    name,
+                    active: bank == banks.active,
+                })
+                .ok();
+        }
+
         // Process events for diagnostic display.
         for event in events {
             match event {
@@ -353,6 +446,8 @@ pub struct ConfigContext<'a> {
     pub encoder_configs: [EncoderInfo; 2],
     /// Analog/expression config summaries (Exp1, Exp2).
     pub analog_configs: [AnalogInfo; 2],
+    /// Bank names and the active bank.
+    pub banks: &'a Banks,
 }
 
 /// Summary of an encoder's MIDI output for config mode display.
@@ -376,6 +471,21 @@ pub struct ButtonAction {
     pub summary: String<24>,
 }
 
+/// Idle screen event for `context`.
+fn info_screen(context: &ConfigContext) -> ConfigDisplayEvent {
+    ConfigDisplayEvent::Info(InfoScreen {
+        firmware_version: context.firmware_version,
+        git_hash: context.git_hash,
+        preset_count: context.preset_count,
+        din_enabled: context.din_enabled,
+        midi_clock: context.midi_clock,
+        bpm: context.bpm,
+        din_to_usb_thru: context.din_to_usb_thru,
+        usb_to_din_thru: context.usb_to_din_thru,
+        usb_to_usb_thru: context.usb_to_usb_thru,
+    })
+}
+
 /// Generate the detail string for a button press in config mode.
 fn button_detail(context: &ConfigContext, index: usize) -> String<40> {
     let mut s: String<40> = String::new();
@@ -609,6 +719,7 @@ mod tests {
     }
 
     fn test_context() -> ConfigContext<'static> {
+        static BANKS: Banks = Banks::new();
         static ACTIONS: [ButtonAction; 6] = [
             ButtonAction {
                 summary: String::new(),
@@ -642,6 +753,7 @@ mod tests {
             button_actions: &ACTIONS,
             encoder_configs: [EncoderInfo::default(), EncoderInfo::default()],
             analog_configs: [AnalogInfo::default(), AnalogInfo::default()],
+            banks: &BANKS,
         }
     }
 }
@@ -0,0 +1,256 @@
+//! AT24CS01 EEPROM driver for the runtime state image.
+//!
+//! The 128-byte EEPROM is split into two 64-byte slots that are written
+//! alternately. Each slot holds a header (magic, format version, sequence
+//! number), the state image packed to fit, and a CRC-16. At boot the newest
+//! slot with a valid CRC wins, so a write torn by power loss falls back to the
+//! state saved before it.
+//!
+//! Saves only write the pages that differ from what the target slot already
+//! holds, and are skipped when the state matches the newest slot.
+//!
+//! The state image is `PresetStateStore::to_eeprom`'s 128-byte layout:
+//! `[magic][active][state0..stateN]`. Each slot also records the preset bank
+//! the state belongs to (bank 0 in slots older firmware wrote).
+
+use core::future::Future;
+
+use embedded_hal::i2c::I2c;
+use midi_controller::config::{MAX_BUTTONS, MAX_CYCLE_VALUES, MAX_ENCODERS};
+use midi_controller::state::{DefaultPresetState, DefaultPresetStateStore, EEPROM_MAX_PRESETS};
+
+/// I²C address of the EEPROM data array.
+pub const EEPROM_ADDR: u8 = 0x50;
+/// EEPROM size, also the size of the state image.
+pub const EEPROM_SIZE: usize = 128;
+/// Page write size.
+pub const PAGE_SIZE: usize = 8;
+/// Self-timed write cycle after each page write (t_WR).
+pub const WRITE_CYCLE_MS: u32 = 5;
+/// Quiet time before a state change is written, so bursts of changes
+/// (encoder turns, quick toggles) cost one save.
+pub const SAVE_DEBOUNCE_MS: u32 = 250;
+
+/// Packed slot layout version. Bump when the slot layout changes.
+pub const FORMAT_VERSION: u8 = 1;
+
+const SLOT_SIZE: usize = EEPROM_SIZE / 2;
+const SLOT_MAGIC: u8 = 0x5A;
+const HEADER_SIZE: usize = 3; // magic, version, sequence
+const CRC_SIZE: usize = 2;
+const PAYLOAD_SIZE: usize = SLOT_SIZE - HEADER_SIZE - CRC_SIZE;
+const PAYLOAD: core::ops::Range<usize> = HEADER_SIZE..HEADER_SIZE + PAYLOAD_SIZE;
+
+/// State image header: magic + active preset.
+const IMAGE_HEADER_SIZE: usize = 2;
+/// Packed preset state: button bits, one nibble per cycle index, encoder values.
+const PACKED_STATE_SIZE: usize = 1 + MAX_BUTTONS.div_ceil(2) + MAX_ENCODERS;
+
+/// Packed state image: active preset + every preset state.
+const PACKED_SIZE: usize = 1 + EEPROM_MAX_PRESETS * PACKED_STATE_SIZE;
+
+/// Payload offset of the bank tag, in the spare bytes after the packed image.
+const BANK_OFFSET: usize = PACKED_SIZE;
+
+const _: () = assert!(MAX_BUTTONS <= 8 && MAX_CYCLE_VALUES <= 16);
+const _: () = assert!(BANK_OFFSET < PAYLOAD_SIZE);
+
+/// Runtime state storage on the AT24CS01.
+pub struct Eeprom<I> {
+    i2c: I,
+    /// Sequence number of the newest slot.
+    seq: u8,
+    /// Slot the next save goes to.
+    next_slot: usize,
+    /// What each slot holds on the chip; None when unknown (before `load`,
+    /// after a failed write).
+    slots: [Option<[u8; SLOT_SIZE]>; 2],
+    /// Pages written since boot.
+    writes: u32,
+    /// A write failed since `take_error` was last called.
+    failed: bool,
+    /// Bank of the newest slot; saves tag their slot with it.
+    bank: u8,
+}
+
+impl<I: I2c> Eeprom<I> {
+    pub fn new(i2c: I) -> Self {
+        Self {
+            i2c,
+            seq: 0,
+            next_slot: 0,
+            slots: [None; 2],
+            writes: 0,
+            failed: false,
+            bank: 0,
+        }
+    }
+
+    /// Preset bank of the state image last loaded or saved.
+    pub fn bank(&self) -> u8 {
+        self.bank
+    }
+
+    /// Tag the following saves with `bank`.
+    pub fn set_bank(&mut self, bank: u8) {
+        self.bank = bank;
+    }
+
+    /// Pages written since boot.
+    pub fn writes(&self) -> u32 {
+        self.writes
+    }
+
+    /// Whether a write failed since the last call.
+    pub fn take_error(&mut self) -> bool {
+        core::mem::take(&mut self.failed)
+    }
+
+    /// Read the newest valid state image. Falls back to the unslotted image
+    /// older firmware wrote over the whole EEPROM.
+    pub fn load(&mut self) -> Result<Option<[u8; EEPROM_SIZE]>, I::Error> {
+        let mut raw = [0u8; EEPROM_SIZE];
+        self.i2c.write_read(EEPROM_ADDR, &[0x00], &mut raw)?;
+
+        let (first, second) = raw.split_at(SLOT_SIZE);
+        self.slots = [first.try_into().ok(), second.try_into().ok()];
+        let newest = match (valid_slot(first), valid_slot(second)) {
+            (Some(a), Some(b)) if newer(b, a) => Some((1, b)),
+            (Some(a), _) => Some((0, a)),
+            (None, Some(b)) => Some((1, b)),
+            (None, None) => None,
+        };
+        match newest {
+            Some((slot, seq)) => {
+                self.seq = seq;
+                self.next_slot = slot ^ 1;
+                let payload = &raw[slot * SLOT_SIZE..][PAYLOAD];
+                self.bank = payload[BANK_OFFSET];
+                Ok(Some(unpack(payload)))
+            }
+            None => {
+                self.bank = 0;
+                Ok(DefaultPresetStateStore::from_eeprom(&raw).map(|_| raw))
+            }
+        }
+    }
+
+    /// Write `image` to the older slot, waiting on `write_cycle` after each
+    /// page written. On error the slot is left torn and the other one stays
+    /// newest.
+    pub async fn save<W: Future<Output = ()>>(
+        &mut self,
+        image: &[u8; EEPROM_SIZE],
+        mut write_cycle: impl FnMut() -> W,
+    ) -> Result<(), I::Error> {
+        let seq = self.seq.wrapping_add(1);
+        let mut slot = [0u8; SLOT_SIZE];
+        slot[0] = SLOT_MAGIC;
+        slot[1] = FORMAT_VERSION;
+        slot[2] = seq;
+        pack(image, &mut slot[PAYLOAD]);
+        slot[PAYLOAD][BANK_OFFSET] = self.bank;
+        let crc = crc16(&slot[..SLOT_SIZE - CRC_SIZE]);
+        slot[SLOT_SIZE - CRC_SIZE..].copy_from_slice(&crc.to_le_bytes());
+
+        // Nothing changed since the last save
+        let newest = self.slots[self.next_slot ^ 1];
+        if newest.is_some_and(|old| valid_slot(&old).is_some() && old[PAYLOAD] == slot[PAYLOAD]) {
+            return Ok(());
+        }
+
+        let base = self.next_slot * SLOT_SIZE;
+        let old = self.slots[self.next_slot].take();
+        for (page, data) in slot.chunks(PAGE_SIZE).enumerate() {
+            let range = page * PAGE_SIZE..(page + 1) * PAGE_SIZE;
+            if old.is_some_and(|old| old[range] == *data) {
+                continue;
+            }
+            let mut buf = [0u8; PAGE_SIZE + 1];
+            buf[0] = (base + page * PAGE_SIZE) as u8;
+            buf[1..].copy_from_slice(data);
+            if let Err(err) = self.i2c.write(EEPROM_ADDR, &buf) {
+                self.failed = true;
+                return Err(err);
+            }
+            self.writes += 1;
+            write_cycle().await;
+        }
+        self.slots[self.next_slot] = Some(slot);
+        self.seq = seq;
+        self.next_slot ^= 1;
+        Ok(())
+    }
+}
+
+/// Sequence number of `slot` if its header and CRC are valid.
+fn valid_slot(slot: &[u8]) -> Option<u8> {
+    let (body, crc) = slot.split_at(SLOT_SIZE - CRC_SIZE);
+    let valid = body[0] == SLOT_MAGIC
+        && body[1] == FORMAT_VERSION
+        && u16::from_le_bytes([crc[0], crc[1]]) == crc16(body);
+    valid.then_some(body[2])
+}
+
+/// Whether sequence number `a` was written after `b` (wrapping).
+fn newer(a: u8, b: u8) -> bool {
+    (a.wrapping_sub(b) as i8) > 0
+}
+
+fn pack(image: &[u8; EEPROM_SIZE], out: &mut [u8]) {
+    out[0] = image[1];
+    for i in 0..EEPROM_MAX_PRESETS {
+        let offset = IMAGE_HEADER_SIZE + i * DefaultPresetState::SIZE;
+        let state = DefaultPresetState::from_bytes(&image[offset..]);
+        let packed = &mut out[1 + i * PACKED_STATE_SIZE..][..PACKED_STATE_SIZE];
+        packed.fill(0);
+        for (b, &active) in state.button_active.iter().enumerate() {
+            packed[0] |= (active as u8) << b;
+        }
+        for (b, &index) in state.cycle_index.iter().enumerate() {
+            packed[1 + b / 2] |= (index & 0x0F) << (4 * (b % 2));
+        }
+        packed[PACKED_STATE_SIZE - MAX_ENCODERS..].copy_from_slice(&state.encoder_values);
+    }
+}
+
+fn unpack(payload: &[u8]) -> [u8; EEPROM_SIZE] {
+    let mut image = DefaultPresetStateStore::cleared_eeprom();
+    image[1] = payload[0];
+    for i in 0..EEPROM_MAX_PRESETS {
+        let packed = &payload[1 + i * PACKED_STATE_SIZE..][..PACKED_STATE_SIZE];
+        let mut state = DefaultPresetState::default();
+        for (b, active) in state.button_active.iter_mut().enumerate() {
+            *active = packed[0] & (1 << b) != 0;
+        }
+        for (b, index) in state.cycle_index.iter_mut().enumerate() {
+            *index = (packed[1 + b / 2] >> (4 * (b % 2))) & 0x0F;
+        }
+        state
+            .encoder_values
+            .copy_from_slice(&packed[PACKED_STATE_SIZE - MAX_ENCODERS..]);
+        let offset = IMAGE_HEADER_SIZE + i * DefaultPresetState::SIZE;
+        state.to_bytes(&mut image[offset..]);
+    }
+    image
+}
+
+/// CRC-16/CCITT-FALSE.
+fn crc16(data: &[u8]) -> u16 {
+    crc16_update(0xFFFF, data)
+}
+
+/// CRC-16/CCITT-FALSE, continued from `crc` (0xFFFF to start).
+pub(crate) fn crc16_update(mut crc: u16, data: &[u8]) -> u16 {
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
@@ -0,0 +1,86 @@
+//! RP2040 flash backend for [`ConfigStore`].
+//!
+//! Uses the last 64KB of flash (16 pages of 4KB each). Flash operations use
+//! `rp2040-flash` which executes from RAM to avoid XIP conflicts.
+
+use embedded_storage::nor_flash::{NorFlashError, NorFlashErrorKind};
+use embedded_storage_async::nor_flash::{ErrorType, MultiwriteNorFlash, NorFlash, ReadNorFlash};
+
+use crate::storage::ConfigStore;
+
+const STORAGE_ORIGIN: u32 = 0x001F_0000; // offset from flash start (2MB - 64KB)
+const STORAGE_SIZE: usize = 64 * 1024; // 16 sectors, full region (wear-leveled)
+const SECTOR_SIZE: usize = 4096;
+const PAGE_SIZE: usize = 256;
+
+#[derive(Debug)]
+pub struct FlashError;
+
+impl NorFlashError for FlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        NorFlashErrorKind::Other
+    }
+}
+
+pub struct FlashStorage;
+
+impl ErrorType for FlashStorage {
+    type Error = FlashError;
+}
+
+impl ReadNorFlash for FlashStorage {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let addr = (0x1000_0000 + STORAGE_ORIGIN + offset) as *const u8;
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = unsafe { core::ptr::read_volatile(addr.add(i)) };
+        }
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        STORAGE_SIZE
+    }
+}
+
+impl NorFlash for FlashStorage {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = SECTOR_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let flash_addr = STORAGE_ORIGIN + from;
+        let len = to - from;
+        cortex_m::interrupt::free(|_| unsafe {
+            rp2040_flash::flash::flash_range_erase(flash_addr, len, true);
+        });
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let mut flash_addr = STORAGE_ORIGIN + offset;
+        let mut remaining = bytes;
+        while !remaining.is_empty() {
+            let aligned_addr = flash_addr & !0xFF;
+            let start_offset = (flash_addr - aligned_addr) as usize;
+            let copy_len = remaining.len().min(PAGE_SIZE - start_offset);
+            let mut page_buf = [0xFFu8; PAGE_SIZE];
+            page_buf[start_offset..start_offset + copy_len].copy_from_slice(&remaining[..copy_len]);
+            cortex_m::interrupt::free(|_| unsafe {
+                rp2040_flash::flash::flash_range_program(aligned_addr, &page_buf, true);
+            });
+            remaining = &remaining[copy_len..];
+            flash_addr += copy_len as u32;
+        }
+        Ok(())
+    }
+}
+
+impl MultiwriteNorFlash for FlashStorage {}
+
+impl ConfigStore<FlashStorage> {
+    /// Store on the reserved region of the on-board flash.
+    pub fn try_new() -> Option<Self> {
+        Self::new(FlashStorage, 0..STORAGE_SIZE as u32)
+    }
+}
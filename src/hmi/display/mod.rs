@@ -228,6 +228,51 @@ impl<I2CL: I2c, I2CR: I2c> Displays<I2CL, I2CR> {
         }
     }
 
+    /// Show a previewed (not yet selected) preset on the left display.
+    pub fn draw_preview_overlay_left(&mut self, number: u8, name: &str) {
+        use pedalboard_midi::views::preset_overlay;
+        if let Some(display) = &mut self.display_l.driver {
+            display.clear(Gray4::BLACK).ok();
+            preset_overlay::draw_with_arrow(display, number, name, "Preview").ok();
+            display.flush().ok();
+        }
+    }
+
+    /// Show the looper state on the left display.
+    pub fn draw_looper_overlay_left(&mut self, state: &str) {
+        use pedalboard_midi::views::preset_overlay;
+        if let Some(display) = &mut self.display_l.driver {
+            display.clear(Gray4::BLACK).ok();
+            preset_overlay::draw_captioned(display, "Looper", state).ok();
+            display.flush().ok();
+        }
+    }
+
+    /// Show the current song on the left display and the next one on the right.
+    pub fn draw_song_overlay(
+        &mut self,
+        position: u8,
+        name: &str,
+        up_next: Option<&str>,
+        forward: bool,
+    ) {
+        use pedalboard_midi::views::preset_overlay;
+        if let Some(display) = &mut self.display_l.driver {
+            display.clear(Gray4::BLACK).ok();
+            let arrow = if forward { ">>" } else { "<<" };
+            preset_overlay::draw_with_arrow(display, position, name, arrow).ok();
+            display.flush().ok();
+        }
+        if let Some(display) = &mut self.display_r.driver {
+            display.clear(Gray4::BLACK).ok();
+            match up_next {
+                Some(next) => preset_overlay::draw_up_next(display, next).ok(),
+                None => preset_overlay::draw_name(display, "End of set").ok(),
+            };
+            display.flush().ok();
+        }
+    }
+
     pub fn draw_long_press_hint(&mut self, label: &str) {
         use pedalboard_midi::views::preset_overlay;
         // Show hint on both displays
@@ -320,6 +365,43 @@ impl<I2CL: I2c, I2CR: I2c> Displays<I2CL, I2CR> {
         }
     }
 
+    pub fn draw_config_storage(&mut self, health: &pedalboard_midi::storage::StorageHealth) {
+        use pedalboard_midi::views::config_mode;
+        if let Some(display) = &mut self.display_l.driver {
+            display.clear(Gray4::BLACK).ok();
+            config_mode::draw_storage_left(display, health).ok();
+            display.flush().ok();
+        }
+        if let Some(display) = &mut self.display_r.driver {
+            display.clear(Gray4::BLACK).ok();
+            config_mode::draw_storage_right(display, health).ok();
+            display.flush().ok();
+        }
+    }
+
+    pub fn draw_config_bank(&mut self, bank: u8, name: &str, active: bool) {
+        use pedalboard_midi::views::config_mode;
+        if let Some(display) = &mut self.display_l.driver {
+            display.clear(Gray4::BLACK).ok();
+            config_mode::draw_bank_left(display, bank, name).ok();
+            display.flush().ok();
+        }
+        if let Some(display) = &mut self.display_r.driver {
+            display.clear(Gray4::BLACK).ok();
+            config_mode::draw_bank_right(display, active).ok();
+            display.flush().ok();
+        }
+    }
+
+    pub fn draw_config_snapshot(&mut self) {
+        use pedalboard_midi::views::config_mode;
+        if let Some(display) = &mut self.display_l.driver {
+            display.clear(Gray4::BLACK).ok();
+            config_mode::draw_snapshot(display).ok();
+            display.flush().ok();
+        }
+    }
+
     pub fn draw_config_button_press(&mut self, button: &str, detail: &str) {
         use pedalboard_midi::views::config_mode;
         if let Some(display) = &mut self.display_l.driver {
@@ -73,6 +73,8 @@ pub enum LedEvent {
     SetSingle(Led, Option<RGB8>),
     /// Flash a single LED for `duration_ticks` frames then auto-clear.
     Flash(Led, RGB8, u8),
+    /// Blink a single LED until the next `SetSingle` (e.g. bank preview).
+    Blink(Led, RGB8),
     /// MIDI clock tick (24ppqn). When received, animations sync to BPM.
     BpmTick,
     /// Reactive LED: set heatmap on button ring (index 0-5, fill 0-12).
@@ -85,6 +87,7 @@ pub struct Leds {
     singles: [Option<RGB8>; NUM_LEDS],
     ledrings: [LedRing; NUM_LED_RINGS],
     flash_ticks: [u8; NUM_LEDS],
+    blinking: [bool; NUM_LEDS],
     /// Rings controlled by reactive LED (SetReactiveRing) — skipped by SetAllRings.
     reactive: [bool; NUM_LED_RINGS],
     pub buffer: LedData,
@@ -100,6 +103,7 @@ impl Leds {
             ledrings: [LedRing::with_rotation(&midi_controller::led::PEDALBOARD_CLOCK_MAP, 8);
                 NUM_LED_RINGS],
             flash_ticks: [0; NUM_LEDS],
+            blinking: [false; NUM_LEDS],
             reactive: [false; NUM_LED_RINGS],
             buffer: [RGB8 { r: 0, g: 0, b: 0 }; LED_OUTPUTS],
             tick: 0,
@@ -135,10 +139,17 @@ impl Leds {
             LedEvent::SetSingle(led, color) => {
                 self.singles[led as usize] = color;
                 self.flash_ticks[led as usize] = 0;
+                self.blinking[led as usize] = false;
             }
             LedEvent::Flash(led, color, duration) => {
                 self.singles[led as usize] = Some(color);
                 self.flash_ticks[led as usize] = duration;
+                self.blinking[led as usize] = false;
+            }
+            LedEvent::Blink(led, color) => {
+                self.singles[led as usize] = Some(color);
+                self.flash_ticks[led as usize] = 0;
+                self.blinking[led as usize] = true;
             }
             LedEvent::BpmTick => {
                 self.bpm_active = true;
@@ -210,9 +221,15 @@ impl Leds {
             }
         }
 
+        // Blinking singles follow the free-running tick (same rate as ring blink)
+        let blink_off = (self.tick / 12) % 2 == 1;
         for (i, color) in self.singles.iter().enumerate() {
             let led = NUM_LED_RINGS * LEDS_PER_RING + i;
-            self.buffer[led] = color.unwrap_or_default();
+            self.buffer[led] = if self.blinking[i] && blink_off {
+                RGB8::default()
+            } else {
+                color.unwrap_or_default()
+            };
         }
 
         &self.buffer
@@ -286,6 +303,20 @@ mod tests {
         assert_eq!(leds.render()[mon_index], RGB8::default());
     }
 
+    #[test]
+    fn test_blink_single_until_set() {
+        let mut leds = Leds::new();
+        leds.handle_event(LedEvent::Blink(Led::Mode, GREEN));
+        let mode_index = NUM_LED_RINGS * LEDS_PER_RING + Led::Mode as usize;
+        assert_eq!(leds.render()[mode_index], GREEN);
+        for _ in 0..12 {
+            leds.tick();
+        }
+        assert_eq!(leds.render()[mode_index], RGB8::default());
+        leds.handle_event(LedEvent::SetSingle(Led::Mode, Some(BLUE)));
+        assert_eq!(leds.render()[mode_index], BLUE);
+    }
+
     #[test]
     fn test_tick_advances_blink() {
         let mut leds = Leds::new();
@@ -0,0 +1,253 @@
+//! Tempo-synced LFO: a continuous CC waveform locked to the MIDI clock.
+//!
+//! The generator advances one step per 24 ppqn clock tick of the clock in
+//! charge (incoming clock while it arrives, the internal clock otherwise), so
+//! its rate follows BPM changes without drifting against the clock. Only value
+//! and ring changes are sent to keep the MIDI and LED streams light.
+
+use serde::{Deserialize, Serialize};
+
+/// Clock ticks per quarter note.
+pub const PPQN: u16 = 24;
+
+/// One sine cycle, 0-127, starting at the midpoint and rising.
+const SINE: [u8; 64] = [
+    64, 70, 76, 82, 88, 93, 99, 104, 108, 113, 116, 120, 122, 124, 126, 127, 127, 127, 126, 124,
+    122, 120, 116, 113, 108, 104, 99, 93, 88, 82, 76, 70, 64, 57, 51, 45, 39, 34, 28, 23, 19, 14,
+    11, 7, 5, 3, 1, 0, 0, 0, 1, 3, 5, 7, 11, 14, 19, 23, 28, 34, 39, 45, 51, 57,
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Waveform {
+    #[default]
+    Sine,
+    /// Starts at the bottom, peaks mid-cycle.
+    Triangle,
+    /// High for the first half of the cycle.
+    Square,
+    /// Random value held for one cycle.
+    SampleHold,
+}
+
+/// Length of one LFO cycle as a note division.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Division {
+    FourBars,
+    TwoBars,
+    Whole,
+    Half,
+    DottedQuarter,
+    #[default]
+    Quarter,
+    QuarterTriplet,
+    Eighth,
+    EighthTriplet,
+    Sixteenth,
+}
+
+impl Division {
+    /// Cycle length in clock ticks (bars are 4/4).
+    pub fn ticks(&self) -> u16 {
+        match self {
+            Division::FourBars => PPQN * 16,
+            Division::TwoBars => PPQN * 8,
+            Division::Whole => PPQN * 4,
+            Division::Half => PPQN * 2,
+            Division::DottedQuarter => PPQN * 3 / 2,
+            Division::Quarter => PPQN,
+            Division::QuarterTriplet => PPQN * 2 / 3,
+            Division::Eighth => PPQN / 2,
+            Division::EighthTriplet => PPQN / 3,
+            Division::Sixteenth => PPQN / 4,
+        }
+    }
+}
+
+/// LFO setup of a preset (see `PresetExt::lfo`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LfoConfig {
+    /// MIDI channel 1-16.
+    pub channel: u8,
+    pub cc: u8,
+    pub waveform: Waveform,
+    pub division: Division,
+    /// Output range; `min > max` inverts the waveform.
+    pub min: u8,
+    pub max: u8,
+}
+
+/// What an LFO button does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LfoControl {
+    Toggle,
+    Start,
+    Stop,
+}
+
+/// Waveform value (0-127) at `phase` ticks into a cycle of `period` ticks.
+/// `held` is the sample & hold value of the current cycle.
+pub fn shape(waveform: Waveform, phase: u16, period: u16, held: u8) -> u8 {
+    let period = period.max(1) as u32;
+    let phase = phase as u32 % period;
+    match waveform {
+        Waveform::Sine => {
+            // 8.8 fixed-point table position, linearly interpolated
+            let pos = phase * 64 * 256 / period;
+            let i = (pos >> 8) as usize;
+            let a = SINE[i] as i32;
+            let b = SINE[(i + 1) % SINE.len()] as i32;
+            (a + (b - a) * (pos & 0xFF) as i32 / 256) as u8
+        }
+        Waveform::Triangle => {
+            let x = phase * 254 / period;
+            if x <= 127 {
+                x as u8
+            } else {
+                (254 - x) as u8
+            }
+        }
+        Waveform::Square => {
+            if phase * 2 < period {
+                127
+            } else {
+                0
+            }
+        }
+        Waveform::SampleHold => held & 0x7F,
+    }
+}
+
+/// LFO generator. Configured from the active preset, started and stopped by
+/// buttons, and ticked by the clock in charge (internal or incoming).
+#[derive(Debug, Clone)]
+pub struct Lfo {
+    config: Option<LfoConfig>,
+    running: bool,
+    /// Clock ticks into the current cycle.
+    phase: u16,
+    /// Last CC value sent (None = send the next value unconditionally).
+    last: Option<u8>,
+    /// Waveform value (0-127) of the last tick, for the ring.
+    level: u8,
+    /// Sample & hold value of the current cycle.
+    held: u8,
+    /// xorshift32 state for sample & hold.
+    seed: u32,
+    /// Button whose ring shows the LFO.
+    button: Option<u8>,
+    /// Ring fill last shown (None = show the next one).
+    shown: Option<u8>,
+}
+
+impl Default for Lfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Lfo {
+    pub const fn new() -> Self {
+        Self {
+            config: None,
+            running: false,
+            phase: 0,
+            last: None,
+            level: 0,
+            held: 0,
+            seed: 0x2545_F491,
+            button: None,
+            shown: None,
+        }
+    }
+
+    /// Set the LFO of the active preset. A different setup stops the LFO.
+    pub fn configure(&mut self, config: Option<LfoConfig>) {
+        if self.config != config {
+            self.config = config;
+            self.stop();
+        }
+    }
+
+    /// Apply a button press; `button` is the button whose ring shows the LFO.
+    /// Does nothing without a configured LFO.
+    pub fn control(&mut self, control: LfoControl, button: u8) {
+        let start = match control {
+            LfoControl::Toggle => !self.running,
+            LfoControl::Start => true,
+            LfoControl::Stop => false,
+        };
+        if !start {
+            self.stop();
+        } else if self.config.is_some() {
+            // (Re)start at the beginning of a cycle
+            self.running = true;
+            self.phase = 0;
+            self.last = None;
+            self.button = Some(button);
+            self.shown = None;
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.button = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Button whose ring shows the running LFO.
+    pub fn button(&self) -> Option<u8> {
+        self.button
+    }
+
+    /// Ring segments (0-12) for the current waveform value.
+    pub fn fill(&self) -> u8 {
+        (self.level as u16 * 12 / 127) as u8
+    }
+
+    /// Button and fill to show on its ring, when the fill changed since the
+    /// last call.
+    pub fn ring_update(&mut self) -> Option<(u8, u8)> {
+        let button = self.button?;
+        let fill = self.fill();
+        if self.shown == Some(fill) {
+            return None;
+        }
+        self.shown = Some(fill);
+        Some((button, fill))
+    }
+
+    /// Advance one clock tick. Returns the CC message when the value changed.
+    pub fn tick(&mut self) -> Option<[u8; 3]> {
+        if !self.running {
+            return None;
+        }
+        let cfg = self.config?;
+        let period = cfg.division.ticks();
+        if self.phase == 0 && cfg.waveform == Waveform::SampleHold {
+            self.held = self.next_random();
+        }
+        self.level = shape(cfg.waveform, self.phase, period, self.held);
+        self.phase = (self.phase + 1) % period.max(1);
+
+        let (lo, hi) = (cfg.min.min(127) as i32, cfg.max.min(127) as i32);
+        let value = (lo + (hi - lo) * self.level as i32 / 127) as u8;
+        if self.last == Some(value) {
+            return None;
+        }
+        self.last = Some(value);
+        let ch = cfg.channel.saturating_sub(1) & 0x0F;
+        Some([0xB0 | ch, cfg.cc & 0x7F, value])
+    }
+
+    fn next_random(&mut self) -> u8 {
+        let mut x = self.seed;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.seed = x;
+        (x >> 25) as u8
+    }
+}
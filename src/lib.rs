@@ -26,10 +26,19 @@ pub const MAX_PRESET_SIZE: usize = 256;
 /// Bump when the postcard-serialized layout of `Preset` or `GlobalConfig` changes
 /// (reordering, removing, or changing field types). Adding a new `#[serde(default)]`
 /// field at the end does NOT require a bump (postcard tolerates trailing data).
+/// Freeze the previous layout in `migrate` so stored blobs are upgraded at boot.
 ///
 /// See: `dotgithub/docs/adr-versioning.md`
 pub const FLASH_FORMAT_VERSION: u8 = midi_controller::config::PRESET_SCHEMA_VERSION;
 
+/// Format version byte prepended to every firmware-local blob (settings,
+/// preset extensions, profiles, songs, banks).
+///
+/// Independent of the protocol schema. Bump when the layout of one of these
+/// types changes in a way `#[serde(default)]` trailing fields do not cover, and
+/// freeze the previous layout in `migrate`.
+pub const LOCAL_FORMAT_VERSION: u8 = 1;
+
 /// Maximum PE GET reply message size (capped by protocol Vec<u8, 350>).
 pub const MAX_PE_REPLY_SIZE: usize = 350;
 
@@ -37,15 +46,30 @@ pub const MAX_PE_REPLY_SIZE: usize = 350;
 pub const MIN_USB_OUT_CAPACITY: usize = MAX_PE_REPLY_SIZE / 3 + 1;
 
 pub mod action;
+pub mod backup;
+pub mod bank;
+pub mod clock_out;
 pub mod config_mode;
 pub mod display;
+pub mod eeprom;
 pub mod events;
+#[cfg(target_arch = "arm")]
+pub mod flash;
 pub mod ledring;
 pub mod leds;
+pub mod lfo;
+pub mod looper;
+pub mod migrate;
 pub mod pe_handler;
 pub mod pe_sysex;
 pub mod persist;
-#[cfg(target_arch = "arm")]
+pub mod preset_ext;
+pub mod profile;
+pub mod resource;
+pub mod setlist;
+pub mod settings;
+pub mod slots;
 pub mod storage;
 pub mod system_status;
+pub mod transport;
 pub mod views;
@@ -0,0 +1,231 @@
+//! Looper control: a state machine for an external looper (empty → record →
+//! play → overdub → stop, undo/redo) that emits the configured MIDI per transition.
+//!
+//! Plain toggles drift out of sync with the looper because they only know
+//! on/off; here the firmware tracks the looper's actual state. With bar sync,
+//! transitions that end a recording or enter/leave overdub wait for the next bar
+//! boundary, counted in 24 ppqn clock ticks from the start of the recording.
+//! Without a running clock they fire immediately.
+
+use serde::{Deserialize, Serialize};
+
+/// MIDI clock ticks per beat.
+const TICKS_PER_BEAT: u32 = 24;
+
+/// No clock tick for this long means the clock stopped (below 10 BPM).
+const CLOCK_TIMEOUT_MS: u32 = 250;
+
+/// State of the external looper as tracked by the firmware.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LooperState {
+    #[default]
+    Empty,
+    Recording,
+    Playing,
+    Overdubbing,
+    Stopped,
+}
+
+impl LooperState {
+    /// Short label for the display.
+    pub fn label(&self) -> &'static str {
+        match self {
+            LooperState::Empty => "Empty",
+            LooperState::Recording => "Record",
+            LooperState::Playing => "Play",
+            LooperState::Overdubbing => "Overdub",
+            LooperState::Stopped => "Stop",
+        }
+    }
+
+    /// Audio is running (recording, playing or overdubbing).
+    pub fn is_running(&self) -> bool {
+        matches!(
+            self,
+            LooperState::Recording | LooperState::Playing | LooperState::Overdubbing
+        )
+    }
+}
+
+/// What a looper button does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LooperRole {
+    /// Record → play → overdub → play; starts playback when stopped.
+    Main,
+    Stop,
+    /// Undo the last overdub, or redo it if it was just undone.
+    UndoRedo,
+    Clear,
+}
+
+/// Raw 3-byte MIDI message sent for each transition (None = send nothing).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LooperMessages {
+    pub record: Option<[u8; 3]>,
+    pub play: Option<[u8; 3]>,
+    pub overdub: Option<[u8; 3]>,
+    pub stop: Option<[u8; 3]>,
+    pub undo: Option<[u8; 3]>,
+    pub redo: Option<[u8; 3]>,
+    pub clear: Option<[u8; 3]>,
+}
+
+/// Looper setup of a preset (see `PresetExt::looper`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LooperConfig {
+    pub messages: LooperMessages,
+    /// Ending a recording goes straight to overdub instead of playback.
+    pub record_to_overdub: bool,
+    /// Beats per bar for bar sync (0 = transitions fire immediately).
+    pub sync_beats: u8,
+}
+
+/// A state change and the message to send for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub from: LooperState,
+    pub to: LooperState,
+    pub message: Option<[u8; 3]>,
+}
+
+/// Looper state machine.
+#[derive(Debug, Clone, Default)]
+pub struct Looper {
+    state: LooperState,
+    can_undo: bool,
+    can_redo: bool,
+    /// Clock ticks since the start of the recording: position on the bar grid.
+    ticks: u32,
+    /// Time of the last clock tick, while the clock runs.
+    last_tick_ms: Option<u32>,
+    /// Role of a transition waiting for the next bar.
+    pending: Option<LooperRole>,
+}
+
+impl Looper {
+    pub const fn new() -> Self {
+        Self {
+            state: LooperState::Empty,
+            can_undo: false,
+            can_redo: false,
+            ticks: 0,
+            last_tick_ms: None,
+            pending: None,
+        }
+    }
+
+    pub fn state(&self) -> LooperState {
+        self.state
+    }
+
+    /// A transition is waiting for the next bar.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.can_undo
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.can_redo
+    }
+
+    /// Handle a button press. Returns the transition if it happened now; with bar
+    /// sync and a running clock, a main-button transition is deferred to
+    /// `clock_tick` instead.
+    pub fn press(
+        &mut self,
+        config: &LooperConfig,
+        role: LooperRole,
+        now_ms: u32,
+    ) -> Option<Transition> {
+        let quantized = config.sync_beats > 0
+            && role == LooperRole::Main
+            && self.state.is_running()
+            && self.clock_running(now_ms);
+        if !quantized {
+            self.pending = None;
+            return self.apply(config, role);
+        }
+        if self.pending.take().is_some() {
+            // Second press before the bar: cancel the queued transition
+            return None;
+        }
+        self.pending = Some(role);
+        None
+    }
+
+    /// A 24 ppqn clock tick. Fires a deferred transition on a bar boundary.
+    pub fn clock_tick(&mut self, config: &LooperConfig, now_ms: u32) -> Option<Transition> {
+        self.last_tick_ms = Some(now_ms);
+        self.ticks = self.ticks.wrapping_add(1);
+        let bar = config.sync_beats as u32 * TICKS_PER_BEAT;
+        if bar == 0 || self.ticks % bar != 0 {
+            return None;
+        }
+        let role = self.pending.take()?;
+        self.apply(config, role)
+    }
+
+    /// Drop a transition waiting for the bar.
+    pub fn cancel_pending(&mut self) {
+        self.pending = None;
+    }
+
+    /// Fire a deferred transition right away if the clock stopped before the bar.
+    pub fn tick(&mut self, config: &LooperConfig, now_ms: u32) -> Option<Transition> {
+        if self.clock_running(now_ms) {
+            return None;
+        }
+        let role = self.pending.take()?;
+        self.apply(config, role)
+    }
+
+    /// A clock tick arrived recently.
+    fn clock_running(&self, now_ms: u32) -> bool {
+        matches!(self.last_tick_ms, Some(last) if now_ms.wrapping_sub(last) <= CLOCK_TIMEOUT_MS)
+    }
+
+    fn apply(&mut self, config: &LooperConfig, role: LooperRole) -> Option<Transition> {
+        use LooperState::*;
+
+        let msgs = &config.messages;
+        let (to, message) = match (role, self.state) {
+            (LooperRole::Main, Empty) => {
+                self.ticks = 0;
+                (Recording, msgs.record)
+            }
+            (LooperRole::Main, Recording) if config.record_to_overdub => {
+                (Overdubbing, msgs.overdub)
+            }
+            (LooperRole::Main, Recording) | (LooperRole::Main, Stopped) => (Playing, msgs.play),
+            (LooperRole::Main, Playing) => (Overdubbing, msgs.overdub),
+            (LooperRole::Main, Overdubbing) => {
+                self.can_undo = true;
+                self.can_redo = false;
+                (Playing, msgs.play)
+            }
+            (LooperRole::Stop, s) if s.is_running() => (Stopped, msgs.stop),
+            (LooperRole::UndoRedo, s) if s != Empty && self.can_undo => {
+                self.can_undo = false;
+                self.can_redo = true;
+                (s, msgs.undo)
+            }
+            (LooperRole::UndoRedo, s) if s != Empty && self.can_redo => {
+                self.can_undo = true;
+                self.can_redo = false;
+                (s, msgs.redo)
+            }
+            (LooperRole::Clear, s) if s != Empty => {
+                self.can_undo = false;
+                self.can_redo = false;
+                (Empty, msgs.clear)
+            }
+            _ => return None,
+        };
+        let from = self.state;
+        self.state = to;
+        Some(Transition { from, to, message })
+    }
+}
@@ -120,6 +120,15 @@ mod app {
         state_store: midi_controller::state::PresetStateStore,
         presets_skipped: u8,
         button_active: [bool; 6],
+        setlist: pedalboard_midi::setlist::Setlist,
+        active_song: Option<u8>,
+        settings: pedalboard_midi::settings::Settings,
+        preview_preset: Option<u8>,
+        looper_state: Option<pedalboard_midi::looper::LooperState>,
+        profiles: pedalboard_midi::profile::Profiles,
+        lfo: pedalboard_midi::lfo::Lfo,
+        storage_health: pedalboard_midi::storage::StorageHealth,
+        banks: pedalboard_midi::bank::Banks,
     }
 
     #[local]
@@ -137,11 +146,18 @@ mod app {
         usb_sender_usb_thru: Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
         din_thru_receiver: Receiver<'static, [u8; 3], DIN_THRU_CAPACITY>,
         din_thru_sender: Sender<'static, [u8; 3], DIN_THRU_CAPACITY>,
-        trigger_sender_din: Sender<'static, [u8; 3], TRIGGER_CAPACITY>,
-        trigger_sender_usb: Sender<'static, [u8; 3], TRIGGER_CAPACITY>,
-        trigger_receiver: Receiver<'static, [u8; 3], TRIGGER_CAPACITY>,
+        trigger_sender_din:
+            Sender<'static, (midi_controller::routing::MidiPort, [u8; 3]), TRIGGER_CAPACITY>,
+        trigger_sender_usb:
+            Sender<'static, (midi_controller::routing::MidiPort, [u8; 3]), TRIGGER_CAPACITY>,
+        trigger_receiver:
+            Receiver<'static, (midi_controller::routing::MidiPort, [u8; 3]), TRIGGER_CAPACITY>,
+        transport_sender_usb:
+            Sender<'static, pedalboard_midi::transport::TransportEvent, TRANSPORT_CAPACITY>,
+        transport_receiver:
+            Receiver<'static, pedalboard_midi::transport::TransportEvent, TRANSPORT_CAPACITY>,
         persist_sender: Sender<'static, pedalboard_midi::persist::PersistCommand, PERSIST_CAPACITY>,
-        eeprom_i2c: AtomicDevice<'static, I2CBus>,
+        eeprom: pedalboard_midi::eeprom::Eeprom<AtomicDevice<'static, I2CBus>>,
     }
     const USB_OUT_CAPACITY: usize = 128;
     const _: () = assert!(
@@ -152,8 +168,14 @@ mod app {
     const LED_CAPACITY: usize = 4;
     const DIN_THRU_CAPACITY: usize = 8;
     const TRIGGER_CAPACITY: usize = 8;
+    const TRANSPORT_CAPACITY: usize = 4;
     const SYSTEM_STATUS_CAPACITY: usize = 1;
     const CONFIG_DISPLAY_CAPACITY: usize = 8;
+    const PRESET_EXT_CAPACITY: usize = 1;
+    const CLOCK_TICK_CAPACITY: usize = 4;
+    const SONG_POSITION_CAPACITY: usize = 1;
+    /// Room for a full result behind one still waiting on a delay.
+    const STEP_QUEUE_CAPACITY: usize = 2 * pedalboard_midi::pe_handler::MAX_RESULT_STEPS;
 
     #[init(local = [
         usb_bus: MaybeUninit<usb_device::bus::UsbBusAllocator<UsbBus>> = MaybeUninit::uninit(),
@@ -296,22 +318,19 @@ mod app {
             &clocks.system_clock,
         );
 
-        // Read runtime state from EEPROM (data address 0x50)
+        let i2c_bus = ctx.local.i2c_bus.write(AtomicCell::new(i2c));
+
+        // Read runtime state from EEPROM
+        let mut eeprom = pedalboard_midi::eeprom::Eeprom::new(AtomicDevice::new(i2c_bus));
         let mut restored_state = midi_controller::state::PresetStateStore::new();
         let mut restored_active: u8 = 0;
-        {
-            use embedded_hal::i2c::I2c;
-            let mut buf = [0u8; 128];
-            if i2c.write_read(0x50u8, &[0x00u8], &mut buf).is_ok() {
-                if let Some(store) = midi_controller::state::PresetStateStore::from_eeprom(&buf) {
-                    restored_active = store.active_index();
-                    restored_state = store;
-                    info!("EEPROM: restored runtime state, preset {}", restored_active);
-                }
+        if let Ok(Some(buf)) = eeprom.load() {
+            if let Some(store) = midi_controller::state::PresetStateStore::from_eeprom(&buf) {
+                restored_active = store.active_index();
+                restored_state = store;
+                info!("EEPROM: restored runtime state, preset {}", restored_active);
             }
         }
-
-        let i2c_bus = ctx.local.i2c_bus.write(AtomicCell::new(i2c));
         let mut displays = crate::hmi::display::Displays::new(
             AtomicDevice::new(i2c_bus),
             AtomicDevice::new(i2c_bus),
@@ -326,7 +345,14 @@ mod app {
 
         let (led_sender, led_receiver) = make_channel!(LedEvent, LED_CAPACITY);
         let (din_thru_sender, din_thru_receiver) = make_channel!([u8; 3], DIN_THRU_CAPACITY);
-        let (trigger_sender, trigger_receiver) = make_channel!([u8; 3], TRIGGER_CAPACITY);
+        let (trigger_sender, trigger_receiver) = make_channel!(
+            (midi_controller::routing::MidiPort, [u8; 3]),
+            TRIGGER_CAPACITY
+        );
+        let (transport_sender, transport_receiver) = make_channel!(
+            pedalboard_midi::transport::TransportEvent,
+            TRANSPORT_CAPACITY
+        );
         let (persist_sender, persist_receiver) =
             make_channel!(pedalboard_midi::persist::PersistCommand, PERSIST_CAPACITY);
         let (system_status_sender, system_status_receiver) =
@@ -335,6 +361,13 @@ mod app {
             pedalboard_midi::config_mode::ConfigDisplayEvent,
             CONFIG_DISPLAY_CAPACITY
         );
+        let (preset_ext_sender, preset_ext_receiver) = make_channel!(
+            (u8, pedalboard_midi::preset_ext::PresetExt),
+            PRESET_EXT_CAPACITY
+        );
+        let (clock_tick_sender, clock_tick_receiver) = make_channel!((), CLOCK_TICK_CAPACITY);
+        let (song_position_sender, song_position_receiver) =
+            make_channel!(u16, SONG_POSITION_CAPACITY);
 
         blink::spawn().unwrap();
         led_out::spawn(led_receiver).unwrap();
@@ -345,6 +378,9 @@ mod app {
             led_sender.clone(),
             persist_sender.clone(),
             config_display_sender,
+            preset_ext_receiver,
+            clock_tick_receiver,
+            song_position_sender,
         )
         .unwrap();
         display_out::spawn(
@@ -354,11 +390,19 @@ mod app {
             config_display_receiver,
         )
         .unwrap();
-        persist::spawn(persist_receiver, system_status_sender).unwrap();
+        persist::spawn(
+            persist_receiver,
+            system_status_sender,
+            usb_sender.clone(),
+            preset_ext_sender,
+        )
+        .unwrap();
         midi_clock::spawn(
             usb_sender.clone(),
             din_thru_sender.clone(),
             led_sender.clone(),
+            clock_tick_sender,
+            song_position_receiver,
         )
         .unwrap();
 
@@ -377,6 +421,15 @@ mod app {
                 state_store: restored_state,
                 presets_skipped: 0,
                 button_active: [false; 6],
+                setlist: pedalboard_midi::setlist::Setlist::new(),
+                active_song: None,
+                settings: pedalboard_midi::settings::Settings::new(),
+                preview_preset: None,
+                looper_state: None,
+                lfo: pedalboard_midi::lfo::Lfo::new(),
+                profiles: pedalboard_midi::profile::Profiles::new(),
+                storage_health: Default::default(),
+                banks: pedalboard_midi::bank::Banks::new(),
             },
             Local {
                 uart_midi_out,
@@ -392,8 +445,10 @@ mod app {
                 trigger_sender_din: trigger_sender.clone(),
                 trigger_sender_usb: trigger_sender.clone(),
                 trigger_receiver,
+                transport_sender_usb: transport_sender,
+                transport_receiver,
                 persist_sender,
-                eeprom_i2c: AtomicDevice::new(i2c_bus),
+                eeprom,
             },
         )
     }
@@ -405,14 +460,17 @@ mod app {
                 let mut buf = [0x00u8; 3];
                 m.render_slice(&mut buf);
                 // All routing, reactive LEDs, and Mon LED handled in poll_input
-                ctx.local.trigger_sender_din.try_send(buf).ok();
+                ctx.local
+                    .trigger_sender_din
+                    .try_send((midi_controller::routing::MidiPort::DIN, buf))
+                    .ok();
             }
             Err(nb::Error::WouldBlock) => {}
             Err(_) => error!("failed to receive midi message"),
         }
     }
 
-    #[task(priority = 2, local = [inputs, uart_midi_out, din_thru_receiver, trigger_receiver], shared = [active_preset, pe_config, global_config, state_store, button_active])]
+    #[task(priority = 2, local = [inputs, uart_midi_out, din_thru_receiver, trigger_receiver, transport_receiver], shared = [active_preset, pe_config, global_config, state_store, button_active, setlist, active_song, settings, preview_preset, profiles, looper_state, lfo, banks])]
     async fn poll_input(
         mut ctx: poll_input::Context,
         mut sender: Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
@@ -429,6 +487,13 @@ mod app {
             pedalboard_midi::config_mode::ConfigDisplayEvent,
             CONFIG_DISPLAY_CAPACITY,
         >,
+        mut preset_ext_receiver: Receiver<
+            'static,
+            (u8, pedalboard_midi::preset_ext::PresetExt),
+            PRESET_EXT_CAPACITY,
+        >,
+        mut clock_tick_receiver: Receiver<'static, (), CLOCK_TICK_CAPACITY>,
+        mut song_position_sender: Sender<'static, u16, SONG_POSITION_CAPACITY>,
     ) {
         let inputs = ctx.local.inputs;
         let uart_midi_out = ctx.local.uart_midi_out;
@@ -445,6 +510,8 @@ mod app {
             let store = ctx.shared.state_store.lock(|s| s.clone());
             pedalboard_midi::pe_handler::PeHandler::with_state(store)
         };
+        // Per-port shaping of forwarded external clock
+        let mut ext_clock = pedalboard_midi::clock_out::ClockShaper::new();
 
         // Initial LED render from restored state
         {
@@ -472,7 +539,9 @@ mod app {
                                         }
                                     }
                                 }
-                                MidiStep::Delay(_) | MidiStep::SetLed { .. } => {}
+                                MidiStep::Delay(_)
+                                | MidiStep::SysEx(_)
+                                | MidiStep::SetLed { .. } => {}
                             }
                         }
                     }
@@ -488,6 +557,8 @@ mod app {
 
         let mut config_mode = pedalboard_midi::config_mode::ConfigMode::new();
         let mut leds_initialized = false;
+        // Action steps in flight (delays are awaited here, not inline)
+        let mut step_queue = pedalboard_midi::pe_handler::StepQueue::<STEP_QUEUE_CAPACITY>::new();
 
         loop {
             // One-shot LED init: render LEDs once config is loaded from flash.
@@ -530,43 +601,79 @@ mod app {
                 }
             }
 
-            // Process incoming MIDI (routing, reactive LEDs, triggers)
-            while let Ok(raw) = ctx.local.trigger_receiver.try_recv() {
-                // Log incoming MIDI to config mode display.
-                if config_mode.is_active() {
-                    let msg_len: u8 = match raw[0] & 0xF0 {
-                        0xC0 | 0xD0 => 2, // Program Change, Channel Pressure
-                        _ => 3,
-                    };
-                    config_display_sender
-                        .try_send(pedalboard_midi::config_mode::ConfigDisplayEvent::MidiIn {
-                            data: raw,
-                            len: msg_len,
-                        })
+            // Process incoming MIDI (routing, reactive LEDs, triggers), MMC and
+            // internal clock ticks
+            loop {
+                let now_ms = (Mono::now().ticks() / 1_000) as u32;
+                let (result, incoming) = if let Ok((source, raw)) =
+                    ctx.local.trigger_receiver.try_recv()
+                {
+                    // Log incoming MIDI to config mode display.
+                    if config_mode.is_active() {
+                        let msg_len: u8 = match raw[0] & 0xF0 {
+                            0xC0 | 0xD0 => 2, // Program Change, Channel Pressure
+                            _ => 3,
+                        };
+                        config_display_sender
+                            .try_send(pedalboard_midi::config_mode::ConfigDisplayEvent::MidiIn {
+                                data: raw,
+                                len: msg_len,
+                            })
+                            .ok();
+                    }
+                    let result = ctx
+                        .shared
+                        .pe_config
+                        .lock(|cfg| pe.process_midi_from(cfg, &raw, source, now_ms));
+                    (result, true)
+                } else if let Ok(event) = ctx.local.transport_receiver.try_recv() {
+                    let result = ctx
+                        .shared
+                        .pe_config
+                        .lock(|cfg| pe.process_transport(cfg, event));
+                    (result, true)
+                } else if clock_tick_receiver.try_recv().is_ok() {
+                    (pe.internal_clock_tick(now_ms), false)
+                } else {
+                    break;
+                };
+                // Mon LED: blue flash for incoming MIDI activity
+                if incoming {
+                    led_sender
+                        .try_send(LedEvent::Flash(
+                            Led::Mon,
+                            smart_leds::RGB8::new(0, 0, 64),
+                            5,
+                        ))
                         .ok();
                 }
-                let result = ctx
-                    .shared
-                    .pe_config
-                    .lock(|cfg| pe.process_incoming_midi(cfg, &raw));
-                // Mon LED: blue flash for incoming MIDI activity
-                led_sender
-                    .try_send(LedEvent::Flash(
-                        Led::Mon,
-                        smart_leds::RGB8::new(0, 0, 64),
-                        5,
-                    ))
-                    .ok();
                 // Thru routing: send routed MIDI via DIN and/or USB
                 for routed in &result.routed {
                     use midi_controller::routing::MidiPort;
                     let bytes = routed.bytes();
-                    if routed.dest.contains(MidiPort::DIN) {
+                    // Forwarded clock follows the per-port clock settings
+                    let dest = match bytes.first() {
+                        Some(0xF8) => {
+                            let ports = ctx.shared.settings.lock(|s| s.clock);
+                            ext_clock.external_tick(
+                                &ports,
+                                routed.dest,
+                                Mono::now().ticks() as u32,
+                            );
+                            continue;
+                        }
+                        Some(&status @ (0xFA | 0xFB | 0xFC)) => {
+                            let ports = ctx.shared.settings.lock(|s| s.clock);
+                            ext_clock.transport(&ports, routed.dest, status)
+                        }
+                        _ => routed.dest,
+                    };
+                    if dest.contains(MidiPort::DIN) {
                         if let Ok(mm) = MidiMessage::try_parse_slice(bytes) {
                             uart_midi_out.write(&mm).ok();
                         }
                     }
-                    if routed.dest.contains(MidiPort::USB) {
+                    if dest.contains(MidiPort::USB) {
                         if let Ok(packet) =
                             UsbMidiEventPacket::try_from_payload_bytes(CableNumber::Cable0, bytes)
                         {
@@ -621,6 +728,16 @@ mod app {
                                 }
                             }
                         }
+                        MidiStep::SysEx(msg) => {
+                            for chunk in msg.chunks(3) {
+                                if let Ok(packet) = UsbMidiEventPacket::try_from_payload_bytes(
+                                    CableNumber::Cable0,
+                                    chunk,
+                                ) {
+                                    sender.try_send(packet).ok();
+                                }
+                            }
+                        }
                         MidiStep::Delay(_) => {}
                         MidiStep::SetLed { .. } => {}
                     }
@@ -628,17 +745,57 @@ mod app {
                 // Handle tap tempo from result
                 if let Some(bpm) = result.bpm {
                     ctx.shared.global_config.lock(|gc| gc.bpm = bpm);
+                    persist_sender
+                        .try_send(pedalboard_midi::persist::PersistCommand::SaveTempo(bpm))
+                        .ok();
                 }
                 // Handle clock start/stop from button actions
                 if let Some(running) = result.clock_running {
                     ctx.shared.global_config.lock(|gc| gc.midi_clock = running);
+                    persist_sender
+                        .try_send(pedalboard_midi::persist::PersistCommand::SaveClockRunning(
+                            running,
+                        ))
+                        .ok();
                 }
-                // Handle preset change
+                // Handle preset change (triggers, Program Change follow)
                 if result.preset_changed {
                     let new_idx = pe.active_preset();
                     ctx.shared.active_preset.lock(|p| *p = new_idx);
+                    persist_sender
+                        .try_send(pedalboard_midi::persist::PersistCommand::SaveActivePreset(
+                            new_idx,
+                        ))
+                        .ok();
                 }
+                // Persist state set by incoming MIDI (preset follow, state sync)
                 if result.leds_changed || result.preset_changed {
+                    persist_sender
+                        .try_send(pedalboard_midi::persist::PersistCommand::SaveState(
+                            pe.eeprom_state(),
+                        ))
+                        .ok();
+                }
+                if result.looper_changed || result.preset_changed {
+                    let looper = pe.looper_state();
+                    ctx.shared.looper_state.lock(|l| *l = looper);
+                }
+                // Advance the LFO on the clock in charge
+                if result.clock_tick {
+                    let (cc, ring) = ctx.shared.lfo.lock(|lfo| (lfo.tick(), lfo.ring_update()));
+                    if cc.is_some() || ring.is_some() {
+                        let din_enabled = ctx.shared.global_config.lock(|gc| gc.din_enabled);
+                        dispatch_lfo(
+                            cc,
+                            ring,
+                            din_enabled,
+                            &mut sender,
+                            uart_midi_out,
+                            &mut led_sender,
+                        );
+                    }
+                }
+                if result.leds_changed || result.preset_changed || result.rings_changed {
                     let new_idx = pe.active_preset();
                     ctx.shared.pe_config.lock(|cfg| {
                         if let Some(preset) = cfg.presets.get(new_idx as usize) {
@@ -659,6 +816,27 @@ mod app {
                 }
             }
 
+            // Forwarded clock ticks that are due (divided, multiplied or swung)
+            loop {
+                use midi_controller::routing::MidiPort;
+                let ports = ext_clock.poll(Mono::now().ticks() as u32);
+                if ports.is_empty() {
+                    break;
+                }
+                if ports.contains(MidiPort::DIN) {
+                    if let Ok(mm) = MidiMessage::try_parse_slice(&[0xF8]) {
+                        uart_midi_out.write(&mm).ok();
+                    }
+                }
+                if ports.contains(MidiPort::USB) {
+                    if let Ok(packet) =
+                        UsbMidiEventPacket::try_from_payload_bytes(CableNumber::Cable0, &[0xF8])
+                    {
+                        sender.try_send(packet).ok();
+                    }
+                }
+            }
+
             let mut events = heapless::Vec::<_, 14>::new();
             inputs.poll_encoders(&mut events);
             let slow_events = inputs.update();
@@ -735,6 +913,7 @@ mod app {
                             }
                             (actions, encs, analogs)
                         });
+                    let banks = ctx.shared.banks.lock(|b| b.clone());
 
                     let context = pedalboard_midi::config_mode::ConfigContext {
                         firmware_version: env!("CARGO_PKG_VERSION"),
@@ -749,12 +928,32 @@ mod app {
                         button_actions: &button_actions,
                         encoder_configs,
                         analog_configs,
+                        banks: &banks,
                     };
 
                     let config_events = config_mode.process_events(&events, now_ms_cfg, &context);
                     for evt in config_events {
                         config_display_sender.try_send(evt).ok();
                     }
+                    // Bank picked in config mode: the persist task switches
+                    // and reboots
+                    if let Some(bank) = config_mode.take_bank_switch() {
+                        persist_sender
+                            .try_send(pedalboard_midi::persist::PersistCommand::SelectBank(
+                                bank, None,
+                            ))
+                            .ok();
+                    }
+                    // Vol hold in config mode: same as a snapshot button
+                    if config_mode.take_snapshot() {
+                        let preset = pe.active_preset();
+                        let defaults = ctx.shared.pe_config.lock(|cfg| pe.defaults_snapshot(cfg));
+                        persist_sender
+                            .try_send(pedalboard_midi::persist::PersistCommand::SaveDefaults(
+                                preset, defaults,
+                            ))
+                            .ok();
+                    }
                 }
                 config_mode.is_active()
             };
@@ -765,41 +964,143 @@ mod app {
                 // MIDI processing continues below.
             }
 
-            let mut pe_midi_steps: heapless::Vec<pedalboard_midi::pe_handler::MidiStep, 24> =
-                heapless::Vec::new();
+            let now_ms = (Mono::now().ticks() / 1_000) as u32;
             let mut led_event: Option<LedEvent> = None;
-            let mut din_enabled = true;
 
             let mut preset_idx = ctx.shared.active_preset.lock(|p| *p);
 
+            ctx.shared.settings.lock(|s| pe.set_settings(s));
+            // Preset extensions loaded or uploaded by the persist task
+            while let Ok((idx, ext)) = preset_ext_receiver.try_recv() {
+                pe.set_preset_ext(idx as usize, ext);
+            }
+            let profiles_revision = ctx.shared.profiles.lock(|p| p.revision);
+            if profiles_revision != pe.profiles_revision() {
+                ctx.shared.profiles.lock(|p| pe.set_profiles(p));
+            }
+
+            // Pick up setlist changes (flash load or PE upload)
+            let setlist_revision = ctx.shared.setlist.lock(|s| s.revision);
+            if setlist_revision != pe.setlist_revision() {
+                let restored = ctx.shared.active_song.lock(|s| *s);
+                ctx.shared.setlist.lock(|s| pe.set_setlist(s));
+                if let (None, Some(slot)) = (pe.active_song(), restored) {
+                    pe.set_active_song(slot);
+                }
+                ctx.shared.active_song.lock(|s| *s = pe.active_song());
+            }
+
             // Process events through PE handler
             let need_tick = !events.is_empty() || pe.any_active();
             if need_tick {
-                let now_ms = (Mono::now().ticks() / 1_000) as u32;
                 let result = ctx
                     .shared
                     .pe_config
                     .lock(|cfg| pe.handle_events(cfg, &events, now_ms));
-                for step in &result.midi {
-                    pe_midi_steps.push(step.clone()).ok();
+                if result.panic {
+                    // Panic supersedes this result's MIDI and anything still pending
+                    step_queue.clear();
+                    let din_on = ctx.shared.global_config.lock(|gc| gc.din_enabled);
+                    for msg in pedalboard_midi::pe_handler::panic_messages() {
+                        if din_on {
+                            if let Ok(mm) = MidiMessage::try_parse_slice(&msg) {
+                                uart_midi_out.write(&mm).ok();
+                            }
+                        }
+                        if let Ok(packet) =
+                            UsbMidiEventPacket::try_from_payload_bytes(CableNumber::Cable0, &msg)
+                        {
+                            sender.try_send(packet).ok();
+                        }
+                    }
+                    info!("panic: all notes off sent");
+                } else {
+                    let dropped = step_queue.extend(&result.midi);
+                    if dropped > 0 {
+                        warn!(
+                            "step queue full: {} steps dropped ({} since boot)",
+                            dropped,
+                            step_queue.dropped()
+                        );
+                    }
                 }
                 // Handle tap tempo from result
                 if let Some(bpm) = result.bpm {
                     ctx.shared.global_config.lock(|gc| gc.bpm = bpm);
+                    persist_sender
+                        .try_send(pedalboard_midi::persist::PersistCommand::SaveTempo(bpm))
+                        .ok();
                     if !result.preset_changed && !config_active {
                         display_event_sender
                             .try_send(pedalboard_midi::pe_handler::DisplayEvent::BpmOverlay { bpm })
                             .ok();
                     }
                 }
+                // Song position goes out with the clock, ahead of its Continue
+                if let Some(position) = result.song_position {
+                    song_position_sender.try_send(position).ok();
+                }
                 // Handle clock start/stop from button actions
                 if let Some(running) = result.clock_running {
                     ctx.shared.global_config.lock(|gc| gc.midi_clock = running);
+                    persist_sender
+                        .try_send(pedalboard_midi::persist::PersistCommand::SaveClockRunning(
+                            running,
+                        ))
+                        .ok();
                 }
                 let new_preset = pe.active_preset();
                 if result.preset_changed {
                     ctx.shared.active_preset.lock(|p| *p = new_preset);
                 }
+                if result.preview_changed {
+                    let preview = pe.preview();
+                    ctx.shared.preview_preset.lock(|p| *p = preview);
+                    // Blink the Mode LED in the candidate's bank colour while browsing
+                    let mode_led = match preview {
+                        Some(target) => {
+                            LedEvent::Blink(Led::Mode, pedalboard_midi::leds::preset_color(target))
+                        }
+                        None => LedEvent::SetSingle(
+                            Led::Mode,
+                            Some(pedalboard_midi::leds::preset_color(new_preset)),
+                        ),
+                    };
+                    led_sender.try_send(mode_led).ok();
+                }
+                if result.looper_changed || result.preset_changed {
+                    let looper = pe.looper_state();
+                    ctx.shared.looper_state.lock(|l| *l = looper);
+                }
+                if result.lfo.is_some() || result.preset_changed {
+                    let setup = pe.lfo_config();
+                    ctx.shared.lfo.lock(|lfo| {
+                        lfo.configure(setup);
+                        if let Some((control, button)) = result.lfo {
+                            lfo.control(control, button);
+                        }
+                    });
+                }
+                if result.song_changed {
+                    let song = pe.active_song();
+                    ctx.shared.active_song.lock(|s| *s = song);
+                    if let Some(slot) = song {
+                        persist_sender
+                            .try_send(pedalboard_midi::persist::PersistCommand::SaveActiveSong(
+                                slot,
+                            ))
+                            .ok();
+                    }
+                }
+                // Snapshot button: keep the current sound as the preset's defaults
+                if result.snapshot {
+                    let defaults = ctx.shared.pe_config.lock(|cfg| pe.defaults_snapshot(cfg));
+                    persist_sender
+                        .try_send(pedalboard_midi::persist::PersistCommand::SaveDefaults(
+                            new_preset, defaults,
+                        ))
+                        .ok();
+                }
                 // Send display events directly (no MIDI round-trip)
                 if !config_active {
                     for evt in result.display {
@@ -807,7 +1108,8 @@ mod app {
                     }
                 }
                 // Update LEDs and preset index on actual switch
-                let led_dirty = result.leds_changed || result.preset_changed;
+                let state_dirty = result.leds_changed || result.preset_changed;
+                let led_dirty = state_dirty || result.rings_changed;
                 if result.preset_changed {
                     preset_idx = new_preset;
                     led_sender
@@ -817,20 +1119,16 @@ mod app {
                         ))
                         .ok();
                 }
-                if !pe_midi_steps.is_empty() || led_dirty {
-                    // Read DIN enabled from global config
-                    din_enabled = ctx.shared.global_config.lock(|gc| gc.din_enabled);
-                    if led_dirty {
-                        ctx.shared.pe_config.lock(|cfg| {
-                            let preset = &cfg.presets[preset_idx as usize];
-                            let anims = pe.led_state(preset);
-                            led_event = Some(LedEvent::SetAllRings(anims));
-                        });
-                        ctx.shared.button_active.lock(|ba| *ba = pe.button_active());
-                    }
+                if led_dirty {
+                    ctx.shared.pe_config.lock(|cfg| {
+                        let preset = &cfg.presets[preset_idx as usize];
+                        let anims = pe.led_state(preset);
+                        led_event = Some(LedEvent::SetAllRings(anims));
+                    });
+                    ctx.shared.button_active.lock(|ba| *ba = pe.button_active());
                 }
                 // Persist state changes to EEPROM/flash
-                if result.preset_changed || led_dirty {
+                if state_dirty {
                     use pedalboard_midi::persist::PersistCommand;
                     if result.preset_changed {
                         persist_sender
@@ -845,11 +1143,13 @@ mod app {
 
             // Send MIDI outside the lock (latency-critical path first)
             let mut midi_sent = false;
-            {
+            if !step_queue.is_empty() {
+                // Read DIN enabled from global config
+                let din_enabled = ctx.shared.global_config.lock(|gc| gc.din_enabled);
                 use midi_controller::routing::MidiPort;
                 use pedalboard_midi::pe_handler::MidiStep;
-                for step in &pe_midi_steps {
-                    match step {
+                while let Some(step) = step_queue.pop_ready(now_ms) {
+                    match &step {
                         MidiStep::Send(raw, len, dest) => {
                             midi_sent = true;
                             // Log outgoing MIDI to config mode display.
@@ -893,9 +1193,19 @@ mod app {
                                 });
                             }
                         }
-                        MidiStep::Delay(ms) => {
-                            Mono::delay(fugit::ExtU32::millis(*ms as u32).into()).await;
+                        MidiStep::SysEx(msg) => {
+                            midi_sent = true;
+                            for chunk in msg.chunks(3) {
+                                if let Ok(packet) = UsbMidiEventPacket::try_from_payload_bytes(
+                                    CableNumber::Cable0,
+                                    chunk,
+                                ) {
+                                    sender.try_send(packet).ok();
+                                }
+                            }
                         }
+                        // Consumed by the queue
+                        MidiStep::Delay(_) => {}
                         MidiStep::SetLed {
                             btn_idx,
                             color,
@@ -951,8 +1261,8 @@ mod app {
     }
 
     #[task(binds = USBCTRL_IRQ, priority = 3,
-        local = [ buf: Vec::<u8, 350>=Vec::new(), led_sender_usb, usb_sender_usb_thru, din_thru_sender, trigger_sender_usb, persist_sender],
-        shared =[usb_midi,usb_dev,pe_config,global_config,active_preset,presets_skipped]
+        local = [ buf: Vec::<u8, 350>=Vec::new(), led_sender_usb, usb_sender_usb_thru, din_thru_sender, trigger_sender_usb, transport_sender_usb, persist_sender],
+        shared =[usb_midi,usb_dev,pe_config,global_config,active_preset,presets_skipped,setlist,settings,profiles,storage_health,banks]
     )]
     fn usb_rx(mut ctx: usb_rx::Context) {
         let sysex_receive_buffer = ctx.local.buf;
@@ -990,7 +1300,10 @@ mod app {
                     if raw.len() >= 3 {
                         let mut arr = [0u8; 3];
                         arr.copy_from_slice(&raw[..3]);
-                        ctx.local.trigger_sender_usb.try_send(arr).ok();
+                        ctx.local
+                            .trigger_sender_usb
+                            .try_send((midi_controller::routing::MidiPort::USB, arr))
+                            .ok();
                     }
                 }
                 continue;
@@ -1006,21 +1319,43 @@ mod app {
                     if packet.is_sysex_end() {
                         debug!("SysEx IN  message: {:?}", sysex_receive_buffer);
 
+                        // MMC from the DAW: handled as transport triggers in poll_input
+                        let mmc_device = ctx.shared.settings.lock(|s| s.mmc_device);
+                        if let Some(event) = pedalboard_midi::transport::parse_mmc(
+                            sysex_receive_buffer.as_ref(),
+                            mmc_device,
+                        ) {
+                            ctx.local.transport_sender_usb.try_send(event).ok();
+                            sysex_receive_buffer.clear();
+                            continue;
+                        }
+
                         // Handle MIDI-CI Property Exchange messages
                         if let Some(result) =
                             pedalboard_midi::pe_sysex::handle_set(sysex_receive_buffer.as_ref())
                         {
+                            let mut reply = result.reply;
                             if let Some(cmd) = result.command {
-                                ctx.local.persist_sender.try_send(cmd).ok();
-                            }
-                            for chunk in result.reply.chunks(3) {
-                                if let Ok(p) = UsbMidiEventPacket::try_from_payload_bytes(
-                                    CableNumber::Cable0,
-                                    chunk,
-                                ) {
-                                    ctx.local.usb_sender_usb_thru.try_send(p).ok();
+                                if ctx.local.persist_sender.try_send(cmd).is_err() {
+                                    // Answered now: the persist task never sees it
+                                    warn!("persist queue full, PE Set rejected");
+                                    let to = pedalboard_midi::persist::ReplyTo {
+                                        muid: midi_controller::property_exchange::source_muid(
+                                            sysex_receive_buffer.as_ref(),
+                                        ),
+                                        request_id: midi_controller::property_exchange::request_id(
+                                            sysex_receive_buffer.as_ref(),
+                                        ),
+                                    };
+                                    reply = Some(pedalboard_midi::pe_sysex::build_reply(
+                                        to,
+                                        pedalboard_midi::persist::SetStatus::Busy,
+                                    ));
                                 }
                             }
+                            if let Some(reply) = reply {
+                                send_sysex(&reply, ctx.local.usb_sender_usb_thru);
+                            }
                             sysex_receive_buffer.clear();
                             continue;
                         }
@@ -1040,16 +1375,46 @@ mod app {
                                 let src_muid = midi_controller::property_exchange::source_muid(
                                     sysex_receive_buffer.as_ref(),
                                 );
+                                let to = pedalboard_midi::persist::ReplyTo {
+                                    muid: src_muid,
+                                    request_id: req_id,
+                                };
+                                // The backup image is streamed by the persist
+                                // task, which also serves the preset extensions
+                                // (kept in RAM by poll_input only) from flash
+                                let from_flash =
+                                    if resource == pedalboard_midi::persist::BACKUP_RESOURCE {
+                                        Some(pedalboard_midi::persist::PersistCommand::Backup(to))
+                                    } else if pedalboard_midi::preset_ext::preset_ext_slot(resource)
+                                        .is_some()
+                                    {
+                                        Some(pedalboard_midi::persist::PersistCommand::Get(
+                                            resource, to,
+                                        ))
+                                    } else {
+                                        None
+                                    };
+                                if let Some(cmd) = from_flash {
+                                    if ctx.local.persist_sender.try_send(cmd).is_err() {
+                                        warn!("persist queue full, PE Get {} rejected", resource);
+                                        let reply = pedalboard_midi::pe_sysex::build_get_error(
+                                            to,
+                                            resource,
+                                            pedalboard_midi::persist::SetStatus::Busy,
+                                        );
+                                        send_sysex(&reply, ctx.local.usb_sender_usb_thru);
+                                    }
+                                    sysex_receive_buffer.clear();
+                                    continue;
+                                }
                                 // Serialize from RAM for PE Get reply
                                 static mut GET_BUF: [u8; pedalboard_midi::MAX_PRESET_SIZE] =
                                     [0u8; pedalboard_midi::MAX_PRESET_SIZE];
+                                let buf = unsafe { &mut *core::ptr::addr_of_mut!(GET_BUF) };
                                 let body = if resource
                                     == midi_controller::config::GLOBAL_CONFIG_RESOURCE
                                 {
-                                    ctx.shared.global_config.lock(|gc| {
-                                        let buf = unsafe { &mut *core::ptr::addr_of_mut!(GET_BUF) };
-                                        postcard::to_slice(gc, buf).ok().map(|s| s.len())
-                                    })
+                                    ctx.shared.global_config.lock(|gc| get_body(Some(gc), buf))
                                 } else if resource == midi_controller::config::DEVICE_INFO_RESOURCE
                                 {
                                     let mut version = heapless::String::<24>::new();
@@ -1069,28 +1434,43 @@ mod app {
                                         presets_skipped: ctx.shared.presets_skipped.lock(|s| *s),
                                         version,
                                     };
-                                    let buf = unsafe { &mut *core::ptr::addr_of_mut!(GET_BUF) };
-                                    postcard::to_slice(&info, buf).ok().map(|s| s.len())
+                                    get_body(Some(&info), buf)
+                                } else if resource == pedalboard_midi::bank::BANKS_RESOURCE {
+                                    ctx.shared.banks.lock(|banks| get_body(Some(banks), buf))
+                                } else if resource == pedalboard_midi::bank::BANK_SELECT_RESOURCE {
+                                    buf[0] = ctx.shared.banks.lock(|banks| banks.active);
+                                    Some(1)
+                                } else if resource == pedalboard_midi::storage::HEALTH_RESOURCE {
+                                    ctx.shared
+                                        .storage_health
+                                        .lock(|health| get_body(Some(health), buf))
+                                } else if resource == pedalboard_midi::settings::SETTINGS_RESOURCE {
+                                    ctx.shared
+                                        .settings
+                                        .lock(|settings| get_body(Some(settings), buf))
+                                } else if let Some(slot) =
+                                    pedalboard_midi::profile::profile_slot(resource)
+                                {
+                                    ctx.shared
+                                        .profiles
+                                        .lock(|profiles| get_body(profiles.get(slot), buf))
+                                } else if let Some(slot) =
+                                    pedalboard_midi::setlist::song_slot(resource)
+                                {
+                                    ctx.shared
+                                        .setlist
+                                        .lock(|setlist| get_body(setlist.get(slot), buf))
                                 } else {
                                     ctx.shared.pe_config.lock(|cfg| {
-                                        let buf = unsafe { &mut *core::ptr::addr_of_mut!(GET_BUF) };
-                                        if let Some(preset) = cfg.presets.get(resource as usize) {
-                                            if preset.name.is_empty() {
-                                                None
-                                            } else {
-                                                postcard::to_slice(preset, buf)
-                                                    .ok()
-                                                    .map(|s| s.len())
-                                            }
-                                        } else {
-                                            None
-                                        }
+                                        let preset = cfg
+                                            .presets
+                                            .get(resource as usize)
+                                            .filter(|preset| !preset.name.is_empty());
+                                        get_body(preset, buf)
                                     })
                                 };
                                 let reply_body: &[u8] = match body {
-                                    Some(len) => unsafe {
-                                        &(&(*core::ptr::addr_of!(GET_BUF)))[..len]
-                                    },
+                                    Some(len) => &buf[..len],
                                     None => &[],
                                 };
                                 let get_status = if reply_body.is_empty() {
@@ -1099,7 +1479,7 @@ mod app {
                                     midi_controller::property_exchange::PeStatus::Ok
                                 };
                                 let reply = midi_controller::property_exchange::build_get_reply(
-                                    [0x01, 0x02, 0x03, 0x04],
+                                    pedalboard_midi::pe_sysex::DEVICE_MUID,
                                     src_muid,
                                     req_id,
                                     resource,
@@ -1130,48 +1510,75 @@ mod app {
         }
     }
 
-    #[task(local = [eeprom_i2c], shared = [pe_config, global_config, active_preset, state_store, presets_skipped])]
+    #[task(local = [eeprom], shared = [pe_config, global_config, active_preset, state_store, presets_skipped, setlist, active_song, settings, profiles, storage_health, banks])]
     async fn persist(
         mut ctx: persist::Context,
         mut receiver: Receiver<'static, pedalboard_midi::persist::PersistCommand, PERSIST_CAPACITY>,
         mut status_sender: Sender<'static, SystemStatus, SYSTEM_STATUS_CAPACITY>,
+        mut usb_sender: Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
+        mut preset_ext_sender: Sender<
+            'static,
+            (u8, pedalboard_midi::preset_ext::PresetExt),
+            PRESET_EXT_CAPACITY,
+        >,
     ) {
-        let eeprom = ctx.local.eeprom_i2c;
+        let eeprom = ctx.local.eeprom;
         info!("config persistence: loading from flash");
         if let Some(mut store) = pedalboard_midi::storage::ConfigStore::try_new() {
             info!("config persistence ready");
 
-            // Load presets from flash
+            // Presets and extensions come from the active bank
+            let bank = store
+                .load_value(pedalboard_midi::storage::ACTIVE_BANK_KEY)
+                .await
+                .map(|b| b as u8)
+                .filter(|&b| (b as usize) < pedalboard_midi::bank::MAX_BANKS)
+                .unwrap_or(0);
+            store.set_bank(bank);
+            if bank != 0 {
+                info!("preset bank {} active", bank);
+            }
+
+            // Load presets from flash, upgrading legacy layouts
+            use pedalboard_midi::migrate::{self, Loaded};
             let mut preset_count = 0u8;
+            let mut upgraded: heapless::Vec<u8, 32> = heapless::Vec::new();
             store
                 .load_all_presets(|idx, data| {
-                    // Check flash format version prefix
                     if data.is_empty() {
                         return;
                     }
-                    if data[0] != pedalboard_midi::FLASH_FORMAT_VERSION {
-                        warn!(
-                            "preset {}: flash format v{}, firmware expects v{} — skipped",
-                            idx,
-                            data[0],
-                            pedalboard_midi::FLASH_FORMAT_VERSION
-                        );
-                        ctx.shared.presets_skipped.lock(|s| *s += 1);
-                        return;
-                    }
-                    let payload = &data[1..]; // strip version byte
-                    if let Ok(preset) =
-                        postcard::from_bytes::<midi_controller::config::Preset>(payload)
-                    {
-                        ctx.shared.pe_config.lock(|cfg| {
-                            let i = idx as usize;
-                            while cfg.presets.len() <= i {
-                                cfg.presets.push(Default::default()).ok();
-                            }
-                            cfg.presets[i] = preset;
-                        });
-                        preset_count += 1;
-                    }
+                    let preset = match migrate::load::<midi_controller::config::Preset>(
+                        data,
+                        pedalboard_midi::FLASH_FORMAT_VERSION,
+                        migrate::PRESET_LAYOUTS,
+                    ) {
+                        Loaded::Current(preset) => preset,
+                        Loaded::Upgraded(preset) => {
+                            info!("preset {}: upgraded from flash format v{}", idx, data[0]);
+                            upgraded.push(idx).ok();
+                            preset
+                        }
+                        Loaded::Unsupported(version) => {
+                            warn!(
+                                "preset {}: flash format v{}, firmware expects v{} — skipped",
+                                idx,
+                                version,
+                                pedalboard_midi::FLASH_FORMAT_VERSION
+                            );
+                            ctx.shared.presets_skipped.lock(|s| *s += 1);
+                            return;
+                        }
+                        Loaded::Corrupt => return,
+                    };
+                    ctx.shared.pe_config.lock(|cfg| {
+                        let i = idx as usize;
+                        while cfg.presets.len() <= i {
+                            cfg.presets.push(Default::default()).ok();
+                        }
+                        cfg.presets[i] = preset;
+                    });
+                    preset_count += 1;
                 })
                 .await;
             if preset_count == 0 {
@@ -1179,171 +1586,808 @@ mod app {
             } else {
                 info!("{} presets loaded from flash", preset_count);
             }
+            // Write upgraded presets back in the current layout
+            for idx in upgraded {
+                let preset = ctx
+                    .shared
+                    .pe_config
+                    .lock(|cfg| cfg.presets.get(idx as usize).cloned());
+                let mut buf = [0u8; pedalboard_midi::MAX_PRESET_SIZE + 1];
+                let version = pedalboard_midi::FLASH_FORMAT_VERSION;
+                if let Some(blob) = preset.and_then(|p| migrate::encode(&p, version, &mut buf)) {
+                    store.save_preset(idx, blob).await.ok();
+                }
+            }
 
             // Load global config from flash
-            let mut gc_buf = [0u8; 64];
-            if let Some(data) = store
-                .load_preset(midi_controller::config::GLOBAL_CONFIG_RESOURCE, &mut gc_buf)
-                .await
+            if let Some(gc) = load_resource::<midi_controller::config::GlobalConfig>(
+                &mut store,
+                midi_controller::config::GLOBAL_CONFIG_RESOURCE,
+                migrate::GLOBAL_CONFIG_LAYOUTS,
+            )
+            .await
             {
-                if data.is_empty() || data[0] != pedalboard_midi::FLASH_FORMAT_VERSION {
-                    if !data.is_empty() {
-                        warn!(
-                            "global config: flash format v{}, firmware expects v{} — skipped",
-                            data[0],
-                            pedalboard_midi::FLASH_FORMAT_VERSION
-                        );
-                    }
-                } else if let Ok(gc) =
-                    postcard::from_bytes::<midi_controller::config::GlobalConfig>(&data[1..])
+                info!("global config loaded from flash");
+                ctx.shared.global_config.lock(|g| *g = gc.clone());
+                ctx.shared.pe_config.lock(|cfg| cfg.global = gc);
+            }
+
+            // Tempo and clock changed at runtime win over the uploaded config
+            let mut runtime = pedalboard_midi::persist::RuntimeOverride::from_values(
+                store.load_value(pedalboard_midi::storage::TEMPO_KEY).await,
+                store
+                    .load_value(pedalboard_midi::storage::CLOCK_RUNNING_KEY)
+                    .await,
+            );
+            if runtime != Default::default() {
+                info!("runtime tempo/clock restored");
+                ctx.shared.global_config.lock(|g| runtime.apply(g));
+                ctx.shared
+                    .pe_config
+                    .lock(|cfg| runtime.apply(&mut cfg.global));
+            }
+            // Runtime override changed since it was last stored
+            let mut runtime_dirty = false;
+
+            // Load device settings from flash
+            if let Some(settings) = load_resource::<pedalboard_midi::settings::Settings>(
+                &mut store,
+                pedalboard_midi::settings::SETTINGS_RESOURCE,
+                migrate::SETTINGS_LAYOUTS,
+            )
+            .await
+            {
+                info!("device settings loaded from flash");
+                ctx.shared.settings.lock(|s| *s = settings);
+            }
+
+            // Preset extensions go to poll_input, which keeps the only copy
+            for idx in 0..pedalboard_midi::preset_ext::MAX_PRESET_EXTS as u8 {
+                let resource = pedalboard_midi::preset_ext::PRESET_EXT_RESOURCE_BASE + idx;
+                if let Some(ext) =
+                    load_resource(&mut store, resource, migrate::PRESET_EXT_LAYOUTS).await
+                {
+                    preset_ext_sender.send((idx, ext)).await.ok();
+                }
+            }
+
+            // Load device profiles from flash (one slot per profile)
+            for slot in 0..pedalboard_midi::profile::MAX_PROFILES as u8 {
+                let resource = pedalboard_midi::profile::PROFILE_RESOURCE_BASE + slot;
+                if let Some(profile) =
+                    load_resource(&mut store, resource, migrate::PROFILE_LAYOUTS).await
+                {
+                    info!("profile {} loaded: \"{}\"", slot, profile.name.as_str());
+                    ctx.shared.profiles.lock(|p| p.set(slot as usize, profile));
+                }
+            }
+
+            // Load bank names from flash
+            let mut banks: pedalboard_midi::bank::Banks = load_resource(
+                &mut store,
+                pedalboard_midi::bank::BANKS_RESOURCE,
+                migrate::BANKS_LAYOUTS,
+            )
+            .await
+            .unwrap_or_default();
+            banks.active = bank;
+            ctx.shared.banks.lock(|b| *b = banks);
+
+            // Load setlist songs from flash (one slot per resource)
+            let mut setlist = pedalboard_midi::setlist::Setlist::new();
+            let mut song_count = 0u8;
+            for slot in 0..pedalboard_midi::setlist::MAX_SONGS as u8 {
+                let resource = pedalboard_midi::setlist::SONG_RESOURCE_BASE + slot;
+                if let Some(song) = load_resource(&mut store, resource, migrate::SONG_LAYOUTS).await
                 {
-                    info!("global config loaded from flash");
-                    ctx.shared.global_config.lock(|g| *g = gc.clone());
-                    ctx.shared.pe_config.lock(|cfg| cfg.global = gc);
+                    setlist.set(slot as usize, song);
+                    song_count += 1;
+                }
+            }
+            if song_count > 0 {
+                info!("{} setlist songs loaded from flash", song_count);
+                // Restore the active song before publishing the setlist so
+                // poll_input picks both up in the same sync.
+                if let Some(slot) = store.load_active_song().await {
+                    ctx.shared.active_song.lock(|s| *s = Some(slot));
+                }
+                ctx.shared.setlist.lock(|s| *s = setlist);
+            }
+
+            // Upload transaction: an open one never got its commit and is
+            // dropped; an interrupted commit is finished by replaying what is
+            // still staged.
+            use pedalboard_midi::persist::{PersistCommand, ReplyTo, SetStatus, UploadCommand};
+            use pedalboard_midi::storage::UploadState;
+            let mut upload_open = false;
+            // Staged resources of the open upload (bit = resource index)
+            let mut staged: u128 = 0;
+            // Other banks a restore staged resources for (bit = bank)
+            let mut staged_banks: u8 = 0;
+            // Staged resources still to apply on commit
+            let mut replay: u128 = 0;
+            let mut committing = false;
+            // Commit reply, sent with the first failure once all is applied
+            let mut commit_reply: Option<ReplyTo> = None;
+            let mut commit_status = SetStatus::Ok;
+            // Restore in progress (an upload carrying a backup image)
+            let mut restore: Option<pedalboard_midi::backup::Reader> = None;
+            // Reboot once the commit is applied, so every task picks up the
+            // restored state
+            let mut reboot_after_commit = false;
+            // Latest runtime state, written once changes settle
+            let mut pending_state: Option<[u8; pedalboard_midi::eeprom::EEPROM_SIZE]> = None;
+            // Most recent failed write, for the storage health report
+            let mut last_error: Option<pedalboard_midi::storage::PersistError> = None;
+            match store.upload_state().await {
+                UploadState::Open => {
+                    warn!("unfinished upload discarded");
+                    discard_upload(&mut store, bank, u128::MAX, u8::MAX)
+                        .await
+                        .ok();
+                }
+                UploadState::Committing => {
+                    info!("resuming interrupted upload commit");
+                    replay = u128::MAX;
+                    staged_banks = u8::MAX;
+                    committing = true;
                 }
+                UploadState::Idle => {}
             }
 
+            // Interrupted bank switch: the EEPROM still holds the old bank's
+            // state. Finish it and reboot so every task starts from it.
+            if eeprom.bank() != bank
+                && put_bank_state(&mut store, eeprom, bank).await == SetStatus::Ok
+            {
+                info!("bank {} switch finished, rebooting", bank);
+                status_sender.try_send(SystemStatus::Rebooting).ok();
+                Mono::delay(1000.millis()).await;
+                cortex_m::peripheral::SCB::sys_reset();
+            }
+
+            // Storage health needs a walk of the flash: refreshed only after
+            // commands that may have written
+            let mut health_stale = true;
+
             // Enter persist loop
-            while let Ok(cmd) = receiver.recv().await {
-                use pedalboard_midi::persist::PersistCommand;
-                match cmd {
-                    PersistCommand::SavePreset(preset_index, data) => {
-                        // Prepend flash format version byte before storing
-                        let mut versioned: heapless::Vec<
-                            u8,
-                            { pedalboard_midi::MAX_PRESET_SIZE + 1 },
-                        > = heapless::Vec::new();
-                        versioned.push(pedalboard_midi::FLASH_FORMAT_VERSION).ok();
-                        versioned.extend_from_slice(&data).ok();
-
-                        if data.is_empty() {
-                            // Empty body = delete preset from flash and RAM
-                            if preset_index == midi_controller::config::GLOBAL_CONFIG_RESOURCE {
-                                info!("global config cleared");
-                                ctx.shared.global_config.lock(|g| *g = Default::default());
-                                ctx.shared
-                                    .pe_config
-                                    .lock(|cfg| cfg.global = Default::default());
-                                let empty_marker: heapless::Vec<
-                                    u8,
-                                    { pedalboard_midi::MAX_PRESET_SIZE + 1 },
-                                > = heapless::Vec::new();
-                                store.save_preset(preset_index, &empty_marker).await;
-                            } else {
-                                // Only write to flash if the slot was actually occupied
-                                let was_occupied = ctx.shared.pe_config.lock(|cfg| {
-                                    let idx = preset_index as usize;
-                                    if idx < cfg.presets.len() && !cfg.presets[idx].name.is_empty()
-                                    {
-                                        cfg.presets[idx] =
-                                            midi_controller::config::Preset::default();
-                                        true
-                                    } else {
-                                        false
-                                    }
-                                });
-                                if was_occupied {
-                                    info!("preset {} deleted", preset_index);
-                                    let empty_marker: heapless::Vec<
-                                        u8,
-                                        { pedalboard_midi::MAX_PRESET_SIZE + 1 },
-                                    > = heapless::Vec::new();
-                                    store.save_preset(preset_index, &empty_marker).await;
-                                }
-                            }
-                        } else if preset_index == midi_controller::config::GLOBAL_CONFIG_RESOURCE {
-                            // Global config — apply and save to flash
-                            if let Ok(gc) =
-                                postcard::from_bytes::<midi_controller::config::GlobalConfig>(&data)
-                            {
-                                info!("global config applied and saved");
-                                ctx.shared.global_config.lock(|g| *g = gc.clone());
-                                ctx.shared.pe_config.lock(|cfg| cfg.global = gc);
-                            }
-                            store.save_preset(preset_index, &versioned).await;
-                        } else if let Ok(preset) =
-                            postcard::from_bytes::<midi_controller::config::Preset>(&data)
-                        {
-                            info!(
-                                "preset {} loaded: \"{}\"",
-                                preset_index,
-                                preset.name.as_str()
-                            );
-                            ctx.shared.pe_config.lock(|cfg| {
-                                let idx = preset_index as usize;
-                                // Extend presets vec if needed
-                                while cfg.presets.len() <= idx {
-                                    cfg.presets
-                                        .push(midi_controller::config::Preset::default())
-                                        .ok();
-                                }
-                                cfg.presets[idx] = preset;
-                            });
-                            store.save_preset(preset_index, &versioned).await;
-                            // Write initial state from preset defaults to EEPROM
-                            let buf = ctx.shared.pe_config.lock(|cfg| {
-                                let mut state_store =
-                                    midi_controller::state::PresetStateStore::new();
-                                for (i, p) in cfg.presets.iter().enumerate() {
-                                    if i >= midi_controller::state::EEPROM_MAX_PRESETS {
-                                        break;
-                                    }
-                                    if !p.defaults.button_active.is_empty()
-                                        || !p.defaults.encoder_values.is_empty()
-                                    {
-                                        state_store.set_state(
-                                            i,
-                                            midi_controller::state::PresetState::from_defaults(p),
-                                        );
-                                    }
-                                }
-                                let mut buf = [0u8; 128];
-                                state_store.to_eeprom(&mut buf);
-                                buf
-                            });
-                            use embedded_hal::i2c::I2c;
-                            for page in 0..16 {
-                                let offset = page * 8;
-                                let mut wbuf = [0u8; 9];
-                                wbuf[0] = offset as u8;
-                                wbuf[1..9].copy_from_slice(&buf[offset..offset + 8]);
-                                eeprom.write(0x50u8, &wbuf).ok();
-                                Mono::delay(5.millis()).await;
-                            }
-                        } else {
-                            warn!("preset {} deserialize failed", preset_index);
+            loop {
+                // Publish storage health for PE Gets and the config-mode screen
+                if core::mem::take(&mut health_stale) {
+                    let health = storage_health(&mut store, eeprom, &mut last_error).await;
+                    ctx.shared.storage_health.lock(|h| *h = health);
+                }
+
+                // Commit fully applied: end the transaction and report it
+                if committing && replay == 0 {
+                    committing = false;
+                    let others = (0..pedalboard_midi::bank::MAX_BANKS as u8)
+                        .filter(|&b| b != bank && staged_banks & (1 << b) != 0);
+                    for other in others {
+                        let status = apply_staged_bank(&mut store, other, bank).await;
+                        if commit_status == SetStatus::Ok {
+                            commit_status = status;
                         }
                     }
-                    PersistCommand::SaveActivePreset(idx) => {
-                        store.save(8, 0, 0, idx as u16).await;
+                    staged_banks = 0;
+                    if store.set_upload_state(UploadState::Idle).await.is_err() {
+                        commit_status = SetStatus::FlashError;
                     }
-                    PersistCommand::SaveState(data) => {
-                        // Write to AT24CS01 EEPROM at 0x50 in 8-byte pages
-                        use embedded_hal::i2c::I2c;
-                        for page in 0..16 {
-                            let offset = page * 8;
-                            let mut wbuf = [0u8; 9];
-                            wbuf[0] = offset as u8;
-                            wbuf[1..9].copy_from_slice(&data[offset..offset + 8]);
-                            eeprom.write(0x50u8, &wbuf).ok();
-                            Mono::delay(5.millis()).await;
-                        }
+                    if let Some(to) = commit_reply.take() {
+                        send_set_reply(to, commit_status, &mut usb_sender);
+                    }
+                    if core::mem::take(&mut reboot_after_commit) {
+                        info!("restore applied, rebooting");
+                        status_sender.try_send(SystemStatus::Rebooting).ok();
+                        Mono::delay(1000.millis()).await;
+                        cortex_m::peripheral::SCB::sys_reset();
+                    }
+                }
+
+                let (cmd, replayed) = if replay != 0 {
+                    let index = replay.trailing_zeros() as u8;
+                    replay &= !(1u128 << index);
+                    let mut blob = [0u8; pedalboard_midi::MAX_PRESET_SIZE];
+                    let cmd = store
+                        .load_staged(index, &mut blob)
+                        .await
+                        .and_then(|data| heapless::Vec::from_slice(data).ok())
+                        .map(|data| PersistCommand::SavePreset(index, data, None));
+                    match cmd {
+                        Some(cmd) => (cmd, Some(index)),
+                        None => continue,
+                    }
+                } else {
+                    // Pending runtime saves wait until changes stop coming
+                    let cmd = if pending_state.is_some() || runtime_dirty {
+                        let quiet = pedalboard_midi::eeprom::SAVE_DEBOUNCE_MS.millis();
+                        match Mono::timeout_after(quiet, receiver.recv()).await {
+                            Ok(cmd) => cmd,
+                            Err(_) => {
+                                if let Some(image) = pending_state.take() {
+                                    save_state(eeprom, &image).await;
+                                }
+                                if core::mem::take(&mut runtime_dirty) {
+                                    save_override(&mut store, runtime).await;
+                                }
+                                health_stale = true;
+                                continue;
+                            }
+                        }
+                    } else {
+                        receiver.recv().await
+                    };
+                    let Ok(cmd) = cmd else {
+                        break;
+                    };
+                    (cmd, None)
+                };
+                health_stale = !matches!(cmd, PersistCommand::Get(..));
+
+                // Hold uploads back from the live config until commit
+                if upload_open {
+                    if let PersistCommand::SavePreset(index, data, reply_to) = &cmd {
+                        let mut status = body_status(*index, data);
+                        if status == SetStatus::Ok {
+                            status = write_status(store.stage_preset(*index, data).await);
+                        }
+                        if status == SetStatus::Ok {
+                            staged |= 1u128 << (*index & 0x7F);
+                        }
+                        if let Some(to) = reply_to {
+                            send_set_reply(*to, status, &mut usb_sender);
+                        }
+                        continue;
+                    }
+                }
+
+                // Write out pending runtime saves before a reset
+                if matches!(
+                    cmd,
+                    PersistCommand::Reboot
+                        | PersistCommand::Bootloader
+                        | PersistCommand::SelectBank(..)
+                ) {
+                    if let Some(image) = pending_state.take() {
+                        save_state(eeprom, &image).await;
+                    }
+                    if core::mem::take(&mut runtime_dirty) {
+                        save_override(&mut store, runtime).await;
+                    }
+                }
+
+                match cmd {
+                    PersistCommand::Upload(UploadCommand::Begin, reply_to) => {
+                        let mut result = Ok(());
+                        if upload_open {
+                            // Restarted upload: drop what the last one staged
+                            result = discard_upload(&mut store, bank, staged, staged_banks).await;
+                        }
+                        if result.is_ok() {
+                            result = store.set_upload_state(UploadState::Open).await;
+                        }
+                        let status = write_status(result);
+                        if status == SetStatus::Ok {
+                            info!("upload started");
+                            upload_open = true;
+                            staged = 0;
+                            staged_banks = 0;
+                            restore = None;
+                        }
+                        send_set_reply(reply_to, status, &mut usb_sender);
+                    }
+                    PersistCommand::Upload(UploadCommand::Commit, reply_to) => {
+                        if !upload_open {
+                            send_set_reply(reply_to, SetStatus::FormatError, &mut usb_sender);
+                        } else if let Err(err) =
+                            store.set_upload_state(UploadState::Committing).await
+                        {
+                            // Still open: the host can retry or abort
+                            send_set_reply(reply_to, write_status(Err(err)), &mut usb_sender);
+                        } else {
+                            info!("upload committed");
+                            upload_open = false;
+                            replay = staged;
+                            staged = 0;
+                            committing = true;
+                            commit_reply = Some(reply_to);
+                            commit_status = SetStatus::Ok;
+                        }
+                    }
+                    PersistCommand::Upload(UploadCommand::Abort, reply_to) => {
+                        let mut status = SetStatus::Ok;
+                        if upload_open {
+                            info!("upload aborted");
+                            status = write_status(
+                                discard_upload(&mut store, bank, staged, staged_banks).await,
+                            );
+                            upload_open = false;
+                            staged = 0;
+                            staged_banks = 0;
+                            restore = None;
+                        }
+                        send_set_reply(reply_to, status, &mut usb_sender);
+                    }
+                    PersistCommand::Get(resource, to) => {
+                        use midi_controller::property_exchange::{build_get_reply, PeStatus};
+
+                        let mut buf = [0u8; pedalboard_midi::MAX_PRESET_SIZE + 1];
+                        let body = stored_body(&mut store, resource, &mut buf).await;
+                        let status = if body.is_empty() {
+                            PeStatus::NotFound
+                        } else {
+                            PeStatus::Ok
+                        };
+                        let reply = build_get_reply(
+                            pedalboard_midi::pe_sysex::DEVICE_MUID,
+                            to.muid,
+                            to.request_id,
+                            resource,
+                            status,
+                            body,
+                        );
+                        stream_sysex(&reply, &mut usb_sender).await;
+                    }
+                    PersistCommand::Backup(to) => {
+                        // Runtime state as it stands on the EEPROM
+                        if let Some(image) = pending_state.take() {
+                            save_state(eeprom, &image).await;
+                        }
+                        let state = eeprom.load().ok().flatten();
+                        let state: &[u8] = state.as_ref().map_or(&[], |s| &s[..]);
+                        // Global config live, with runtime tempo and clock
+                        let mut global = [0u8; 64];
+                        let global_len = ctx
+                            .shared
+                            .global_config
+                            .lock(|gc| postcard::to_slice(gc, &mut global).map_or(0, |s| s.len()));
+                        let global = &global[..global_len];
+
+                        let mut buf = [0u8; pedalboard_midi::MAX_PRESET_SIZE + 1];
+                        let (mut entries, mut bodies) = (0u16, 0usize);
+                        for entry in backup_entries() {
+                            let body =
+                                backup_body(&mut store, entry, bank, global, state, &mut buf).await;
+                            bodies += body.len();
+                            entries += 1;
+                        }
+                        let size = pedalboard_midi::backup::image_size(entries, bodies);
+                        let chunks = pedalboard_midi::backup::chunk_count(size);
+
+                        let mut image = pedalboard_midi::backup::Writer::new(entries);
+                        let mut sent = 0u16;
+                        for entry in backup_entries() {
+                            let body =
+                                backup_body(&mut store, entry, bank, global, state, &mut buf).await;
+                            let header =
+                                pedalboard_midi::backup::entry_header(entry.0, entry.1, body.len());
+                            for mut data in [&header[..], body] {
+                                while let Some(chunk) = image.write(&mut data) {
+                                    sent += 1;
+                                    let reply = pedalboard_midi::pe_sysex::build_get_chunk(
+                                        to,
+                                        pedalboard_midi::persist::BACKUP_RESOURCE,
+                                        chunks,
+                                        sent,
+                                        &chunk,
+                                    );
+                                    stream_sysex(&reply, &mut usb_sender).await;
+                                }
+                            }
+                        }
+                        for chunk in image.finish() {
+                            sent += 1;
+                            let reply = pedalboard_midi::pe_sysex::build_get_chunk(
+                                to,
+                                pedalboard_midi::persist::BACKUP_RESOURCE,
+                                chunks,
+                                sent,
+                                &chunk,
+                            );
+                            stream_sysex(&reply, &mut usb_sender).await;
+                        }
+                        info!("backup sent: {} entries, {} bytes", entries, size);
+                    }
+                    PersistCommand::Restore(chunk, chunks, data, reply_to) => {
+                        let mut result = Ok(());
+                        if chunk == 1 {
+                            // A restore is an upload of every backed-up resource
+                            if upload_open {
+                                result =
+                                    discard_upload(&mut store, bank, staged, staged_banks).await;
+                            }
+                            if result.is_ok() {
+                                result = store.set_upload_state(UploadState::Open).await;
+                            }
+                            if result.is_ok() {
+                                info!("restore started");
+                                upload_open = true;
+                                staged = 0;
+                                staged_banks = 0;
+                                restore = Some(pedalboard_midi::backup::Reader::new(chunks));
+                            }
+                        }
+                        let mut status = write_status(result);
+
+                        // Stage entries as they complete
+                        if status == SetStatus::Ok {
+                            status = match restore.as_mut() {
+                                Some(reader) if reader.start_chunk(chunk, chunks).is_ok() => {
+                                    let mut status = SetStatus::Ok;
+                                    for &byte in data.iter() {
+                                        let entry = match reader.push(byte) {
+                                            Ok(Some(entry)) => entry,
+                                            Ok(None) => continue,
+                                            Err(_) => {
+                                                status = SetStatus::FormatError;
+                                                break;
+                                            }
+                                        };
+                                        let (entry_bank, resource, body) = entry;
+                                        if !backup_entries().any(|e| e == (entry_bank, resource)) {
+                                            status = SetStatus::FormatError;
+                                            break;
+                                        }
+                                        // Other banks are staged in their own
+                                        // area and applied straight to flash
+                                        let target = if is_bank_resource(resource) {
+                                            entry_bank
+                                        } else {
+                                            bank
+                                        };
+                                        status = body_status(resource, body);
+                                        if status == SetStatus::Ok {
+                                            store.set_bank(target);
+                                            status = write_status(
+                                                store.stage_preset(resource, body).await,
+                                            );
+                                            store.set_bank(bank);
+                                        }
+                                        if status != SetStatus::Ok {
+                                            break;
+                                        }
+                                        if target == bank {
+                                            staged |= 1u128 << (resource & 0x7F);
+                                        } else {
+                                            staged_banks |= 1 << target;
+                                        }
+                                    }
+                                    if status == SetStatus::Ok
+                                        && chunk == chunks
+                                        && reader.finish().is_err()
+                                    {
+                                        status = SetStatus::FormatError;
+                                    }
+                                    status
+                                }
+                                _ => SetStatus::FormatError,
+                            };
+                        }
+
+                        if status != SetStatus::Ok {
+                            // Nothing of a failed restore is applied
+                            warn!("restore chunk {}/{} rejected", chunk, chunks);
+                            if restore.take().is_some() {
+                                discard_upload(&mut store, bank, staged, staged_banks)
+                                    .await
+                                    .ok();
+                                upload_open = false;
+                                staged = 0;
+                                staged_banks = 0;
+                            }
+                            send_set_reply(reply_to, status, &mut usb_sender);
+                        } else if chunk < chunks {
+                            send_set_reply(reply_to, SetStatus::Ok, &mut usb_sender);
+                        } else if let Err(err) =
+                            store.set_upload_state(UploadState::Committing).await
+                        {
+                            warn!("restore commit failed");
+                            discard_upload(&mut store, bank, staged, staged_banks)
+                                .await
+                                .ok();
+                            restore = None;
+                            upload_open = false;
+                            staged = 0;
+                            staged_banks = 0;
+                            send_set_reply(reply_to, write_status(Err(err)), &mut usb_sender);
+                        } else {
+                            // Applied like an upload commit; replies and
+                            // reboots once done
+                            info!("restore committed");
+                            restore = None;
+                            upload_open = false;
+                            replay = staged;
+                            staged = 0;
+                            committing = true;
+                            commit_reply = Some(reply_to);
+                            commit_status = SetStatus::Ok;
+                            reboot_after_commit = true;
+                        }
+                    }
+                    PersistCommand::SavePreset(preset_index, data, reply_to) => {
+                        use pedalboard_midi::resource::Resource;
+
+                        // Each branch validates, writes to flash, and only
+                        // then applies the value
+                        let status = match Resource::of(preset_index) {
+                            Some(Resource::Settings) => {
+                                match store_resource::<pedalboard_midi::settings::Settings>(
+                                    &mut store,
+                                    preset_index,
+                                    &data,
+                                )
+                                .await
+                                {
+                                    Ok(settings) => {
+                                        info!("device settings applied and saved");
+                                        ctx.shared.settings.lock(|s| *s = settings);
+                                        SetStatus::Ok
+                                    }
+                                    Err(status) => status,
+                                }
+                            }
+                            Some(Resource::PresetExt(idx)) => {
+                                match store_resource::<pedalboard_midi::preset_ext::PresetExt>(
+                                    &mut store,
+                                    preset_index,
+                                    &data,
+                                )
+                                .await
+                                {
+                                    Ok(ext) => {
+                                        info!("preset {} extension set", idx);
+                                        preset_ext_sender.send((idx as u8, ext)).await.ok();
+                                        SetStatus::Ok
+                                    }
+                                    Err(status) => status,
+                                }
+                            }
+                            Some(Resource::Profile(slot)) => {
+                                match store_resource::<pedalboard_midi::profile::Profile>(
+                                    &mut store,
+                                    preset_index,
+                                    &data,
+                                )
+                                .await
+                                {
+                                    Ok(profile) => {
+                                        info!(
+                                            "profile {} set: \"{}\"",
+                                            slot,
+                                            profile.name.as_str()
+                                        );
+                                        ctx.shared.profiles.lock(|p| p.set(slot, profile));
+                                        SetStatus::Ok
+                                    }
+                                    Err(status) => status,
+                                }
+                            }
+                            Some(Resource::Song(slot)) => {
+                                match store_resource::<pedalboard_midi::setlist::Song>(
+                                    &mut store,
+                                    preset_index,
+                                    &data,
+                                )
+                                .await
+                                {
+                                    Ok(song) => {
+                                        info!("song {} set: \"{}\"", slot, song.name.as_str());
+                                        ctx.shared.setlist.lock(|s| s.set(slot, song));
+                                        SetStatus::Ok
+                                    }
+                                    Err(status) => status,
+                                }
+                            }
+                            Some(Resource::GlobalConfig) => {
+                                match store_resource::<midi_controller::config::GlobalConfig>(
+                                    &mut store,
+                                    preset_index,
+                                    &data,
+                                )
+                                .await
+                                {
+                                    Ok(gc) => {
+                                        info!("global config applied and saved");
+                                        ctx.shared.global_config.lock(|g| *g = gc.clone());
+                                        ctx.shared.pe_config.lock(|cfg| cfg.global = gc);
+                                        // Uploaded tempo and clock replace the
+                                        // runtime override
+                                        runtime_dirty = false;
+                                        if core::mem::take(&mut runtime) != Default::default() {
+                                            save_override(&mut store, runtime).await;
+                                        }
+                                        SetStatus::Ok
+                                    }
+                                    Err(status) => status,
+                                }
+                            }
+                            Some(Resource::Banks) => {
+                                match store_resource::<pedalboard_midi::bank::Banks>(
+                                    &mut store,
+                                    preset_index,
+                                    &data,
+                                )
+                                .await
+                                {
+                                    Ok(banks) => {
+                                        info!("bank names set");
+                                        ctx.shared.banks.lock(|b| b.names = banks.names);
+                                        SetStatus::Ok
+                                    }
+                                    Err(status) => status,
+                                }
+                            }
+                            // Runtime state image — written to the EEPROM and
+                            // picked up at the next boot; empty body clears it
+                            Some(Resource::State) => {
+                                match pedalboard_midi::resource::decode_state(&data) {
+                                    Some(image) => {
+                                        pending_state = None;
+                                        match eeprom.save(&image, write_cycle).await {
+                                            Ok(()) => SetStatus::Ok,
+                                            Err(_) => SetStatus::FlashError,
+                                        }
+                                    }
+                                    None => {
+                                        warn!("runtime state image invalid");
+                                        SetStatus::FormatError
+                                    }
+                                }
+                            }
+                            Some(Resource::Preset(_)) if data.is_empty() => {
+                                // Empty body = delete preset from flash and RAM.
+                                // Only write to flash if the slot was actually occupied
+                                let idx = preset_index as usize;
+                                let was_occupied = ctx.shared.pe_config.lock(|cfg| {
+                                    cfg.presets.get(idx).is_some_and(|p| !p.name.is_empty())
+                                });
+                                if was_occupied {
+                                    let status =
+                                        write_status(store.save_preset(preset_index, &[]).await);
+                                    if status == SetStatus::Ok {
+                                        info!("preset {} deleted", preset_index);
+                                        ctx.shared.pe_config.lock(|cfg| {
+                                            cfg.presets[idx] =
+                                                midi_controller::config::Preset::default()
+                                        });
+                                    }
+                                    status
+                                } else {
+                                    SetStatus::Ok
+                                }
+                            }
+                            Some(Resource::Preset(_)) => {
+                                match postcard::from_bytes::<midi_controller::config::Preset>(&data)
+                                {
+                                    Ok(preset) => {
+                                        let mut buf = [0u8; pedalboard_midi::MAX_PRESET_SIZE + 1];
+                                        let blob = versioned(preset_index, &data, &mut buf);
+                                        let status = write_status(
+                                            store.save_preset(preset_index, blob).await,
+                                        );
+                                        if status == SetStatus::Ok {
+                                            info!(
+                                                "preset {} loaded: \"{}\"",
+                                                preset_index,
+                                                preset.name.as_str()
+                                            );
+                                            // Write initial state from preset defaults to EEPROM
+                                            let image = ctx.shared.pe_config.lock(|cfg| {
+                                                let idx = preset_index as usize;
+                                                // Extend presets vec if needed
+                                                while cfg.presets.len() <= idx {
+                                                    cfg.presets.push(Default::default()).ok();
+                                                }
+                                                cfg.presets[idx] = preset;
+                                                defaults_image(cfg)
+                                            });
+                                            // Supersedes any pending state save
+                                            pending_state = None;
+                                            eeprom.save(&image, write_cycle).await.ok();
+                                        }
+                                        status
+                                    }
+                                    Err(_) => {
+                                        warn!("preset {} deserialize failed", preset_index);
+                                        SetStatus::FormatError
+                                    }
+                                }
+                            }
+                            None => {
+                                warn!("resource {} out of range", preset_index);
+                                SetStatus::OutOfRange
+                            }
+                        };
+
+                        match reply_to {
+                            Some(to) => send_set_reply(to, status, &mut usb_sender),
+                            // Replayed from staging: the commit reply reports
+                            // the first failure
+                            None if commit_status == SetStatus::Ok => commit_status = status,
+                            None => {}
+                        }
+                    }
+                    PersistCommand::SelectBank(bank, reply_to) => {
+                        let active = ctx.shared.banks.lock(|b| b.active);
+                        let status = if bank as usize >= pedalboard_midi::bank::MAX_BANKS {
+                            SetStatus::OutOfRange
+                        } else if upload_open {
+                            // Staged blobs belong to the active bank
+                            SetStatus::FormatError
+                        } else if bank == active {
+                            SetStatus::Ok
+                        } else {
+                            switch_bank(&mut store, eeprom, active, bank).await
+                        };
+                        if let Some(to) = reply_to {
+                            send_set_reply(to, status, &mut usb_sender);
+                        }
+                        if status == SetStatus::Ok && bank != active {
+                            info!("switched to bank {}, rebooting", bank);
+                            status_sender.try_send(SystemStatus::Rebooting).ok();
+                            Mono::delay(1000.millis()).await;
+                            cortex_m::peripheral::SCB::sys_reset();
+                        }
+                    }
+                    PersistCommand::SaveDefaults(idx, defaults) => {
+                        let preset = ctx.shared.pe_config.lock(|cfg| {
+                            cfg.presets.get(idx as usize).cloned().map(|mut p| {
+                                p.defaults = defaults;
+                                p
+                            })
+                        });
+                        let mut buf = [0u8; pedalboard_midi::MAX_PRESET_SIZE + 1];
+                        if let Some(preset) = preset {
+                            let version = pedalboard_midi::FLASH_FORMAT_VERSION;
+                            let saved = match migrate::encode(&preset, version, &mut buf) {
+                                Some(blob) => store.save_preset(idx, blob).await.is_ok(),
+                                None => false,
+                            };
+                            if saved {
+                                info!("preset {} defaults saved", idx);
+                                ctx.shared.pe_config.lock(|cfg| {
+                                    if let Some(p) = cfg.presets.get_mut(idx as usize) {
+                                        p.defaults = preset.defaults;
+                                    }
+                                });
+                            } else {
+                                warn!("preset {} defaults write failed", idx);
+                            }
+                        }
+                    }
+                    PersistCommand::SaveActivePreset(idx) => {
+                        store
+                            .save_value(pedalboard_midi::storage::ACTIVE_PRESET_KEY, idx as u16)
+                            .await
+                            .ok();
+                    }
+                    PersistCommand::SaveActiveSong(slot) => {
+                        store.save_active_song(slot).await.ok();
+                    }
+                    PersistCommand::SaveTempo(bpm) => {
+                        runtime.bpm = Some(bpm);
+                        runtime_dirty = true;
+                    }
+                    PersistCommand::SaveClockRunning(running) => {
+                        runtime.clock_running = Some(running);
+                        runtime_dirty = true;
+                    }
+                    PersistCommand::SaveState(data) => {
+                        if let Ok(image) =
+                            <[u8; pedalboard_midi::eeprom::EEPROM_SIZE]>::try_from(data.as_slice())
+                        {
+                            pending_state = Some(image);
+                        }
                     }
                     PersistCommand::EraseAll => {
                         status_sender.try_send(SystemStatus::FactoryReset).ok();
                         Mono::delay(200.millis()).await;
-                        store.erase_all().await;
-                        // Clear EEPROM runtime state
+                        store.erase_all().await.ok();
+                        // Clear EEPROM runtime state, back on bank 0
+                        pending_state = None;
                         let buf = midi_controller::state::DefaultPresetStateStore::cleared_eeprom();
-                        use embedded_hal::i2c::I2c;
-                        for page in 0..16 {
-                            let offset = page * 8;
-                            let mut wbuf = [0u8; 9];
-                            wbuf[0] = offset as u8;
-                            wbuf[1..9].copy_from_slice(&buf[offset..offset + 8]);
-                            eeprom.write(0x50u8, &wbuf).ok();
-                            Mono::delay(5.millis()).await;
-                        }
+                        eeprom.set_bank(0);
+                        eeprom.save(&buf, write_cycle).await.ok();
                         info!("factory reset: storage + presets + eeprom erased, rebooting");
                         Mono::delay(1000.millis()).await;
                         cortex_m::peripheral::SCB::sys_reset();
@@ -1359,6 +2403,11 @@ mod app {
                         rp2040_hal::rom_data::reset_to_usb_boot(0, 0);
                     }
                 }
+
+                // Applied from the staging area: drop the staged copy
+                if let Some(index) = replayed {
+                    store.remove_staged(index).await.ok();
+                }
             }
         } else {
             warn!("flash config store init failed, persistence disabled");
@@ -1427,7 +2476,7 @@ mod app {
         }
     }
 
-    #[task(local = [displays], shared = [active_preset, pe_config, button_active])]
+    #[task(local = [displays], shared = [active_preset, pe_config, button_active, setlist, active_song, preview_preset, looper_state, storage_health])]
     async fn display_out(
         mut ctx: display_out::Context,
         mut receiver: Receiver<'static, [u8; 3], DISPLAY_LOG_CAPACITY>,
@@ -1454,6 +2503,9 @@ mod app {
         });
 
         let mut current_preset: u8 = 0;
+        let mut current_song: Option<u8> = None;
+        let mut current_preview: Option<u8> = None;
+        let mut current_looper: Option<pedalboard_midi::looper::LooperState> = None;
 
         // If no presets have names, flash likely has stale/missing data — show hint
         let has_presets = presets.iter().any(|p| !p.name.is_empty());
@@ -1498,6 +2550,16 @@ mod app {
                     ConfigDisplayEvent::Info(info) => {
                         displays.draw_config_info(info);
                     }
+                    ConfigDisplayEvent::Storage => {
+                        let health = ctx.shared.storage_health.lock(|h| h.clone());
+                        displays.draw_config_storage(&health);
+                    }
+                    ConfigDisplayEvent::Snapshot => {
+                        displays.draw_config_snapshot();
+                    }
+                    ConfigDisplayEvent::Bank { bank, name, active } => {
+                        displays.draw_config_bank(*bank, name.as_str(), *active);
+                    }
                     ConfigDisplayEvent::ButtonPress { button, detail } => {
                         displays.draw_config_button_press(button, detail.as_str());
                     }
@@ -1606,8 +2668,13 @@ mod app {
             });
             let any_changed = buttons_changed.iter().any(|&c| c);
 
+            // Song change (setlist mode) replaces the preset overlay.
+            let new_song = ctx.shared.active_song.lock(|s| *s);
+            let song_changed = new_song != current_song;
+            let preset_switched = new_preset != current_preset;
+
             // Preset switch takes priority — check first.
-            if new_preset != current_preset {
+            if preset_switched {
                 let forward =
                     new_preset > current_preset || (current_preset > 0 && new_preset == 0);
                 current_preset = new_preset;
@@ -1616,7 +2683,7 @@ mod app {
                 ctx.shared.button_active.lock(|ba| {
                     presets[idx].button_active = *ba;
                 });
-                if !debug_mode {
+                if !debug_mode && !song_changed {
                     debug!("DISP: preset_switch overlay_left + perf_right");
                     displays.draw_preset_overlay_left(
                         current_preset + 1,
@@ -1639,6 +2706,77 @@ mod app {
                 displays.draw_performance_partial(&presets[idx], buttons_changed);
             }
 
+            // Bank preview: candidate name + labels, held until confirm or timeout.
+            let new_preview = ctx.shared.preview_preset.lock(|p| *p);
+            if new_preview != current_preview {
+                current_preview = new_preview;
+                if !debug_mode {
+                    match new_preview {
+                        Some(target) => {
+                            debug!("DISP: preview overlay");
+                            let pidx = (target as usize) % presets.len();
+                            displays
+                                .draw_preview_overlay_left(target + 1, presets[pidx].name.as_str());
+                            displays.draw_performance_right(&presets[pidx]);
+                        }
+                        None if !preset_switched => {
+                            // Preview ended without a switch
+                            displays.draw_performance(&presets[idx]);
+                        }
+                        None => {}
+                    }
+                }
+            }
+            if current_preview.is_some() {
+                show_overlay = true;
+            }
+
+            if song_changed {
+                let forward = match (current_song, new_song) {
+                    (Some(old), Some(new)) => new > old,
+                    _ => true,
+                };
+                current_song = new_song;
+                let song = new_song.and_then(|slot| {
+                    ctx.shared.setlist.lock(|setlist| {
+                        let slot = slot as usize;
+                        setlist.get(slot).map(|song| {
+                            (
+                                setlist.position(slot),
+                                song.name.clone(),
+                                setlist.up_next(slot).map(|next| next.name.clone()),
+                            )
+                        })
+                    })
+                });
+                if let Some((position, name, up_next)) = song {
+                    if !debug_mode {
+                        debug!("DISP: song overlay");
+                        displays.draw_song_overlay(
+                            position,
+                            name.as_str(),
+                            up_next.as_ref().map(|n| n.as_str()),
+                            forward,
+                        );
+                        overlay_ticks = OVERLAY_DURATION;
+                        show_overlay = true;
+                    }
+                }
+            }
+
+            // Looper state changes show a labelled overlay; a preset switch
+            // just resyncs (its own overlay has priority).
+            let new_looper = ctx.shared.looper_state.lock(|l| *l);
+            if new_looper != current_looper {
+                current_looper = new_looper;
+                if let Some(state) = new_looper.filter(|_| !preset_switched && !debug_mode) {
+                    debug!("DISP: looper overlay");
+                    displays.draw_looper_overlay_left(state.label());
+                    overlay_ticks = OVERLAY_DURATION;
+                    show_overlay = true;
+                }
+            }
+
             // PE display events (direct from action layer, no MIDI round-trip)
             while let Ok(evt) = event_receiver.try_recv() {
                 use crate::hmi::display::DisplayLocation;
@@ -1848,20 +2986,29 @@ mod app {
         }
     }
 
-    #[task(priority = 2, shared = [global_config])]
+    #[task(priority = 2, shared = [global_config, lfo, settings])]
     async fn midi_clock(
         mut ctx: midi_clock::Context,
         mut sender: Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
         mut din_sender: Sender<'static, [u8; 3], DIN_THRU_CAPACITY>,
         mut led_sender: Sender<'static, LedEvent, LED_CAPACITY>,
+        mut clock_tick_sender: Sender<'static, (), CLOCK_TICK_CAPACITY>,
+        mut song_position_receiver: Receiver<'static, u16, SONG_POSITION_CAPACITY>,
     ) {
         use midi_controller::clock::MidiClock;
+        use midi_controller::routing::MidiPort;
+        use pedalboard_midi::clock_out::ClockShaper;
 
         let mut clock = MidiClock::new();
+        let mut shaper = ClockShaper::new();
+        // Song position that woke the idle clock
+        let mut located: Option<u16> = None;
 
         loop {
+            // A running LFO keeps the clock timeline going even with clock output off
+            let lfo_running = ctx.shared.lfo.lock(|lfo| lfo.is_running());
             let (clock_enabled, interval_us) = ctx.shared.global_config.lock(|gc| {
-                let interval = if gc.midi_clock {
+                let interval = if gc.midi_clock || lfo_running {
                     Some(gc.tick_interval_us())
                 } else {
                     None
@@ -1869,51 +3016,630 @@ mod app {
                 (gc.midi_clock, interval)
             });
 
+            let ports = ctx.shared.settings.lock(|s| s.clock);
+            let tick_start = Mono::now().ticks() as u32;
+
+            // Song Position Pointer action: locate first, then Continue from
+            // there instead of Start
+            let position = located
+                .take()
+                .or_else(|| song_position_receiver.try_recv().ok());
+            if let Some(position) = position {
+                let spp = pedalboard_midi::transport::song_position(position);
+                let dest = shaper.transport(&ports, MidiPort::all(), spp[0]);
+                send_clock_bytes(&spp, dest, &mut sender, &mut din_sender);
+            }
+
             // Update clock state — sends Start/Stop on transitions.
-            if let Some(output) = clock.update_config(clock_enabled) {
-                dispatch_clock_messages(&output, &mut sender, &mut din_sender);
+            if let Some(mut output) = clock.update_config(clock_enabled) {
+                if position.is_some() {
+                    for msg in output.messages.iter_mut() {
+                        if msg.data[0] == 0xFA {
+                            msg.data[0] = 0xFB;
+                        }
+                    }
+                }
+                dispatch_clock_messages(
+                    &output,
+                    &mut shaper,
+                    &ports,
+                    interval_us.unwrap_or(0),
+                    &mut sender,
+                    &mut din_sender,
+                );
+            } else if position.is_some() {
+                // Already running: continue from the new position
+                let dest = shaper.transport(&ports, MidiPort::all(), 0xFB);
+                send_clock_bytes(&[0xFB], dest, &mut sender, &mut din_sender);
             }
 
             match interval_us {
                 Some(us) => {
                     // Tick the clock — sends 0xF8 if running.
                     if let Some(output) = clock.tick() {
-                        dispatch_clock_messages(&output, &mut sender, &mut din_sender);
+                        dispatch_clock_messages(
+                            &output,
+                            &mut shaper,
+                            &ports,
+                            us,
+                            &mut sender,
+                            &mut din_sender,
+                        );
                     }
+                    // Looper bar sync and the LFO count these ticks unless
+                    // external clock drives
+                    clock_tick_sender.try_send(()).ok();
                     // Sync LED animations to BPM
-                    led_sender.try_send(LedEvent::BpmTick).ok();
-                    Mono::delay((us as u64).micros()).await;
+                    if clock_enabled {
+                        led_sender.try_send(LedEvent::BpmTick).ok();
+                    }
+                    // Wait for the next tick, sending shaped ticks due in between
+                    let next_tick = tick_start.wrapping_add(us);
+                    loop {
+                        let now = Mono::now().ticks() as u32;
+                        send_due_clock_ticks(&mut shaper, now, &mut sender, &mut din_sender);
+                        let left = next_tick.wrapping_sub(now);
+                        if left > us {
+                            break; // already late
+                        }
+                        match shaper.next_due_in(now) {
+                            Some(wait) if wait < left => Mono::delay((wait as u64).micros()).await,
+                            _ => {
+                                Mono::delay((left as u64).micros()).await;
+                                break;
+                            }
+                        }
+                    }
                 }
                 None => {
-                    // Clock disabled, check again in 100ms
-                    Mono::delay(100.millis()).await;
+                    // Clock disabled, check again in 100ms or once a song
+                    // position starts it
+                    let wait = Mono::timeout_after(100.millis(), song_position_receiver.recv());
+                    if let Ok(Ok(position)) = wait.await {
+                        located = Some(position);
+                    }
                 }
             }
         }
     }
 
-    /// Dispatch clock messages (F8/FA/FC/FB) to DIN and USB based on port flags.
+    /// Send an LFO value (if it changed) and show the LFO as a fill on the ring
+    /// of the button that started it (`ring` = button index and fill, if the
+    /// fill changed).
+    fn dispatch_lfo(
+        cc: Option<[u8; 3]>,
+        ring: Option<(u8, u8)>,
+        din_enabled: bool,
+        sender: &mut Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
+        uart_midi_out: &mut MidiOut,
+        led_sender: &mut Sender<'static, LedEvent, LED_CAPACITY>,
+    ) {
+        use pedalboard_midi::ledring::{Modifier, Renderer, Rgb, RingAnimation};
+        use pedalboard_midi::leds::LedRings;
+
+        if let Some(cc) = cc {
+            if din_enabled {
+                if let Ok(mm) = MidiMessage::try_parse_slice(&cc) {
+                    uart_midi_out.write(&mm).ok();
+                }
+            }
+            if let Ok(packet) = UsbMidiEventPacket::try_from_payload_bytes(CableNumber::Cable0, &cc)
+            {
+                sender.try_send(packet).ok();
+            }
+        }
+        if let Some((button, fill)) = ring {
+            let ring = match button {
+                0 => LedRings::A,
+                1 => LedRings::B,
+                2 => LedRings::C,
+                3 => LedRings::D,
+                4 => LedRings::E,
+                _ => LedRings::F,
+            };
+            let anim = RingAnimation {
+                renderer: Renderer::Fill(Rgb::new(0, 160, 255), fill),
+                modifier: Modifier::Solid,
+            };
+            led_sender.try_send(LedEvent::SetRing(ring, anim)).ok();
+        }
+    }
+
+    /// Dispatch clock messages (F8/FA/FC/FB) to DIN and USB based on port flags
+    /// and the per-port clock settings. Ticks are queued in `shaper` and sent
+    /// once due.
     fn dispatch_clock_messages(
         output: &midi_controller::clock::ClockOutput,
+        shaper: &mut pedalboard_midi::clock_out::ClockShaper,
+        ports: &[pedalboard_midi::clock_out::PortClock; pedalboard_midi::clock_out::CLOCK_PORTS],
+        interval_us: u32,
         sender: &mut Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
         din_sender: &mut Sender<'static, [u8; 3], DIN_THRU_CAPACITY>,
     ) {
-        use midi_controller::routing::MidiPort;
-
+        let now_us = Mono::now().ticks() as u32;
         for msg in &output.messages {
             let bytes = msg.bytes();
-            if msg.dest.contains(MidiPort::DIN) {
-                let mut raw = [0u8; 3];
-                let len = bytes.len().min(3);
-                raw[..len].copy_from_slice(&bytes[..len]);
-                din_sender.try_send(raw).ok();
-            }
-            if msg.dest.contains(MidiPort::USB) {
-                if let Ok(packet) =
-                    UsbMidiEventPacket::try_from_payload_bytes(CableNumber::Cable0, bytes)
-                {
-                    sender.try_send(packet).ok();
+            let dest = match bytes.first() {
+                Some(0xF8) => {
+                    shaper.tick(ports, msg.dest, now_us, interval_us);
+                    continue;
+                }
+                Some(&status) => shaper.transport(ports, msg.dest, status),
+                None => continue,
+            };
+            send_clock_bytes(bytes, dest, sender, din_sender);
+        }
+        send_due_clock_ticks(shaper, now_us, sender, din_sender);
+    }
+
+    /// Send a SysEx message to USB, three bytes per packet.
+    fn send_sysex(
+        bytes: &[u8],
+        sender: &mut Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
+    ) {
+        for chunk in bytes.chunks(3) {
+            if let Ok(p) = UsbMidiEventPacket::try_from_payload_bytes(CableNumber::Cable0, chunk) {
+                sender.try_send(p).ok();
+            }
+        }
+    }
+
+    /// Serialize a PE Get reply body into `buf`; `None` (not found) if
+    /// `value` is.
+    fn get_body<T: serde::Serialize>(value: Option<&T>, buf: &mut [u8]) -> Option<usize> {
+        value
+            .and_then(|value| postcard::to_slice(value, buf).ok())
+            .map(|body| body.len())
+    }
+
+    /// Send a SysEx message to USB, waiting for room in the queue.
+    async fn stream_sysex(
+        bytes: &[u8],
+        sender: &mut Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
+    ) {
+        for chunk in bytes.chunks(3) {
+            if let Ok(p) = UsbMidiEventPacket::try_from_payload_bytes(CableNumber::Cable0, chunk) {
+                sender.send(p).await.ok();
+            }
+        }
+    }
+
+    /// PE resources a backup covers. Missing ones go in as empty entries, so
+    /// a restore clears them on the target.
+    fn backup_resources() -> impl Iterator<Item = u8> {
+        use pedalboard_midi::{bank, persist, preset_ext, profile, setlist, settings};
+
+        let presets = 0..midi_controller::config::MAX_PRESETS as u8;
+        let exts = preset_ext::PRESET_EXT_RESOURCE_BASE..;
+        let songs = setlist::SONG_RESOURCE_BASE..;
+        let profiles = profile::PROFILE_RESOURCE_BASE..;
+        presets
+            .chain(exts.take(preset_ext::MAX_PRESET_EXTS))
+            .chain(songs.take(setlist::MAX_SONGS))
+            .chain([settings::SETTINGS_RESOURCE])
+            .chain(profiles.take(profile::MAX_PROFILES))
+            .chain([
+                bank::BANKS_RESOURCE,
+                midi_controller::config::GLOBAL_CONFIG_RESOURCE,
+                persist::STATE_RESOURCE,
+            ])
+    }
+
+    /// Whether `resource` is kept per bank: presets, extensions, songs and
+    /// the runtime state.
+    fn is_bank_resource(resource: u8) -> bool {
+        resource < pedalboard_midi::storage::BANK_RESOURCES
+            || resource == pedalboard_midi::persist::STATE_RESOURCE
+    }
+
+    /// Backup entries (bank, resource): the per-bank resources of every bank,
+    /// then the shared ones under bank 0.
+    fn backup_entries() -> impl Iterator<Item = (u8, u8)> {
+        let banks = 0..pedalboard_midi::bank::MAX_BANKS as u8;
+        let per_bank = banks.flat_map(|bank| {
+            backup_resources()
+                .filter(|&r| is_bank_resource(r))
+                .map(move |r| (bank, r))
+        });
+        per_bank.chain(
+            backup_resources()
+                .filter(|&r| !is_bank_resource(r))
+                .map(|r| (0, r)),
+        )
+    }
+
+    /// Backup body of `resource` in `bank`: the stored blob without its
+    /// format version byte, or the live global config or runtime state
+    /// (`state` for the `active` bank, the saved image for the others).
+    async fn backup_body<'b>(
+        store: &mut pedalboard_midi::storage::ConfigStore<pedalboard_midi::flash::FlashStorage>,
+        (bank, resource): (u8, u8),
+        active: u8,
+        global: &'b [u8],
+        state: &'b [u8],
+        buf: &'b mut [u8; pedalboard_midi::MAX_PRESET_SIZE + 1],
+    ) -> &'b [u8] {
+        if resource == midi_controller::config::GLOBAL_CONFIG_RESOURCE {
+            return global;
+        }
+        if resource == pedalboard_midi::persist::STATE_RESOURCE {
+            if bank == active {
+                return state;
+            }
+            return store.load_bank_state(bank, buf).await.unwrap_or(&[]);
+        }
+        if !is_bank_resource(resource) || bank == active {
+            return stored_body(store, resource, buf).await;
+        }
+        store.set_bank(bank);
+        let body = stored_body(store, resource, buf).await;
+        store.set_bank(active);
+        body
+    }
+
+    /// Move the resources a restore staged for inactive `bank` into place,
+    /// its runtime state included. Reports the first failure.
+    async fn apply_staged_bank(
+        store: &mut pedalboard_midi::storage::ConfigStore<pedalboard_midi::flash::FlashStorage>,
+        bank: u8,
+        active: u8,
+    ) -> pedalboard_midi::persist::SetStatus {
+        use pedalboard_midi::persist::{SetStatus, STATE_RESOURCE};
+
+        let mut status = SetStatus::Ok;
+        store.set_bank(bank);
+        for resource in backup_resources().filter(|&r| is_bank_resource(r)) {
+            let mut body = [0u8; pedalboard_midi::MAX_PRESET_SIZE];
+            let Some(data) = store.load_staged(resource, &mut body).await else {
+                continue;
+            };
+            let mut buf = [0u8; pedalboard_midi::MAX_PRESET_SIZE + 1];
+            let result = if resource == STATE_RESOURCE {
+                store.save_bank_state(bank, data).await
+            } else if data.is_empty() {
+                store.save_preset(resource, &[]).await
+            } else {
+                let blob = versioned(resource, data, &mut buf);
+                store.save_preset(resource, blob).await
+            };
+            if status == SetStatus::Ok {
+                status = write_status(result);
+            }
+            store.remove_staged(resource).await.ok();
+        }
+        store.set_bank(active);
+        status
+    }
+
+    /// Drop what an upload staged: `staged` (bit = resource index) of the
+    /// `active` bank and the per-bank resources of the other banks in
+    /// `banks`, then end the transaction.
+    async fn discard_upload(
+        store: &mut pedalboard_midi::storage::ConfigStore<pedalboard_midi::flash::FlashStorage>,
+        active: u8,
+        staged: u128,
+        banks: u8,
+    ) -> Result<(), pedalboard_midi::storage::StorageError> {
+        let others = (0..pedalboard_midi::bank::MAX_BANKS as u8)
+            .filter(|&b| b != active && banks & (1 << b) != 0);
+        for bank in others {
+            store.set_bank(bank);
+            let mut result = Ok(());
+            for resource in backup_resources().filter(|&r| is_bank_resource(r)) {
+                result = store.remove_staged(resource).await;
+                if result.is_err() {
+                    break;
+                }
+            }
+            store.set_bank(active);
+            result?;
+        }
+        store.discard_staged(staged).await
+    }
+
+    /// Stored blob of `resource` without its format version byte; empty if
+    /// there is none or the firmware cannot read it.
+    async fn stored_body<'b>(
+        store: &mut pedalboard_midi::storage::ConfigStore<pedalboard_midi::flash::FlashStorage>,
+        resource: u8,
+        buf: &'b mut [u8; pedalboard_midi::MAX_PRESET_SIZE + 1],
+    ) -> &'b [u8] {
+        let expected = format_version(resource);
+        match store.load_preset(resource, buf).await {
+            Some([version, body @ ..]) if *version == expected => body,
+            _ => &[],
+        }
+    }
+
+    /// Format version byte `resource` is stored with.
+    fn format_version(resource: u8) -> u8 {
+        use pedalboard_midi::resource::Resource;
+
+        Resource::of(resource).map_or(pedalboard_midi::FLASH_FORMAT_VERSION, |r| {
+            r.format_version()
+        })
+    }
+
+    /// Wait out the EEPROM's self-timed write cycle.
+    fn write_cycle() -> impl core::future::Future<Output = ()> {
+        Mono::delay(pedalboard_midi::eeprom::WRITE_CYCLE_MS.millis())
+    }
+
+    /// Write a runtime state image to the EEPROM.
+    async fn save_state(
+        eeprom: &mut pedalboard_midi::eeprom::Eeprom<AtomicDevice<'static, I2CBus>>,
+        image: &[u8; pedalboard_midi::eeprom::EEPROM_SIZE],
+    ) {
+        if eeprom.save(image, write_cycle).await.is_err() {
+            warn!("EEPROM write failed");
+        }
+    }
+
+    /// Put the runtime state kept for `bank` on the EEPROM (a cleared one if
+    /// it has none), tagged with `bank`.
+    async fn put_bank_state(
+        store: &mut pedalboard_midi::storage::ConfigStore<pedalboard_midi::flash::FlashStorage>,
+        eeprom: &mut pedalboard_midi::eeprom::Eeprom<AtomicDevice<'static, I2CBus>>,
+        bank: u8,
+    ) -> pedalboard_midi::persist::SetStatus {
+        use midi_controller::state::DefaultPresetStateStore;
+        use pedalboard_midi::eeprom::EEPROM_SIZE;
+
+        let mut buf = [0u8; EEPROM_SIZE];
+        let image = store
+            .load_bank_state(bank, &mut buf)
+            .await
+            .and_then(|data| <[u8; EEPROM_SIZE]>::try_from(data).ok())
+            .filter(|image| DefaultPresetStateStore::from_eeprom(image).is_some())
+            .unwrap_or_else(DefaultPresetStateStore::cleared_eeprom);
+        eeprom.set_bank(bank);
+        match eeprom.save(&image, write_cycle).await {
+            Ok(()) => pedalboard_midi::persist::SetStatus::Ok,
+            Err(_) => pedalboard_midi::persist::SetStatus::FlashError,
+        }
+    }
+
+    /// Switch from bank `from` to `to`: keep the runtime state of `from` in
+    /// flash, store `to` as active and put its state on the EEPROM. A switch
+    /// interrupted after `to` is stored is finished at the next boot.
+    async fn switch_bank(
+        store: &mut pedalboard_midi::storage::ConfigStore<pedalboard_midi::flash::FlashStorage>,
+        eeprom: &mut pedalboard_midi::eeprom::Eeprom<AtomicDevice<'static, I2CBus>>,
+        from: u8,
+        to: u8,
+    ) -> pedalboard_midi::persist::SetStatus {
+        use pedalboard_midi::persist::SetStatus;
+
+        let Ok(state) = eeprom.load() else {
+            return SetStatus::FlashError;
+        };
+        let state =
+            state.unwrap_or_else(midi_controller::state::DefaultPresetStateStore::cleared_eeprom);
+        let mut status = write_status(store.save_bank_state(from, &state).await);
+        if status == SetStatus::Ok {
+            status = write_status(
+                store
+                    .save_value(pedalboard_midi::storage::ACTIVE_BANK_KEY, to as u16)
+                    .await,
+            );
+        }
+        if status == SetStatus::Ok {
+            status = put_bank_state(store, eeprom, to).await;
+        }
+        status
+    }
+
+    /// Store the runtime tempo/clock override.
+    async fn save_override(
+        store: &mut pedalboard_midi::storage::ConfigStore<pedalboard_midi::flash::FlashStorage>,
+        runtime: pedalboard_midi::persist::RuntimeOverride,
+    ) {
+        let (bpm, clock) = runtime.to_values();
+        let bpm = store
+            .save_value(pedalboard_midi::storage::TEMPO_KEY, bpm)
+            .await;
+        let clock = store
+            .save_value(pedalboard_midi::storage::CLOCK_RUNNING_KEY, clock)
+            .await;
+        if bpm.and(clock).is_err() {
+            warn!("runtime tempo/clock write failed");
+        }
+    }
+
+    /// Set reply status for the outcome of a flash write.
+    fn write_status(
+        result: Result<(), pedalboard_midi::storage::StorageError>,
+    ) -> pedalboard_midi::persist::SetStatus {
+        use pedalboard_midi::persist::SetStatus;
+        use pedalboard_midi::storage::StorageError;
+
+        match result {
+            Ok(()) => SetStatus::Ok,
+            Err(StorageError::Full) => SetStatus::StorageFull,
+            Err(StorageError::Flash) => SetStatus::FlashError,
+        }
+    }
+
+    /// Set reply status for a body about to be staged: staged bodies are only
+    /// applied on commit, so a bad one must be rejected now.
+    fn body_status(resource: u8, data: &[u8]) -> pedalboard_midi::persist::SetStatus {
+        use pedalboard_midi::persist::SetStatus;
+        use pedalboard_midi::resource::Resource;
+
+        match Resource::of(resource) {
+            Some(r) if r.accepts(data) => SetStatus::Ok,
+            Some(_) => SetStatus::FormatError,
+            None => SetStatus::OutOfRange,
+        }
+    }
+
+    /// `data` behind the format version byte of `resource`, as stored.
+    fn versioned<'b>(
+        resource: u8,
+        data: &[u8],
+        buf: &'b mut [u8; pedalboard_midi::MAX_PRESET_SIZE + 1],
+    ) -> &'b [u8] {
+        let len = data.len().min(pedalboard_midi::MAX_PRESET_SIZE);
+        buf[0] = format_version(resource);
+        buf[1..=len].copy_from_slice(&data[..len]);
+        &buf[..=len]
+    }
+
+    /// Decode a typed Set body and store it in its resource slot. The value
+    /// comes back only once it is on flash, for the caller to apply; a body
+    /// that resets the resource is stored as an empty marker.
+    async fn store_resource<T>(
+        store: &mut pedalboard_midi::storage::ConfigStore<pedalboard_midi::flash::FlashStorage>,
+        resource: u8,
+        data: &[u8],
+    ) -> Result<T, pedalboard_midi::persist::SetStatus>
+    where
+        T: serde::de::DeserializeOwned + Default + PartialEq,
+    {
+        use pedalboard_midi::persist::SetStatus;
+
+        let Some(value) = pedalboard_midi::resource::decode::<T>(data) else {
+            warn!("resource {} deserialize failed", resource);
+            return Err(SetStatus::FormatError);
+        };
+        let mut buf = [0u8; pedalboard_midi::MAX_PRESET_SIZE + 1];
+        let blob: &[u8] = if value == T::default() {
+            &[]
+        } else {
+            versioned(resource, data, &mut buf)
+        };
+        match write_status(store.save_preset(resource, blob).await) {
+            SetStatus::Ok => Ok(value),
+            status => Err(status),
+        }
+    }
+
+    /// Load a typed resource from flash. A legacy layout is upgraded and
+    /// written back in the current one.
+    async fn load_resource<T>(
+        store: &mut pedalboard_midi::storage::ConfigStore<pedalboard_midi::flash::FlashStorage>,
+        resource: u8,
+        layouts: &[pedalboard_midi::migrate::Layout<T>],
+    ) -> Option<T>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        use pedalboard_midi::migrate::{self, Loaded};
+
+        let mut buf = [0u8; pedalboard_midi::MAX_PRESET_SIZE + 1];
+        let data = store
+            .load_preset(resource, &mut buf)
+            .await
+            .filter(|data| !data.is_empty())?;
+        let version = format_version(resource);
+        match migrate::load(data, version, layouts) {
+            Loaded::Current(value) => Some(value),
+            Loaded::Upgraded(value) => {
+                info!("resource {} upgraded from a legacy layout", resource);
+                let mut buf = [0u8; pedalboard_midi::MAX_PRESET_SIZE + 1];
+                if let Some(blob) = migrate::encode(&value, version, &mut buf) {
+                    store.save_preset(resource, blob).await.ok();
                 }
+                Some(value)
+            }
+            Loaded::Unsupported(stored) => {
+                warn!(
+                    "resource {}: flash format v{}, firmware expects v{} — skipped",
+                    resource, stored, version
+                );
+                None
+            }
+            Loaded::Corrupt => None,
+        }
+    }
+
+    /// Runtime state image holding every preset's defaults.
+    fn defaults_image(
+        cfg: &midi_controller::config::Config,
+    ) -> [u8; pedalboard_midi::eeprom::EEPROM_SIZE] {
+        use midi_controller::state::{PresetState, PresetStateStore, EEPROM_MAX_PRESETS};
+
+        let mut state_store = PresetStateStore::new();
+        for (i, p) in cfg.presets.iter().enumerate().take(EEPROM_MAX_PRESETS) {
+            if !p.defaults.button_active.is_empty() || !p.defaults.encoder_values.is_empty() {
+                state_store.set_state(i, PresetState::from_defaults(p));
+            }
+        }
+        let mut buf = [0u8; pedalboard_midi::eeprom::EEPROM_SIZE];
+        state_store.to_eeprom(&mut buf);
+        buf
+    }
+
+    /// Store changed erase counts and take a storage health snapshot,
+    /// remembering the most recent write failure in `last_error`.
+    async fn storage_health(
+        store: &mut pedalboard_midi::storage::ConfigStore<pedalboard_midi::flash::FlashStorage>,
+        eeprom: &mut pedalboard_midi::eeprom::Eeprom<AtomicDevice<'static, I2CBus>>,
+        last_error: &mut Option<pedalboard_midi::storage::PersistError>,
+    ) -> pedalboard_midi::storage::StorageHealth {
+        use pedalboard_midi::storage::PersistError;
+
+        store.save_erase_counts().await.ok();
+        if let Some(err) = store.take_error() {
+            *last_error = Some(err.into());
+        }
+        if eeprom.take_error() {
+            *last_error = Some(PersistError::Eeprom);
+        }
+        let mut health = store.health().await;
+        health.eeprom_writes = eeprom.writes();
+        health.last_error = *last_error;
+        health
+    }
+
+    /// Send the PE Set reply owed for a command the persist task has applied.
+    fn send_set_reply(
+        to: pedalboard_midi::persist::ReplyTo,
+        status: pedalboard_midi::persist::SetStatus,
+        sender: &mut Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
+    ) {
+        if status != pedalboard_midi::persist::SetStatus::Ok {
+            warn!("PE Set failed: status {}", status as u8);
+        }
+        send_sysex(&pedalboard_midi::pe_sysex::build_reply(to, status), sender);
+    }
+
+    /// Send the clock ticks `shaper` has due at `now_us`.
+    fn send_due_clock_ticks(
+        shaper: &mut pedalboard_midi::clock_out::ClockShaper,
+        now_us: u32,
+        sender: &mut Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
+        din_sender: &mut Sender<'static, [u8; 3], DIN_THRU_CAPACITY>,
+    ) {
+        loop {
+            let ports = shaper.poll(now_us);
+            if ports.is_empty() {
+                break;
+            }
+            send_clock_bytes(&[0xF8], ports, sender, din_sender);
+        }
+    }
+
+    /// Send a clock/transport message to the ports in `dest`.
+    fn send_clock_bytes(
+        bytes: &[u8],
+        dest: midi_controller::routing::MidiPort,
+        sender: &mut Sender<'static, UsbMidiEventPacket, USB_OUT_CAPACITY>,
+        din_sender: &mut Sender<'static, [u8; 3], DIN_THRU_CAPACITY>,
+    ) {
+        use midi_controller::routing::MidiPort;
+
+        if dest.contains(MidiPort::DIN) {
+            let mut raw = [0u8; 3];
+            let len = bytes.len().min(3);
+            raw[..len].copy_from_slice(&bytes[..len]);
+            din_sender.try_send(raw).ok();
+        }
+        if dest.contains(MidiPort::USB) {
+            if let Ok(packet) =
+                UsbMidiEventPacket::try_from_payload_bytes(CableNumber::Cable0, bytes)
+            {
+                sender.try_send(packet).ok();
             }
         }
     }
@@ -0,0 +1,228 @@
+//! Flash format migration: blobs written by older firmware are decoded with a
+//! frozen copy of their layout, upgraded to the current one and written back
+//! at boot instead of being skipped.
+//!
+//! Every stored blob starts with a format version byte: `FLASH_FORMAT_VERSION`
+//! for presets and the global config (the protocol schema version), and
+//! `LOCAL_FORMAT_VERSION` for firmware-local blobs (settings, preset
+//! extensions, profiles, songs, banks). A [`Layout`] pairs a version byte with
+//! a decoder for one historical layout of a type; [`load`] tries the current
+//! layout first, then the legacy layouts registered for the blob's version
+//! byte, newest first.
+//!
+//! Fields appended to a protocol type do not always bump its schema version,
+//! so a legacy layout can share the current version byte. Legacy decoders
+//! only accept a payload they consume completely, which tells those layouts
+//! apart.
+//!
+//! Layouts in this module must never change. When a stored type changes,
+//! bump its version byte, freeze its previous layout here, register it in the
+//! type's table and add a fixture of it to `tests-host/tests/migrate.rs`.
+
+use midi_controller::config::{GlobalConfig, Preset};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::bank::Banks;
+use crate::preset_ext::PresetExt;
+use crate::profile::Profile;
+use crate::setlist::Song;
+use crate::settings::Settings;
+
+/// Decoder for one historical layout of `T`.
+pub struct Layout<T> {
+    /// Format version byte the layout was stored with.
+    pub version: u8,
+    /// Decodes a payload (without version byte) and upgrades it to `T`.
+    pub decode: fn(&[u8]) -> Option<T>,
+}
+
+/// Outcome of loading a stored blob.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Loaded<T> {
+    /// Stored in the current layout.
+    Current(T),
+    /// Stored in a legacy layout; should be written back with [`encode`].
+    Upgraded(T),
+    /// No layout is known for this version byte (e.g. newer firmware).
+    Unsupported(u8),
+    /// The version is known but no layout decodes the payload.
+    Corrupt,
+}
+
+/// Decode a stored blob (version byte + payload) whose current layout is
+/// stored with `version`, upgrading legacy layouts.
+pub fn load<T: DeserializeOwned>(data: &[u8], version: u8, legacy: &[Layout<T>]) -> Loaded<T> {
+    let Some((&stored, payload)) = data.split_first() else {
+        return Loaded::Corrupt;
+    };
+    let mut known = stored == version;
+    if known {
+        if let Ok(value) = postcard::from_bytes(payload) {
+            return Loaded::Current(value);
+        }
+    }
+    for layout in legacy.iter().filter(|l| l.version == stored) {
+        known = true;
+        if let Some(value) = (layout.decode)(payload) {
+            return Loaded::Upgraded(value);
+        }
+    }
+    if known {
+        Loaded::Corrupt
+    } else {
+        Loaded::Unsupported(stored)
+    }
+}
+
+/// Encode `value` in the current layout behind its `version` byte, for storing.
+pub fn encode<'a, T: Serialize>(value: &T, version: u8, out: &'a mut [u8]) -> Option<&'a [u8]> {
+    let (first, payload) = out.split_first_mut()?;
+    *first = version;
+    let len = postcard::to_slice(value, payload).ok()?.len();
+    Some(&out[..len + 1])
+}
+
+/// Decode a legacy layout `L` that must span the whole payload.
+fn exact<L: DeserializeOwned + Into<T>, T>(payload: &[u8]) -> Option<T> {
+    match postcard::take_from_bytes::<L>(payload) {
+        Ok((legacy, rest)) if rest.is_empty() => Some(legacy.into()),
+        _ => None,
+    }
+}
+
+/// Legacy preset layouts, newest first.
+pub const PRESET_LAYOUTS: &[Layout<Preset>] = &[
+    Layout {
+        version: legacy::SCHEMA_6,
+        decode: exact::<legacy::PresetV6, _>,
+    },
+    Layout {
+        version: legacy::SCHEMA_5,
+        decode: exact::<legacy::PresetV5, _>,
+    },
+];
+
+/// Legacy global config layouts. The layout has not changed since schema 5,
+/// only the version byte it was stored with.
+pub const GLOBAL_CONFIG_LAYOUTS: &[Layout<GlobalConfig>] = &[Layout {
+    version: legacy::SCHEMA_5,
+    decode: exact::<GlobalConfig, _>,
+}];
+
+/// Legacy device settings layouts. None yet: settings were first stored with
+/// `LOCAL_FORMAT_VERSION` 1. Freeze the current layout here when it is
+/// bumped for a settings change.
+pub const SETTINGS_LAYOUTS: &[Layout<Settings>] = &[];
+
+/// Legacy preset extension layouts (see `SETTINGS_LAYOUTS`).
+pub const PRESET_EXT_LAYOUTS: &[Layout<PresetExt>] = &[];
+
+/// Legacy device profile layouts (see `SETTINGS_LAYOUTS`).
+pub const PROFILE_LAYOUTS: &[Layout<Profile>] = &[];
+
+/// Legacy bank name layouts (see `SETTINGS_LAYOUTS`).
+pub const BANKS_LAYOUTS: &[Layout<Banks>] = &[];
+
+/// Legacy setlist song layouts (see `SETTINGS_LAYOUTS`).
+pub const SONG_LAYOUTS: &[Layout<Song>] = &[];
+
+/// Frozen historical layouts of the protocol crate's types, by the
+/// `PRESET_SCHEMA_VERSION` they were stored with. Field types that have not
+/// changed since are shared with the current definitions.
+pub mod legacy {
+    use heapless::Vec;
+    use midi_controller::config::{
+        Action, AnalogConfig, ButtonConfig, EncoderAction, EncoderConfig, InitialState, Label,
+        Preset, Trigger, MAX_ACTIONS, MAX_ANALOG, MAX_BUTTONS, MAX_ENCODERS, MAX_TRIGGERS,
+    };
+    use serde::{Deserialize, Serialize};
+
+    /// Schema of midi-controller 0.1.0 to 0.4.1.
+    pub const SCHEMA_5: u8 = 5;
+
+    /// Schema of midi-controller 0.5.0 to 0.7.1. The preset tempo was later
+    /// appended without a schema bump, so the current layout may share it.
+    pub const SCHEMA_6: u8 = 6;
+
+    /// Schema 6 preset, without the preset tempo.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct PresetV6 {
+        pub name: Label,
+        pub buttons: Vec<ButtonConfig, MAX_BUTTONS>,
+        pub encoders: Vec<EncoderConfig, MAX_ENCODERS>,
+        pub analog: Vec<AnalogConfig, MAX_ANALOG>,
+        pub defaults: InitialState,
+        pub on_enter: Vec<Action, MAX_ACTIONS>,
+        pub on_exit: Vec<Action, MAX_ACTIONS>,
+        pub triggers: Vec<Trigger, MAX_TRIGGERS>,
+    }
+
+    /// Schema 5 preset: schema 6 with encoders that have no LED ring config.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct PresetV5 {
+        pub name: Label,
+        pub buttons: Vec<ButtonConfig, MAX_BUTTONS>,
+        pub encoders: Vec<EncoderConfigV5, MAX_ENCODERS>,
+        pub analog: Vec<AnalogConfig, MAX_ANALOG>,
+        pub defaults: InitialState,
+        pub on_enter: Vec<Action, MAX_ACTIONS>,
+        pub on_exit: Vec<Action, MAX_ACTIONS>,
+        pub triggers: Vec<Trigger, MAX_TRIGGERS>,
+    }
+
+    /// Schema 5 encoder.
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct EncoderConfigV5 {
+        pub label: Label,
+        pub action: EncoderAction,
+    }
+
+    impl From<PresetV6> for Preset {
+        fn from(p: PresetV6) -> Self {
+            Preset {
+                name: p.name,
+                buttons: p.buttons,
+                encoders: p.encoders,
+                analog: p.analog,
+                defaults: p.defaults,
+                on_enter: p.on_enter,
+                on_exit: p.on_exit,
+                triggers: p.triggers,
+                ..Preset::default()
+            }
+        }
+    }
+
+    impl From<EncoderConfigV5> for EncoderConfig {
+        fn from(e: EncoderConfigV5) -> Self {
+            EncoderConfig {
+                label: e.label,
+                action: e.action,
+                // Schema 6 default: heatmap ring tracking the value
+                ..EncoderConfig::default()
+            }
+        }
+    }
+
+    impl From<PresetV5> for PresetV6 {
+        fn from(p: PresetV5) -> Self {
+            PresetV6 {
+                name: p.name,
+                buttons: p.buttons,
+                encoders: p.encoders.into_iter().map(Into::into).collect(),
+                analog: p.analog,
+                defaults: p.defaults,
+                on_enter: p.on_enter,
+                on_exit: p.on_exit,
+                triggers: p.triggers,
+            }
+        }
+    }
+
+    impl From<PresetV5> for Preset {
+        fn from(p: PresetV5) -> Self {
+            PresetV6::from(p).into()
+        }
+    }
+}
@@ -7,14 +7,25 @@
 //!
 //! All business logic lives in the Controller.
 
+use crate::clock_out::ClockSource;
 use crate::events::{Edge, InputEvent, Pulse};
 use crate::ledring::{rgb8_to_rgb, Modifier, Renderer, RingAnimation};
 #[cfg(target_arch = "arm")]
 use crate::leds::LedEvent;
-use midi_controller::config::{Color, Config, LedAnimation, LedRenderer, Preset};
+use crate::lfo::{LfoConfig, LfoControl};
+use crate::looper::{Looper, LooperConfig, LooperRole, LooperState, Transition};
+use crate::preset_ext::{Condition, PresetExt, PresetExts, Repeat};
+use crate::profile::Profiles;
+use crate::setlist::{Setlist, SongStep};
+use crate::settings::{PanicTrigger, Settings};
+use crate::transport::{TransportAction, TransportEvent, MAX_MMC_LEN};
+use midi_controller::config::{
+    Action, ButtonMode, Color, Config, EncoderAction, InitialState, LedAnimation, LedRenderer,
+    Preset,
+};
 use midi_controller::controller::{Controller, Event as CtrlEvent, Output};
 use midi_controller::engine::ActionStep;
-use midi_controller::long_press::Edge as LpEdge;
+use midi_controller::long_press::{Edge as LpEdge, LONG_PRESS_MS};
 use midi_controller::state::PresetStateStore;
 use smart_leds::RGB8;
 
@@ -28,6 +39,8 @@ pub use midi_controller::engine::{DisplayEvent, DisplaySide, SystemAction};
 pub enum MidiStep {
     Send([u8; 3], usize, midi_controller::routing::MidiPort),
     Delay(u16),
+    /// SysEx message (MMC). USB only: the DIN driver has no SysEx path.
+    SysEx(heapless::Vec<u8, MAX_MMC_LEN>),
     SetLed {
         btn_idx: usize,
         color: Color,
@@ -35,9 +48,13 @@ pub enum MidiStep {
     },
 }
 
+/// Maximum number of steps one `handle_events` call produces.
+pub const MAX_RESULT_STEPS: usize = 32;
+
 /// Result of processing events.
+#[derive(Default)]
 pub struct HandleResult {
-    pub midi: heapless::Vec<MidiStep, 32>,
+    pub midi: heapless::Vec<MidiStep, MAX_RESULT_STEPS>,
     pub display: heapless::Vec<DisplayEvent, 2>,
     pub routed: heapless::Vec<midi_controller::routing::MidiOut, 16>,
     pub reactive_led: Option<midi_controller::engine::ReactiveResult>,
@@ -45,6 +62,171 @@ pub struct HandleResult {
     pub preset_changed: bool,
     pub bpm: Option<u16>,
     pub clock_running: Option<bool>,
+    /// Song Position Pointer action: the clock sends it and Continue (instead
+    /// of Start), per the per-port transport settings.
+    pub song_position: Option<u16>,
+    /// Setlist navigation moved to another song.
+    pub song_changed: bool,
+    /// Bank preview started, moved, or ended (see `PeHandler::preview`).
+    pub preview_changed: bool,
+    /// LED rings need re-rendering without a state change (countdown rings).
+    pub rings_changed: bool,
+    /// Looper state changed (see `PeHandler::looper_state`).
+    pub looper_changed: bool,
+    /// LFO button pressed: apply to the generator (with the button index for
+    /// its ring) after configuring it with `PeHandler::lfo_config`.
+    pub lfo: Option<(LfoControl, u8)>,
+    /// A tick of the clock in charge passed: advance the LFO.
+    pub clock_tick: bool,
+    /// Snapshot button pressed: save `PeHandler::defaults_snapshot` as the
+    /// active preset's defaults.
+    pub snapshot: bool,
+    /// Panic fired: cancel pending steps and send `panic_messages()` to all ports.
+    /// Other MIDI in this result is superseded.
+    pub panic: bool,
+}
+
+/// Channel-mode messages sent by the panic action: All Notes Off, All Sound Off
+/// and Reset All Controllers on all 16 channels.
+pub fn panic_messages() -> impl Iterator<Item = [u8; 3]> {
+    const CONTROLLERS: [u8; 3] = [123, 120, 121];
+    (0..16u8).flat_map(|ch| CONTROLLERS.map(|cc| [0xB0 | ch, cc, 0]))
+}
+
+/// Keep a followed or synced message from going back to its sender: output
+/// with the same status byte (kind and channel) no longer goes to `source`.
+/// Other ports and channels still get the thru copy and the preset's
+/// `on_enter` messages.
+fn suppress_echo(
+    result: &mut HandleResult,
+    status: u8,
+    source: midi_controller::routing::MidiPort,
+) {
+    for out in result.routed.iter_mut() {
+        if out.bytes().first() == Some(&status) {
+            out.dest.remove(source);
+        }
+    }
+    result.routed.retain(|out| !out.dest.is_empty());
+    for step in result.midi.iter_mut() {
+        if let MidiStep::Send(data, _, ports) = step {
+            if data[0] == status {
+                ports.remove(source);
+            }
+        }
+    }
+    result
+        .midi
+        .retain(|step| !matches!(step, MidiStep::Send(_, _, ports) if ports.is_empty()));
+}
+
+/// Steps waiting behind a `Delay`. Lets the caller keep polling inputs while a
+/// sequence is in flight, and lets panic drop whatever is still pending.
+/// Size `N` to at least `MAX_RESULT_STEPS` so a full result fits.
+pub struct StepQueue<const N: usize> {
+    steps: heapless::Deque<MidiStep, N>,
+    resume_at_ms: Option<u32>,
+    dropped: u32,
+}
+
+impl<const N: usize> Default for StepQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> StepQueue<N> {
+    pub const fn new() -> Self {
+        Self {
+            steps: heapless::Deque::new(),
+            resume_at_ms: None,
+            dropped: 0,
+        }
+    }
+
+    /// Queue steps behind any pending ones. Steps beyond capacity are dropped;
+    /// returns how many.
+    pub fn extend(&mut self, steps: &[MidiStep]) -> usize {
+        for (i, step) in steps.iter().enumerate() {
+            if self.steps.push_back(step.clone()).is_err() {
+                let dropped = steps.len() - i;
+                self.dropped = self.dropped.saturating_add(dropped as u32);
+                return dropped;
+            }
+        }
+        0
+    }
+
+    /// Steps dropped on overflow since boot.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Next step due at `now_ms`. `Delay` steps are consumed here and hold back
+    /// the rest of the queue until they elapse.
+    pub fn pop_ready(&mut self, now_ms: u32) -> Option<MidiStep> {
+        if let Some(resume_at) = self.resume_at_ms {
+            if (now_ms.wrapping_sub(resume_at) as i32) < 0 {
+                return None;
+            }
+            self.resume_at_ms = None;
+        }
+        loop {
+            match self.steps.pop_front()? {
+                MidiStep::Delay(0) => {}
+                MidiStep::Delay(ms) => {
+                    self.resume_at_ms = Some(now_ms.wrapping_add(ms as u32));
+                    return None;
+                }
+                step => return Some(step),
+            }
+        }
+    }
+
+    /// Drop all pending steps (panic).
+    pub fn clear(&mut self) {
+        self.steps.clear();
+        self.resume_at_ms = None;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty() && self.resume_at_ms.is_none()
+    }
+}
+
+/// Running auto-off timer of a timed toggle (see `preset_ext::AutoOff`).
+#[derive(Debug, Clone, Copy)]
+struct AutoOffTimer {
+    /// None until the next tick picks up a toggle switched on outside `handle_events`.
+    started_ms: Option<u32>,
+    length_ms: u32,
+    countdown: bool,
+    /// Ring segments left (0-12), for countdown rendering.
+    fill: u8,
+}
+
+/// Auto-repeat state of a held button.
+#[derive(Debug, Clone, Copy)]
+struct RepeatTimer {
+    last_ms: u32,
+    /// Wait before the next repeat: the initial delay, then the rate.
+    wait_ms: u32,
+}
+
+/// Press of a hybrid toggle button (see `preset_ext::Hybrid`).
+#[derive(Debug, Clone, Copy)]
+struct HybridPress {
+    since_ms: u32,
+    hold_ms: u32,
+    /// Preset the press toggled; a switch in between cancels the revert.
+    preset: u8,
+}
+
+/// Pending bank preview started by an encoder `PresetScroll`.
+#[derive(Debug, Clone, Copy)]
+struct Preview {
+    target: u8,
+    last_input_ms: u32,
 }
 
 /// LED state for all 8 rings (A-F + Vol + Gain).
@@ -53,6 +235,33 @@ pub type LedAnimations = [RingAnimation; 8];
 /// Stateful PE event handler. Wraps the protocol crate's Controller.
 pub struct PeHandler {
     ctrl: Controller,
+    setlist: Setlist,
+    active_song: Option<usize>,
+    /// Press timestamp of buttons whose edges are handled by setlist navigation.
+    song_hold: [Option<u32>; NUM_BUTTONS],
+    /// Long-press navigation already fired for the held button.
+    song_long_fired: [bool; NUM_BUTTONS],
+    settings: Settings,
+    preview: Option<Preview>,
+    /// Press consumed by the glue (preview confirm, panic); its release is swallowed too.
+    swallow_release: [bool; NUM_BUTTONS],
+    /// Buttons currently down (for panic chords).
+    held: [bool; NUM_BUTTONS],
+    /// Press timestamp of a `PanicTrigger::LongPress` button, until fired or released.
+    panic_hold: Option<u32>,
+    /// The device's only copy of the preset extensions, fed by the persist task.
+    exts: PresetExts,
+    profiles: Profiles,
+    looper: Looper,
+    /// Clock in charge of the looper's bar grid and the LFO.
+    clock: ClockSource,
+    auto_off: [Option<AutoOffTimer>; NUM_BUTTONS],
+    /// Preset the auto-off timers belong to.
+    auto_off_preset: u8,
+    repeat: [Option<RepeatTimer>; NUM_BUTTONS],
+    hybrid_hold: [Option<HybridPress>; NUM_BUTTONS],
+    /// Last tap-tempo BPM (0 = use the global config).
+    bpm: u16,
 }
 
 impl Default for PeHandler {
@@ -63,15 +272,35 @@ impl Default for PeHandler {
 
 impl PeHandler {
     pub fn new() -> Self {
-        Self {
-            ctrl: Controller::new(),
-        }
+        Self::with_controller(Controller::new())
     }
 
     /// Create with a restored state store (from EEPROM).
     pub fn with_state(store: PresetStateStore) -> Self {
+        Self::with_controller(Controller::with_state(store))
+    }
+
+    fn with_controller(ctrl: Controller) -> Self {
         Self {
-            ctrl: Controller::with_state(store),
+            ctrl,
+            setlist: Setlist::new(),
+            active_song: None,
+            song_hold: [None; NUM_BUTTONS],
+            song_long_fired: [false; NUM_BUTTONS],
+            settings: Settings::new(),
+            preview: None,
+            swallow_release: [false; NUM_BUTTONS],
+            held: [false; NUM_BUTTONS],
+            panic_hold: None,
+            exts: PresetExts::new(),
+            profiles: Profiles::new(),
+            looper: Looper::new(),
+            clock: ClockSource::new(),
+            auto_off: [None; NUM_BUTTONS],
+            auto_off_preset: 0,
+            repeat: [None; NUM_BUTTONS],
+            hybrid_hold: [None; NUM_BUTTONS],
+            bpm: 0,
         }
     }
 
@@ -82,20 +311,24 @@ impl PeHandler {
         events: &[InputEvent],
         now_ms: u32,
     ) -> HandleResult {
-        let mut result = HandleResult {
-            midi: heapless::Vec::new(),
-            display: heapless::Vec::new(),
-            routed: heapless::Vec::new(),
-            reactive_led: None,
-            leds_changed: false,
-            preset_changed: false,
-            bpm: None,
-            clock_running: None,
-        };
+        let mut result = HandleResult::default();
 
         // Map hardware button events
         for i in 0..NUM_BUTTONS {
             if let Some(edge) = button_edge(events, i) {
+                self.held[i] = edge == Edge::Activate;
+                let consumed = self.panic_edge(config, i, edge, now_ms, &mut result)
+                    || self.preview_button_edge(config, i, edge, &mut result)
+                    || self.looper_edge(i, edge, now_ms, &mut result);
+                self.arm_repeat(config, i, edge, consumed, now_ms);
+                if consumed || self.song_nav_edge(config, i, edge, now_ms, &mut result) {
+                    continue;
+                }
+                let press_preset = self.ctrl.active_preset();
+                let conditional = match edge {
+                    Edge::Activate => self.eval_conditional(i),
+                    Edge::Deactivate => None,
+                };
                 let r = self.ctrl.process(
                     CtrlEvent::ButtonEdge {
                         index: i as u8,
@@ -105,6 +338,16 @@ impl PeHandler {
                     config,
                 );
                 self.merge(&r, &mut result);
+                if let Some((preset, then)) = conditional {
+                    self.run_conditional(config, preset, i, then, &mut result);
+                }
+                if edge == Edge::Activate {
+                    self.run_semantic(press_preset, i, &mut result);
+                    self.run_lfo(press_preset, i, &mut result);
+                    self.run_transport(press_preset, i, &mut result);
+                    self.run_snapshot(press_preset, i, &mut result);
+                }
+                self.hybrid_edge(config, i, edge, now_ms, &mut result);
             }
         }
 
@@ -113,15 +356,43 @@ impl PeHandler {
             let r = self.ctrl.process(CtrlEvent::Tick, now_ms, config);
             self.merge(&r, &mut result);
         }
+        self.song_nav_tick(config, now_ms, &mut result);
+        self.repeat_tick(config, now_ms, &mut result);
+        self.looper_tick(now_ms, &mut result);
+
+        self.sync_auto_off(config, Some(now_ms), &mut result);
+
+        // Panic long press
+        if let Some(since) = self.panic_hold {
+            if now_ms.wrapping_sub(since) >= LONG_PRESS_MS {
+                self.panic_hold = None;
+                if let PanicTrigger::LongPress(b) = self.settings.panic {
+                    self.swallow_release[b as usize] = true;
+                }
+                self.fire_panic(&mut result);
+            }
+        }
+
+        // Auto-confirm an idle preview
+        if let Some(preview) = self.preview {
+            let timeout = self.settings.preview_timeout_ms as u32;
+            if timeout > 0 && now_ms.wrapping_sub(preview.last_input_ms) >= timeout {
+                self.confirm_preview(config, &mut result);
+            }
+        }
 
         // Map hardware encoder/analog events
         for event in events {
             match event {
                 InputEvent::Vol(pulse) => {
+                    let clockwise = *pulse == Pulse::Clockwise;
+                    if self.preview_scroll(config, 0, clockwise, now_ms, &mut result) {
+                        continue;
+                    }
                     let r = self.ctrl.process(
                         CtrlEvent::EncoderTurn {
                             index: 0,
-                            clockwise: *pulse == Pulse::Clockwise,
+                            clockwise,
                         },
                         now_ms,
                         config,
@@ -129,10 +400,14 @@ impl PeHandler {
                     self.merge(&r, &mut result);
                 }
                 InputEvent::Gain(pulse) => {
+                    let clockwise = *pulse == Pulse::Clockwise;
+                    if self.preview_scroll(config, 1, clockwise, now_ms, &mut result) {
+                        continue;
+                    }
                     let r = self.ctrl.process(
                         CtrlEvent::EncoderTurn {
                             index: 1,
-                            clockwise: *pulse == Pulse::Clockwise,
+                            clockwise,
                         },
                         now_ms,
                         config,
@@ -161,15 +436,33 @@ impl PeHandler {
                     );
                     self.merge(&r, &mut result);
                 }
+                // Encoder push confirms a pending preview
+                InputEvent::VolButton(Edge::Activate) | InputEvent::GainButton(Edge::Activate) => {
+                    self.confirm_preview(config, &mut result);
+                }
                 _ => {}
             }
         }
 
+        if let Some(bpm) = result.bpm {
+            self.bpm = bpm;
+        }
         result
     }
 
     /// Process incoming MIDI: routing, reactive LEDs, and triggers.
     pub fn process_incoming_midi(&mut self, config: &Config, raw: &[u8]) -> HandleResult {
+        self.process_midi_from(config, raw, midi_controller::routing::MidiPort::USB, 0)
+    }
+
+    /// Process MIDI received on `source` (USB or DIN) at `now_ms`.
+    pub fn process_midi_from(
+        &mut self,
+        config: &Config,
+        raw: &[u8],
+        source: midi_controller::routing::MidiPort,
+        now_ms: u32,
+    ) -> HandleResult {
         let mut data = [0u8; 8];
         let len = raw.len().min(8);
         data[..len].copy_from_slice(&raw[..len]);
@@ -177,7 +470,7 @@ impl PeHandler {
             CtrlEvent::Midi {
                 data,
                 len: len as u8,
-                source: midi_controller::routing::MidiPort::USB,
+                source,
             },
             0,
             config,
@@ -185,16 +478,50 @@ impl PeHandler {
         let routed = core::mem::take(&mut r.midi_out);
         let reactive_led = r.reactive_led;
         let mut result = HandleResult {
-            midi: heapless::Vec::new(),
-            display: heapless::Vec::new(),
             routed,
             reactive_led,
-            leds_changed: false,
-            preset_changed: false,
-            bpm: None,
-            clock_running: None,
+            ..Default::default()
         };
         self.merge(&r, &mut result);
+
+        // Program Change follow: the board follows the sender
+        let follow = match raw {
+            [status, program, ..] if status & 0xF0 == 0xC0 => self
+                .settings
+                .pc_follow_target((status & 0x0F) + 1, *program),
+            _ => None,
+        };
+        let synced = self.sync_state(config, raw, &mut result);
+        if let Some(target) = follow {
+            let valid = config.presets.get(target as usize).is_some();
+            if valid && target != self.ctrl.active_preset() {
+                let r = self.ctrl.select_preset(target, config);
+                self.merge(&r, &mut result);
+            }
+        }
+        if follow.is_some() || synced {
+            suppress_echo(&mut result, raw[0], source);
+        }
+        if raw.first() == Some(&0xF8) && self.clock.external_tick(now_ms) {
+            self.clock_tick(now_ms, &mut result);
+        }
+        if let Some(position) = crate::transport::parse_song_position(raw) {
+            self.run_transport_triggers(
+                config,
+                TransportEvent::SongPosition(position),
+                &mut result,
+            );
+        }
+        // Toggles switched on by incoming MIDI start their timer on the next tick
+        self.sync_auto_off(config, None, &mut result);
+        result
+    }
+
+    /// Incoming MMC from the DAW: run the active preset's matching transport triggers.
+    pub fn process_transport(&mut self, config: &Config, event: TransportEvent) -> HandleResult {
+        let mut result = HandleResult::default();
+        self.run_transport_triggers(config, event, &mut result);
+        self.sync_auto_off(config, None, &mut result);
         result
     }
 
@@ -209,6 +536,12 @@ impl PeHandler {
     /// Returns true if any button is currently held.
     pub fn any_active(&self) -> bool {
         self.ctrl.button_held()
+            || self.song_hold.iter().any(Option::is_some)
+            || self.preview.is_some()
+            || self.panic_hold.is_some()
+            || self.auto_off.iter().any(Option::is_some)
+            || self.repeat.iter().any(Option::is_some)
+            || self.looper.is_pending()
     }
 
     /// Returns the current button active state.
@@ -216,6 +549,26 @@ impl PeHandler {
         *self.ctrl.button_states()
     }
 
+    /// Current state of the active preset as its `defaults`: toggle and radio
+    /// states (momentary buttons off) and encoder values, one per configured
+    /// button and encoder.
+    pub fn defaults_snapshot(&self, config: &Config) -> InitialState {
+        let mut defaults = InitialState::default();
+        let Some(preset) = config.presets.get(self.ctrl.active_preset() as usize) else {
+            return defaults;
+        };
+        let active = self.ctrl.button_states();
+        for (button, &on) in preset.buttons.iter().zip(active.iter()) {
+            let latched = !matches!(button.mode, ButtonMode::Momentary);
+            defaults.button_active.push(on && latched).ok();
+        }
+        let values = self.ctrl.encoder_values();
+        for &value in values.iter().take(preset.encoders.len()) {
+            defaults.encoder_values.push(value).ok();
+        }
+        defaults
+    }
+
     /// Get the active preset index.
     pub fn active_preset(&self) -> u8 {
         self.ctrl.active_preset()
@@ -230,19 +583,90 @@ impl PeHandler {
     pub fn switch_to(&mut self, preset_idx: u8, config: &Config) -> HandleResult {
         let r = self.ctrl.select_preset(preset_idx, config);
         let mut result = HandleResult {
-            midi: heapless::Vec::new(),
-            display: heapless::Vec::new(),
-            routed: heapless::Vec::new(),
-            reactive_led: None,
-            leds_changed: false,
-            preset_changed: false,
-            bpm: None,
-            clock_running: None,
+            preview_changed: self.preview.take().is_some(),
+            ..Default::default()
         };
         self.merge(&r, &mut result);
         result
     }
 
+    /// Apply device settings (cheap no-op when unchanged).
+    pub fn set_settings(&mut self, settings: &Settings) {
+        if self.settings != *settings {
+            self.settings = settings.clone();
+        }
+    }
+
+    /// Store (or reset, with `PresetExt::default()`) the extension of a preset.
+    pub fn set_preset_ext(&mut self, index: usize, ext: PresetExt) {
+        self.exts.set(index, ext);
+    }
+
+    /// Apply uploaded device profiles.
+    pub fn set_profiles(&mut self, profiles: &Profiles) {
+        self.profiles = profiles.clone();
+    }
+
+    /// Revision of the loaded profiles (see `Profiles::revision`).
+    pub fn profiles_revision(&self) -> u8 {
+        self.profiles.revision
+    }
+
+    /// LFO setup of the active preset.
+    pub fn lfo_config(&self) -> Option<LfoConfig> {
+        self.exts.get(self.ctrl.active_preset() as usize)?.lfo
+    }
+
+    /// Looper state, if the active preset has a looper setup.
+    pub fn looper_state(&self) -> Option<LooperState> {
+        self.exts
+            .get(self.ctrl.active_preset() as usize)
+            .and_then(|ext| ext.looper)
+            .map(|_| self.looper.state())
+    }
+
+    /// Preset currently being previewed by `PresetScroll`, if any.
+    pub fn preview(&self) -> Option<u8> {
+        self.preview.map(|p| p.target)
+    }
+
+    /// Replace the setlist used for song navigation.
+    /// Keeps the current song if its slot is still populated.
+    pub fn set_setlist(&mut self, setlist: &Setlist) {
+        self.setlist = setlist.clone();
+        if self
+            .active_song
+            .is_some_and(|s| self.setlist.get(s).is_none())
+        {
+            self.active_song = None;
+        }
+    }
+
+    /// Revision of the loaded setlist (see `Setlist::revision`).
+    pub fn setlist_revision(&self) -> u8 {
+        self.setlist.revision
+    }
+
+    /// Get the active song slot, if a setlist is loaded and a song was selected.
+    pub fn active_song(&self) -> Option<u8> {
+        self.active_song.map(|s| s as u8)
+    }
+
+    /// Restore the active song slot without switching presets (boot initialization).
+    pub fn set_active_song(&mut self, slot: u8) {
+        if self.setlist.get(slot as usize).is_some() {
+            self.active_song = Some(slot as usize);
+        }
+    }
+
+    /// Move one song forward/back in the setlist: switches to the song's preset
+    /// (if different) and engages its scene button.
+    pub fn step_song(&mut self, step: SongStep, config: &Config, now_ms: u32) -> HandleResult {
+        let mut result = HandleResult::default();
+        self.apply_song_step(step, config, now_ms, &mut result);
+        result
+    }
+
     /// Compute LED animations for all 8 rings.
     pub fn led_state(&self, preset: &Preset) -> LedAnimations {
         let mut anims = [RingAnimation::off(); 8];
@@ -250,6 +674,10 @@ impl PeHandler {
         let encoder_values = self.ctrl.encoder_values();
 
         for (i, anim) in anims.iter_mut().enumerate().take(NUM_BUTTONS) {
+            if let Some(ring) = self.looper_ring(i) {
+                *anim = ring;
+                continue;
+            }
             if let Some(btn) = preset.buttons.get(i) {
                 if btn.listen_cc.is_some() {
                     continue;
@@ -257,6 +685,12 @@ impl PeHandler {
                 let on_color = color_to_rgb(&btn.color.on);
                 if on_color == RGB8::default() {
                     *anim = RingAnimation::off();
+                } else if let Some(timer) = self.auto_off[i].filter(|t| t.countdown) {
+                    // Timed toggle: draining ring shows the time left
+                    *anim = RingAnimation {
+                        renderer: Renderer::Fill(rgb8_to_rgb(on_color), timer.fill),
+                        modifier: Modifier::Solid,
+                    };
                 } else if button_active[i] {
                     let modifier = anim_to_modifier(btn.color.animation);
                     let rgb = rgb8_to_rgb(on_color);
@@ -294,6 +728,871 @@ impl PeHandler {
 
     // --- Private ---
 
+    /// Step the bank preview for an encoder bound to `PresetScroll`.
+    /// Returns true if the turn was consumed (no Controller processing, no MIDI).
+    fn preview_scroll(
+        &mut self,
+        config: &Config,
+        index: usize,
+        clockwise: bool,
+        now_ms: u32,
+        result: &mut HandleResult,
+    ) -> bool {
+        if !self.settings.scroll_preview {
+            return false;
+        }
+        let is_scroll = config
+            .presets
+            .get(self.ctrl.active_preset() as usize)
+            .and_then(|p| p.encoders.get(index))
+            .is_some_and(|e| matches!(e.action, EncoderAction::PresetScroll));
+        if !is_scroll {
+            return false;
+        }
+        let from = self.preview().unwrap_or(self.ctrl.active_preset());
+        if let Some(target) = scroll_target(config, from, clockwise) {
+            self.preview = Some(Preview {
+                target,
+                last_input_ms: now_ms,
+            });
+            result.preview_changed = true;
+        }
+        true
+    }
+
+    /// Route a button edge to the configured panic gesture. Returns true if consumed.
+    fn panic_edge(
+        &mut self,
+        config: &Config,
+        i: usize,
+        edge: Edge,
+        now_ms: u32,
+        result: &mut HandleResult,
+    ) -> bool {
+        match (self.settings.panic, edge) {
+            (PanicTrigger::Press(b), Edge::Activate) if b as usize == i => {
+                self.swallow_release[i] = true;
+                self.fire_panic(result);
+                true
+            }
+            (PanicTrigger::LongPress(b), Edge::Activate) if b as usize == i => {
+                self.panic_hold = Some(now_ms);
+                true
+            }
+            (PanicTrigger::LongPress(b), Edge::Deactivate)
+                if b as usize == i && self.panic_hold.take().is_some() =>
+            {
+                // Released early: replay the press so the button's own actions run.
+                for edge in [LpEdge::Activate, LpEdge::Deactivate] {
+                    let r = self.ctrl.process(
+                        CtrlEvent::ButtonEdge {
+                            index: i as u8,
+                            edge,
+                        },
+                        now_ms,
+                        config,
+                    );
+                    self.merge(&r, result);
+                }
+                true
+            }
+            (PanicTrigger::Chord(mask), Edge::Activate) => {
+                let held = self
+                    .held
+                    .iter()
+                    .enumerate()
+                    .fold(0u8, |m, (b, &h)| if h { m | 1 << b } else { m });
+                if mask == 0 || held & mask != mask {
+                    return false;
+                }
+                // The completing press is consumed; members pressed earlier
+                // already reached the Controller, so release them there (their
+                // MIDI is superseded by the panic) and stop their repeat. No
+                // member's physical release runs its actions.
+                for b in (0..NUM_BUTTONS).filter(|&b| mask & 1 << b != 0) {
+                    self.swallow_release[b] = true;
+                    self.repeat[b] = None;
+                    if b != i {
+                        let r = self.ctrl.process(
+                            CtrlEvent::ButtonEdge {
+                                index: b as u8,
+                                edge: LpEdge::Deactivate,
+                            },
+                            now_ms,
+                            config,
+                        );
+                        self.merge(&r, result);
+                    }
+                }
+                self.fire_panic(result);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Flag panic in the result; pending delays are cancelled by the caller.
+    fn fire_panic(&mut self, result: &mut HandleResult) {
+        result.panic = true;
+        if self.settings.panic_stops_clock {
+            result.clock_running = Some(false);
+        }
+    }
+
+    /// While previewing, a footswitch press confirms instead of firing its actions.
+    fn preview_button_edge(
+        &mut self,
+        config: &Config,
+        i: usize,
+        edge: Edge,
+        result: &mut HandleResult,
+    ) -> bool {
+        match edge {
+            Edge::Activate if self.preview.is_some() => {
+                self.swallow_release[i] = true;
+                self.confirm_preview(config, result);
+                true
+            }
+            Edge::Deactivate if self.swallow_release[i] => {
+                self.swallow_release[i] = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// End the preview, switching to the previewed preset if it differs.
+    fn confirm_preview(&mut self, config: &Config, result: &mut HandleResult) {
+        let Some(preview) = self.preview.take() else {
+            return;
+        };
+        result.preview_changed = true;
+        if preview.target != self.ctrl.active_preset() {
+            let r = self.ctrl.select_preset(preview.target, config);
+            self.merge(&r, result);
+        }
+    }
+
+    /// Route a button edge to setlist navigation when a setlist is loaded and the
+    /// button is bound to `PresetNext`/`PresetPrev`. Returns true if consumed.
+    ///
+    /// Buttons with a navigation long press are timed here instead of in the
+    /// Controller; a short press is replayed to the Controller on release.
+    fn song_nav_edge(
+        &mut self,
+        config: &Config,
+        i: usize,
+        edge: Edge,
+        now_ms: u32,
+        result: &mut HandleResult,
+    ) -> bool {
+        // The release of a press we consumed is always ours, even if the
+        // navigation switched to a preset where this button does something else.
+        let tracked = self.song_hold[i].is_some();
+        if !tracked && !self.setlist.is_active() {
+            return false;
+        }
+        let (press_step, long_step) = config
+            .presets
+            .get(self.ctrl.active_preset() as usize)
+            .and_then(|p| p.buttons.get(i))
+            .map_or((None, None), |btn| {
+                (nav_step(&btn.on_press), nav_step(&btn.on_long_press))
+            });
+
+        match edge {
+            Edge::Activate => {
+                if long_step.is_some() {
+                    self.song_hold[i] = Some(now_ms);
+                    self.song_long_fired[i] = false;
+                    true
+                } else if let Some(step) = press_step {
+                    self.song_hold[i] = Some(now_ms);
+                    self.song_long_fired[i] = true;
+                    self.apply_song_step(step, config, now_ms, result);
+                    true
+                } else {
+                    false
+                }
+            }
+            Edge::Deactivate => {
+                if self.song_hold[i].take().is_none() {
+                    return false;
+                }
+                if !self.song_long_fired[i] {
+                    if let Some(step) = press_step {
+                        self.apply_song_step(step, config, now_ms, result);
+                    } else {
+                        // Short press on a button whose long press navigates:
+                        // replay the press so the Controller fires on_press.
+                        for edge in [LpEdge::Activate, LpEdge::Deactivate] {
+                            let r = self.ctrl.process(
+                                CtrlEvent::ButtonEdge {
+                                    index: i as u8,
+                                    edge,
+                                },
+                                now_ms,
+                                config,
+                            );
+                            self.merge(&r, result);
+                        }
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    /// Start, advance and expire timed-toggle timers. Timers follow the button
+    /// state, so toggles switched by any path (press, radio group, incoming CC)
+    /// are covered. `now_ms` is None outside the input tick.
+    fn sync_auto_off(&mut self, config: &Config, now_ms: Option<u32>, result: &mut HandleResult) {
+        let active_preset = self.ctrl.active_preset();
+        if active_preset != self.auto_off_preset {
+            // Timers don't survive a preset switch; the toggle keeps its state.
+            self.auto_off = [None; NUM_BUTTONS];
+            self.auto_off_preset = active_preset;
+        }
+        let Some(preset) = config.presets.get(active_preset as usize) else {
+            return;
+        };
+        let bpm = self.tempo(config);
+
+        for i in 0..NUM_BUTTONS {
+            let auto_off = preset
+                .buttons
+                .get(i)
+                .filter(|b| matches!(b.mode, ButtonMode::Toggle))
+                .and_then(|_| self.exts.button(active_preset as usize, i))
+                .and_then(|ext| ext.auto_off);
+            let Some(auto_off) = auto_off else {
+                self.auto_off[i] = None;
+                continue;
+            };
+            if !self.ctrl.button_states()[i] {
+                if self.auto_off[i].take().is_some() {
+                    result.rings_changed = true;
+                }
+                continue;
+            }
+            let timer = self.auto_off[i].get_or_insert(AutoOffTimer {
+                started_ms: None,
+                length_ms: auto_off.length.as_ms(bpm).max(1),
+                countdown: auto_off.countdown,
+                fill: 12,
+            });
+            let Some(now_ms) = now_ms else {
+                continue;
+            };
+            let started = *timer.started_ms.get_or_insert(now_ms);
+            let elapsed = now_ms.wrapping_sub(started);
+            if elapsed >= timer.length_ms {
+                // Expired: toggle off through the Controller so the off actions fire
+                self.auto_off[i] = None;
+                for edge in [LpEdge::Activate, LpEdge::Deactivate] {
+                    let r = self.ctrl.process(
+                        CtrlEvent::ButtonEdge {
+                            index: i as u8,
+                            edge,
+                        },
+                        now_ms,
+                        config,
+                    );
+                    self.merge(&r, result);
+                }
+                result.leds_changed = true;
+                continue;
+            }
+            let remaining = (timer.length_ms - elapsed) as u64;
+            let fill = (remaining * 12).div_ceil(timer.length_ms as u64) as u8;
+            if timer.countdown && fill != timer.fill {
+                result.rings_changed = true;
+            }
+            timer.fill = fill;
+        }
+    }
+
+    /// Start or stop auto-repeat for a button edge. Presses consumed by the glue
+    /// (panic, preview confirm) don't repeat.
+    fn arm_repeat(&mut self, config: &Config, i: usize, edge: Edge, consumed: bool, now_ms: u32) {
+        self.repeat[i] = match edge {
+            Edge::Activate if !consumed => self.repeat_ext(config, i).map(|r| RepeatTimer {
+                last_ms: now_ms,
+                wait_ms: r.delay_ms as u32,
+            }),
+            _ => None,
+        };
+    }
+
+    /// Repeat settings of a button in the active preset, if it is eligible.
+    fn repeat_ext(&self, config: &Config, i: usize) -> Option<Repeat> {
+        let active_preset = self.ctrl.active_preset() as usize;
+        config
+            .presets
+            .get(active_preset)
+            .and_then(|p| p.buttons.get(i))
+            .filter(|b| matches!(b.mode, ButtonMode::Momentary) && b.on_long_press.is_empty())
+            .and_then(|_| self.exts.button(active_preset, i))
+            .and_then(|ext| ext.repeat)
+    }
+
+    /// Re-fire the press of held repeat buttons once their wait has elapsed.
+    fn repeat_tick(&mut self, config: &Config, now_ms: u32, result: &mut HandleResult) {
+        for i in 0..NUM_BUTTONS {
+            let Some(timer) = self.repeat[i] else {
+                continue;
+            };
+            if now_ms.wrapping_sub(timer.last_ms) < timer.wait_ms {
+                continue;
+            }
+            // Re-checked every time: a repeated PresetNext lands on a preset
+            // where this button may not repeat.
+            let Some(repeat) = self.repeat_ext(config, i) else {
+                self.repeat[i] = None;
+                continue;
+            };
+            self.repeat[i] = Some(RepeatTimer {
+                last_ms: now_ms,
+                wait_ms: (repeat.rate_ms as u32).max(1),
+            });
+
+            let song_step = config
+                .presets
+                .get(self.ctrl.active_preset() as usize)
+                .and_then(|p| p.buttons.get(i))
+                .and_then(|b| nav_step(&b.on_press));
+            match song_step {
+                Some(step) if self.song_hold[i].is_some() => {
+                    self.apply_song_step(step, config, now_ms, result);
+                }
+                _ => {
+                    // Release and press again; the button stays down afterwards.
+                    for edge in [LpEdge::Deactivate, LpEdge::Activate] {
+                        let r = self.ctrl.process(
+                            CtrlEvent::ButtonEdge {
+                                index: i as u8,
+                                edge,
+                            },
+                            now_ms,
+                            config,
+                        );
+                        self.merge(&r, result);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Hybrid toggles: remember the press, and on a release after the hold
+    /// threshold toggle back so the button acted momentarily.
+    fn hybrid_edge(
+        &mut self,
+        config: &Config,
+        i: usize,
+        edge: Edge,
+        now_ms: u32,
+        result: &mut HandleResult,
+    ) {
+        match edge {
+            Edge::Activate => {
+                let preset = self.ctrl.active_preset();
+                self.hybrid_hold[i] = config
+                    .presets
+                    .get(preset as usize)
+                    .and_then(|p| p.buttons.get(i))
+                    .filter(|b| matches!(b.mode, ButtonMode::Toggle))
+                    .and_then(|_| self.exts.button(preset as usize, i))
+                    .and_then(|ext| ext.hybrid)
+                    .map(|h| HybridPress {
+                        since_ms: now_ms,
+                        hold_ms: h.hold_ms as u32,
+                        preset,
+                    });
+            }
+            Edge::Deactivate => {
+                let Some(press) = self.hybrid_hold[i].take() else {
+                    return;
+                };
+                if press.preset != self.ctrl.active_preset()
+                    || now_ms.wrapping_sub(press.since_ms) < press.hold_ms
+                {
+                    return;
+                }
+                for edge in [LpEdge::Activate, LpEdge::Deactivate] {
+                    let r = self.ctrl.process(
+                        CtrlEvent::ButtonEdge {
+                            index: i as u8,
+                            edge,
+                        },
+                        now_ms,
+                        config,
+                    );
+                    self.merge(&r, result);
+                }
+                result.leds_changed = true;
+            }
+        }
+    }
+
+    /// Update toggle, cycle and encoder state from incoming feedback (see
+    /// `Settings::sync_cc`/`sync_notes`). Returns true if the message addressed
+    /// synced state, changed or not.
+    ///
+    /// Sync never emits MIDI: toggles and cycle buttons are pressed through the
+    /// Controller with their actions discarded, so feedback can't bounce back
+    /// to the device. Synced state is saved like a press or turn.
+    fn sync_state(&mut self, config: &Config, raw: &[u8], result: &mut HandleResult) -> bool {
+        use midi_controller::engine::ReactiveResult;
+
+        let [status, data1, data2] = match raw {
+            [s, d1, d2, ..] => [*s, *d1, *d2],
+            _ => return false,
+        };
+        let channel = (status & 0x0F) + 1;
+        let is_cc = status & 0xF0 == 0xB0;
+        let Some(preset) = config.presets.get(self.ctrl.active_preset() as usize) else {
+            return false;
+        };
+
+        // Toggles to set: `listen_cc` matches come from the Controller's
+        // reactive result, notes match the button's own Note On.
+        let mut targets: heapless::Vec<(usize, bool), NUM_BUTTONS> = heapless::Vec::new();
+        match status & 0xF0 {
+            0xB0 if self.settings.sync_cc => {
+                if let Some(ReactiveResult::Trigger(idx, active)) = result.reactive_led {
+                    targets.push((idx, active)).ok();
+                }
+            }
+            0x80 | 0x90 if self.settings.sync_notes => {
+                let on = status & 0xF0 == 0x90 && data2 > 0;
+                for (i, btn) in preset.buttons.iter().enumerate().take(NUM_BUTTONS) {
+                    let sends_note = matches!(
+                        btn.on_press.first(),
+                        Some(Action::Midi { data, .. })
+                            if data[0] == 0x90 | (status & 0x0F) && data[1] == data1
+                    );
+                    if sends_note {
+                        targets.push((i, on)).ok();
+                    }
+                }
+            }
+            _ => return false,
+        }
+
+        let mut matched = false;
+        for (i, desired) in targets {
+            let is_toggle = preset
+                .buttons
+                .get(i)
+                .is_some_and(|b| matches!(b.mode, ButtonMode::Toggle));
+            if !is_toggle || i >= NUM_BUTTONS {
+                continue;
+            }
+            matched = true;
+            if self.ctrl.button_states()[i] != desired {
+                self.press_silently(config, i, result);
+            }
+        }
+
+        if is_cc {
+            for (i, btn) in preset.buttons.iter().enumerate().take(NUM_BUTTONS) {
+                let reverse = btn.on_press.iter().find_map(|a| match a {
+                    Action::CcCycle {
+                        cc,
+                        channel: ch,
+                        reverse,
+                    } if *cc == data1 && *ch == channel => Some(*reverse),
+                    _ => None,
+                });
+                let len = btn.cycle_values.len();
+                let pos = btn.cycle_values.iter().position(|&v| v == data2);
+                let (Some(reverse), Some(pos)) = (reverse, pos) else {
+                    continue;
+                };
+                matched = true;
+                // The cycle index is the position sent by the next press: one
+                // past the reported value, in the cycle's direction
+                let index = self.ctrl.snapshot_store().current().cycle_index[i] as usize % len;
+                let presses = if reverse {
+                    let target = (pos + len - 1) % len;
+                    (index + len - target) % len
+                } else {
+                    let target = (pos + 1) % len;
+                    (target + len - index) % len
+                };
+                for _ in 0..presses {
+                    self.press_silently(config, i, result);
+                }
+            }
+
+            for (i, enc) in preset.encoders.iter().enumerate() {
+                if let EncoderAction::Cc {
+                    cc, channel: ch, ..
+                } = enc.action
+                {
+                    if ch == channel && cc == data1.into() {
+                        matched = true;
+                        if self.ctrl.encoder_values().get(i) != Some(&data2) {
+                            // Saved like a turn: the debounced state write
+                            // absorbs streaming feedback (a modeler's volume knob)
+                            self.ctrl.set_encoder_value(i, data2);
+                            result.leds_changed = true;
+                        }
+                    }
+                }
+            }
+        }
+        matched
+    }
+
+    /// Press and release button `i` through the Controller without sending its
+    /// actions' MIDI.
+    fn press_silently(&mut self, config: &Config, i: usize, result: &mut HandleResult) {
+        for edge in [LpEdge::Activate, LpEdge::Deactivate] {
+            let mut r = self.ctrl.process(
+                CtrlEvent::ButtonEdge {
+                    index: i as u8,
+                    edge,
+                },
+                0,
+                config,
+            );
+            r.midi.clear();
+            self.merge(&r, result);
+        }
+        result.leds_changed = true;
+    }
+
+    /// Send a button's semantic press actions through the selected profile.
+    /// Actions the profile doesn't map are skipped.
+    fn run_semantic(&self, preset: u8, i: usize, result: &mut HandleResult) {
+        use midi_controller::routing::MidiPort;
+
+        let Some(profile) = self
+            .settings
+            .profile
+            .and_then(|slot| self.profiles.get(slot as usize))
+        else {
+            return;
+        };
+        let Some(ext) = self.exts.button(preset as usize, i) else {
+            return;
+        };
+        for action in &ext.semantic {
+            if let Some((data, len)) = profile.resolve(action) {
+                result
+                    .midi
+                    .push(MidiStep::Send(data, len, MidiPort::all()))
+                    .ok();
+            }
+        }
+    }
+
+    /// Report a button's LFO control; the button's ring is redrawn when it stops.
+    fn run_lfo(&self, preset: u8, i: usize, result: &mut HandleResult) {
+        if let Some(control) = self.exts.button(preset as usize, i).and_then(|b| b.lfo) {
+            result.lfo = Some((control, i as u8));
+            result.rings_changed = true;
+        }
+    }
+
+    /// Report a snapshot button press.
+    fn run_snapshot(&self, preset: u8, i: usize, result: &mut HandleResult) {
+        result.snapshot |= self
+            .exts
+            .button(preset as usize, i)
+            .is_some_and(|b| b.snapshot);
+    }
+
+    /// Send a button's MMC / Song Position press actions.
+    fn run_transport(&self, preset: u8, i: usize, result: &mut HandleResult) {
+        use crate::transport::{mmc_command, mmc_locate};
+
+        let Some(ext) = self.exts.button(preset as usize, i) else {
+            return;
+        };
+        let device = self.settings.mmc_device;
+        for action in &ext.transport {
+            match *action {
+                TransportAction::Mmc(command) => {
+                    result
+                        .midi
+                        .push(MidiStep::SysEx(mmc_command(device, command)))
+                        .ok();
+                }
+                TransportAction::Locate {
+                    hours,
+                    minutes,
+                    seconds,
+                    frames,
+                } => {
+                    let msg = mmc_locate(device, hours, minutes, seconds, frames);
+                    result.midi.push(MidiStep::SysEx(msg)).ok();
+                }
+                TransportAction::SongPosition(position) => {
+                    result.song_position = Some(position);
+                    result.clock_running = Some(true);
+                }
+            }
+        }
+    }
+
+    /// Run the active preset's transport triggers matching `event`.
+    fn run_transport_triggers(
+        &mut self,
+        config: &Config,
+        event: TransportEvent,
+        result: &mut HandleResult,
+    ) {
+        let Some(ext) = self.exts.get(self.ctrl.active_preset() as usize) else {
+            return;
+        };
+        let triggers = ext.transport_triggers.clone();
+        for trigger in triggers.iter().filter(|t| t.on.matches(&event)) {
+            self.run_actions(config, &trigger.actions, result);
+        }
+    }
+
+    /// Evaluate the conditional press of a button in the active preset.
+    /// Returns the preset and which branch to run, before the press changes state.
+    fn eval_conditional(&self, i: usize) -> Option<(u8, bool)> {
+        let preset = self.ctrl.active_preset();
+        let cond = self.exts.button(preset as usize, i)?.conditional.as_ref()?;
+        let states = self.ctrl.button_states();
+        let met = match cond.condition {
+            Condition::ButtonActive(b) => states.get(b as usize).copied().unwrap_or(false),
+            Condition::ButtonInactive(b) => !states.get(b as usize).copied().unwrap_or(true),
+            Condition::PresetIs(p) => preset == p,
+            Condition::EncoderAtLeast { index, value } => self
+                .ctrl
+                .encoder_values()
+                .get(index as usize)
+                .is_some_and(|&v| v >= value),
+        };
+        Some((preset, met))
+    }
+
+    /// Run one branch of a conditional press.
+    fn run_conditional(
+        &mut self,
+        config: &Config,
+        preset: u8,
+        i: usize,
+        then: bool,
+        result: &mut HandleResult,
+    ) {
+        let Some(cond) = self
+            .exts
+            .button(preset as usize, i)
+            .and_then(|ext| ext.conditional.clone())
+        else {
+            return;
+        };
+        let actions = if then { &cond.then } else { &cond.otherwise };
+        self.run_actions(config, actions, result);
+    }
+
+    /// Run extension actions. MIDI goes to all ports (DIN subject to the global
+    /// enable); preset actions switch like the Controller's.
+    fn run_actions(&mut self, config: &Config, actions: &[Action], result: &mut HandleResult) {
+        use midi_controller::routing::MidiPort;
+
+        for action in actions {
+            let target = match action {
+                Action::Midi { data, len } => {
+                    result
+                        .midi
+                        .push(MidiStep::Send(*data, *len as usize, MidiPort::all()))
+                        .ok();
+                    None
+                }
+                Action::Delay(ms) => {
+                    result.midi.push(MidiStep::Delay(*ms)).ok();
+                    None
+                }
+                Action::PresetSelect(idx) => Some(*idx),
+                Action::PresetNext => scroll_target(config, self.ctrl.active_preset(), true),
+                Action::PresetPrev => scroll_target(config, self.ctrl.active_preset(), false),
+                _ => None,
+            };
+            if let Some(target) = target.filter(|&t| t != self.ctrl.active_preset()) {
+                let r = self.ctrl.select_preset(target, config);
+                self.merge(&r, result);
+            }
+        }
+    }
+
+    /// Looper role of a button in the active preset, with the preset's looper setup.
+    fn looper_button(&self, i: usize) -> Option<(LooperConfig, LooperRole)> {
+        let ext = self.exts.get(self.ctrl.active_preset() as usize)?;
+        Some((ext.looper?, ext.button(i)?.looper?))
+    }
+
+    /// Route a looper button press to the looper. Returns true if consumed.
+    fn looper_edge(
+        &mut self,
+        i: usize,
+        edge: Edge,
+        now_ms: u32,
+        result: &mut HandleResult,
+    ) -> bool {
+        let Some((setup, role)) = self.looper_button(i) else {
+            return false;
+        };
+        if edge == Edge::Activate {
+            let was_pending = self.looper.is_pending();
+            if let Some(t) = self.looper.press(&setup, role, now_ms) {
+                emit_looper(t, result);
+            } else if was_pending != self.looper.is_pending() {
+                // Queued for (or cancelled before) the next bar
+                result.rings_changed = true;
+            }
+        }
+        true
+    }
+
+    /// A tick of the internal clock (24 ppqn). Ignored while external clock
+    /// arrives.
+    pub fn internal_clock_tick(&mut self, now_ms: u32) -> HandleResult {
+        let mut result = HandleResult::default();
+        if self.clock.internal_tick(now_ms) {
+            self.clock_tick(now_ms, &mut result);
+        }
+        result
+    }
+
+    /// A tick of the clock in charge: advances the looper's bar grid and the LFO.
+    fn clock_tick(&mut self, now_ms: u32, result: &mut HandleResult) {
+        result.clock_tick = true;
+        let setup = self
+            .exts
+            .get(self.ctrl.active_preset() as usize)
+            .and_then(|ext| ext.looper);
+        if let Some(setup) = setup {
+            if let Some(t) = self.looper.clock_tick(&setup, now_ms) {
+                emit_looper(t, result);
+            }
+        }
+    }
+
+    /// Fire a bar-synced looper transition if the clock stopped before the bar.
+    fn looper_tick(&mut self, now_ms: u32, result: &mut HandleResult) {
+        if !self.looper.is_pending() {
+            return;
+        }
+        let setup = self
+            .exts
+            .get(self.ctrl.active_preset() as usize)
+            .and_then(|ext| ext.looper);
+        match setup {
+            Some(setup) => {
+                if let Some(t) = self.looper.tick(&setup, now_ms) {
+                    emit_looper(t, result);
+                }
+            }
+            None => {
+                // Switched to a preset without a looper
+                self.looper.cancel_pending();
+                result.rings_changed = true;
+            }
+        }
+    }
+
+    /// Ring of a looper button: each state has its own colour and animation,
+    /// and a transition waiting for the bar blinks.
+    fn looper_ring(&self, i: usize) -> Option<RingAnimation> {
+        let (_, role) = self.looper_button(i)?;
+        let state = self.looper.state();
+        let (color, modifier) = match role {
+            LooperRole::Main => match state {
+                LooperState::Empty => (Color::White, Modifier::Glow),
+                LooperState::Recording => (Color::Red, Modifier::Pulse),
+                LooperState::Playing => (Color::Green, Modifier::Solid),
+                LooperState::Overdubbing => (Color::Orange, Modifier::Pulse),
+                LooperState::Stopped => (Color::Green, Modifier::Glow),
+            },
+            LooperRole::Stop if state.is_running() => (Color::Red, Modifier::Solid),
+            LooperRole::UndoRedo if self.looper.can_undo() => (Color::Yellow, Modifier::Solid),
+            LooperRole::UndoRedo if self.looper.can_redo() => (Color::Cyan, Modifier::Solid),
+            LooperRole::Clear if state != LooperState::Empty => (Color::White, Modifier::Glow),
+            _ => return Some(RingAnimation::off()),
+        };
+        let modifier = if role == LooperRole::Main && self.looper.is_pending() {
+            Modifier::Blink
+        } else {
+            modifier
+        };
+        Some(RingAnimation {
+            renderer: Renderer::Fill(rgb8_to_rgb(color_to_rgb(&color)), 12),
+            modifier,
+        })
+    }
+
+    /// Current tempo: last tap tempo, else the global config BPM.
+    fn tempo(&self, config: &Config) -> u16 {
+        if self.bpm > 0 {
+            self.bpm
+        } else {
+            config.global.bpm
+        }
+    }
+
+    /// Fire pending long-press song navigation for held buttons.
+    fn song_nav_tick(&mut self, config: &Config, now_ms: u32, result: &mut HandleResult) {
+        for i in 0..NUM_BUTTONS {
+            let Some(since) = self.song_hold[i] else {
+                continue;
+            };
+            if self.song_long_fired[i] || now_ms.wrapping_sub(since) < LONG_PRESS_MS {
+                continue;
+            }
+            self.song_long_fired[i] = true;
+            let step = config
+                .presets
+                .get(self.ctrl.active_preset() as usize)
+                .and_then(|p| p.buttons.get(i))
+                .and_then(|b| nav_step(&b.on_long_press));
+            if let Some(step) = step {
+                self.apply_song_step(step, config, now_ms, result);
+            }
+        }
+    }
+
+    fn apply_song_step(
+        &mut self,
+        step: SongStep,
+        config: &Config,
+        now_ms: u32,
+        result: &mut HandleResult,
+    ) {
+        let Some(slot) = self.setlist.step(self.active_song, step) else {
+            return;
+        };
+        let Some(song) = self.setlist.get(slot).cloned() else {
+            return;
+        };
+        self.active_song = Some(slot);
+        result.song_changed = true;
+
+        if song.preset != self.ctrl.active_preset() {
+            let r = self.ctrl.select_preset(song.preset, config);
+            self.merge(&r, result);
+        }
+        // Engage the scene button unless it is already on.
+        if let Some(scene) = song.scene.filter(|&b| (b as usize) < NUM_BUTTONS) {
+            if !self.ctrl.button_states()[scene as usize] {
+                for edge in [LpEdge::Activate, LpEdge::Deactivate] {
+                    let r = self.ctrl.process(
+                        CtrlEvent::ButtonEdge { index: scene, edge },
+                        now_ms,
+                        config,
+                    );
+                    self.merge(&r, result);
+                }
+            }
+        }
+    }
+
     fn merge(&self, ctrl_result: &Output, result: &mut HandleResult) {
         for step in &ctrl_result.midi {
             match step {
@@ -336,6 +1635,45 @@ impl PeHandler {
     }
 }
 
+/// Send a looper transition's message and flag the state change.
+fn emit_looper(t: Transition, result: &mut HandleResult) {
+    use midi_controller::routing::MidiPort;
+
+    if let Some(msg) = t.message {
+        result
+            .midi
+            .push(MidiStep::Send(msg, 3, MidiPort::all()))
+            .ok();
+    }
+    result.looper_changed = true;
+    result.rings_changed = true;
+}
+
+/// Next non-empty preset from `from` in scroll direction, wrapping around.
+fn scroll_target(config: &Config, from: u8, clockwise: bool) -> Option<u8> {
+    let len = config.presets.len();
+    (1..=len)
+        .map(|offset| {
+            if clockwise {
+                (from as usize + offset) % len
+            } else {
+                (from as usize + len - offset % len) % len
+            }
+        })
+        .find(|&i| !config.presets[i].name.is_empty())
+        .map(|i| i as u8)
+}
+
+/// Song navigation step for an action list consisting of a single
+/// `PresetNext`/`PresetPrev`.
+fn nav_step(actions: &[Action]) -> Option<SongStep> {
+    match actions {
+        [Action::PresetNext] => Some(SongStep::Next),
+        [Action::PresetPrev] => Some(SongStep::Prev),
+        _ => None,
+    }
+}
+
 fn button_edge(events: &[InputEvent], i: usize) -> Option<Edge> {
     events.iter().find_map(|e| match (e, i) {
         (InputEvent::ButtonA(e), 0) => Some(*e),
@@ -3,18 +3,110 @@
 //! Extracted from the USB IRQ handler to keep interrupt context thin.
 //! The firmware calls these functions and dispatches the results.
 
-use crate::persist::PersistCommand;
+use crate::bank::BANK_SELECT_RESOURCE;
+use crate::persist::{
+    PersistCommand, ReplyTo, SetStatus, UploadCommand, BACKUP_RESOURCE, UPLOAD_RESOURCE,
+};
 use defmt::debug;
 use heapless::Vec;
 use midi_controller::config;
 use midi_controller::property_exchange;
 
+/// MUID this device replies from.
+pub const DEVICE_MUID: [u8; 4] = [0x01, 0x02, 0x03, 0x04];
+
+/// Offset of the header length in a PE message (after the 14-byte CI
+/// header and request ID). The header follows it; its first byte is the
+/// status of a reply.
+const HEADER_LEN_OFFSET: usize = 15;
+
+/// Offset of the first field after the PE header (the chunk count), taken
+/// from the message's own header length like the crate's parsers do.
+fn after_header(msg: &[u8]) -> Option<usize> {
+    let len =
+        *msg.get(HEADER_LEN_OFFSET)? as usize | (*msg.get(HEADER_LEN_OFFSET + 1)? as usize) << 7;
+    Some(HEADER_LEN_OFFSET + 2 + len)
+}
+
+/// Overwrite the status byte of a reply built with `PeStatus::Ok`
+/// (`PeStatus` has no codes for flash failures or a busy device).
+fn set_status(reply: &mut [u8], status: SetStatus) {
+    if after_header(reply).is_some_and(|end| end > HEADER_LEN_OFFSET + 2) {
+        reply[HEADER_LEN_OFFSET + 2] = status as u8;
+    }
+}
+
 /// Result of handling a PE Set Property message.
 pub struct SetResult {
     /// Persist command to execute (save preset, system command, etc.)
     pub command: Option<PersistCommand>,
-    /// ACK reply SysEx to send back via USB.
-    pub reply: Vec<u8, 256>,
+    /// Reply SysEx to send back via USB right away. None when the persist
+    /// task replies once the command has been applied.
+    pub reply: Option<Vec<u8, 256>>,
+}
+
+/// Build a PE Set reply carrying `status`.
+pub fn build_reply(to: ReplyTo, status: SetStatus) -> Vec<u8, 256> {
+    let reply_data = property_exchange::build_set_reply(
+        DEVICE_MUID,
+        to.muid,
+        to.request_id,
+        property_exchange::PeStatus::Ok,
+    );
+    let mut reply = Vec::new();
+    reply.extend_from_slice(&reply_data).ok();
+    set_status(&mut reply, status);
+    reply
+}
+
+/// Build chunk `chunk` (1-based) of a `chunks`-chunk PE Get reply.
+pub fn build_get_chunk(
+    to: ReplyTo,
+    resource: u8,
+    chunks: u16,
+    chunk: u16,
+    body: &[u8],
+) -> Vec<u8, { crate::MAX_PE_REPLY_SIZE }> {
+    let mut reply = property_exchange::build_get_reply(
+        DEVICE_MUID,
+        to.muid,
+        to.request_id,
+        resource,
+        property_exchange::PeStatus::Ok,
+        body,
+    );
+    // build_get_reply always announces a single chunk
+    let fields = [
+        (chunks & 0x7F) as u8,
+        ((chunks >> 7) & 0x7F) as u8,
+        (chunk & 0x7F) as u8,
+        ((chunk >> 7) & 0x7F) as u8,
+    ];
+    if let Some(bytes) = after_header(&reply).and_then(|pos| reply.get_mut(pos..pos + 4)) {
+        bytes.copy_from_slice(&fields);
+    }
+    reply
+}
+
+/// Build a PE Get reply with no body carrying `status` (a [`SetStatus`]
+/// code, as `PeStatus` has none for a busy device).
+pub fn build_get_error(
+    to: ReplyTo,
+    resource: u8,
+    status: SetStatus,
+) -> Vec<u8, { crate::MAX_PE_REPLY_SIZE }> {
+    let mut reply = build_get_chunk(to, resource, 1, 1, &[]);
+    set_status(&mut reply, status);
+    reply
+}
+
+/// Chunk number and chunk count of a Set inquiry.
+fn set_chunk(sysex: &[u8]) -> Option<(u16, u16)> {
+    let field = |pos: usize| -> Option<u16> {
+        Some(*sysex.get(pos)? as u16 | (*sysex.get(pos + 1)? as u16) << 7)
+    };
+    let chunks_pos = after_header(sysex)?;
+    Some((field(chunks_pos + 2)?, field(chunks_pos)?))
 }
 
 /// Handle a PE Set Property SysEx message.
@@ -25,51 +117,75 @@ pub fn handle_set(sysex: &[u8]) -> Option<SetResult> {
     }
 
     let data = property_exchange::extract_set_property(sysex)?;
+    let reply_to = ReplyTo {
+        muid: property_exchange::source_muid(sysex),
+        request_id: property_exchange::request_id(sysex),
+    };
+    let reject = || SetResult {
+        command: None,
+        reply: Some(build_reply(reply_to, SetStatus::FormatError)),
+    };
 
     let mut decoded = [0u8; crate::MAX_PRESET_SIZE];
     let dec_len = property_exchange::decode_mcoded7(data.body, &mut decoded);
 
-    let command = if data.resource == config::SYSTEM_COMMAND_RESOURCE {
-        // System command (reboot, bootloader, factory reset)
-        if dec_len > 0 {
-            config::SystemCommand::from_byte(decoded[0]).map(|cmd| {
-                debug!("PE System command: {}", cmd as u8);
-                match cmd {
-                    config::SystemCommand::Reboot => PersistCommand::Reboot,
-                    config::SystemCommand::Bootloader => PersistCommand::Bootloader,
-                    config::SystemCommand::FactoryReset => PersistCommand::EraseAll,
-                }
-            })
-        } else {
-            None
+    if data.resource == config::SYSTEM_COMMAND_RESOURCE {
+        // System command (reboot, bootloader, factory reset). Acknowledged
+        // right away: the device resets once it has been carried out.
+        let Some(cmd) = config::SystemCommand::from_byte(decoded[0]).filter(|_| dec_len > 0) else {
+            return Some(reject());
+        };
+        debug!("PE System command: {}", cmd as u8);
+        let command = match cmd {
+            config::SystemCommand::Reboot => PersistCommand::Reboot,
+            config::SystemCommand::Bootloader => PersistCommand::Bootloader,
+            config::SystemCommand::FactoryReset => PersistCommand::EraseAll,
+        };
+        return Some(SetResult {
+            command: Some(command),
+            reply: Some(build_reply(reply_to, SetStatus::Ok)),
+        });
+    }
+
+    let command = if data.resource == UPLOAD_RESOURCE {
+        // Upload transaction control (begin, commit, abort)
+        let Some(cmd) = UploadCommand::from_byte(decoded[0]).filter(|_| dec_len > 0) else {
+            return Some(reject());
+        };
+        debug!("PE Upload command: {}", cmd as u8);
+        PersistCommand::Upload(cmd, reply_to)
+    } else if data.resource == BANK_SELECT_RESOURCE {
+        // Bank switch: one byte, the bank to make active. The Ok reply comes
+        // before the device reboots into it
+        if dec_len != 1 {
+            return Some(reject());
         }
-    } else if data.resource == config::GLOBAL_CONFIG_RESOURCE {
-        debug!("PE Set GlobalConfig body len={}", dec_len);
-        Vec::from_slice(&decoded[..dec_len])
-            .ok()
-            .map(|blob| PersistCommand::SavePreset(config::GLOBAL_CONFIG_RESOURCE, blob))
+        debug!("PE Bank select: {}", decoded[0]);
+        PersistCommand::SelectBank(decoded[0], Some(reply_to))
+    } else if data.resource == BACKUP_RESOURCE {
+        // One chunk of a backup image to restore
+        let Some((chunk, chunks)) = set_chunk(sysex).filter(|&(n, of)| n >= 1 && n <= of) else {
+            return Some(reject());
+        };
+        let Ok(blob) = Vec::from_slice(&decoded[..dec_len]) else {
+            return Some(reject());
+        };
+        debug!("PE Restore chunk {}/{}", chunk, chunks);
+        PersistCommand::Restore(chunk, chunks, blob, reply_to)
     } else {
         debug!(
-            "PE Set Property preset={} body len={}",
-            data.resource,
-            data.body.len()
+            "PE Set Property resource={} body len={}",
+            data.resource, dec_len
         );
-        Vec::from_slice(&decoded[..dec_len])
-            .ok()
-            .map(|blob| PersistCommand::SavePreset(data.resource, blob))
+        let Ok(blob) = Vec::from_slice(&decoded[..dec_len]) else {
+            return Some(reject());
+        };
+        PersistCommand::SavePreset(data.resource, blob, Some(reply_to))
     };
 
-    // Build ACK reply
-    let req_id = property_exchange::request_id(sysex);
-    let src_muid = property_exchange::source_muid(sysex);
-    let reply_data = property_exchange::build_set_reply(
-        [0x01, 0x02, 0x03, 0x04],
-        src_muid,
-        req_id,
-        property_exchange::PeStatus::Ok,
-    );
-    let mut reply = Vec::new();
-    reply.extend_from_slice(&reply_data).ok();
-
-    Some(SetResult { command, reply })
+    // The persist task replies once the data is validated and stored
+    Some(SetResult {
+        command: Some(command),
+        reply: None,
+    })
 }
@@ -2,13 +2,145 @@
 
 pub const PERSIST_CAPACITY: usize = 32;
 
+/// PE resource ID of the upload transaction control (one-byte body).
+pub const UPLOAD_RESOURCE: u8 = 0x65;
+
+/// PE resource ID of the full device backup image (see `backup`). A Get
+/// streams it in chunks; chunked Sets restore it.
+pub const BACKUP_RESOURCE: u8 = 0x66;
+
+/// PE resource ID of the runtime state image (EEPROM layout). Carried in
+/// backups; a Set is written to the EEPROM and applied at the next boot.
+pub const STATE_RESOURCE: u8 = 0x67;
+
+/// Upload transaction control. Between `Begin` and `Commit`, uploaded blobs
+/// are staged in flash and the live config is left untouched; `Commit`
+/// switches to all of them, `Abort` drops them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadCommand {
+    Begin = 0x01,
+    Commit = 0x02,
+    Abort = 0x03,
+}
+
+impl UploadCommand {
+    pub fn from_byte(b: u8) -> Option<Self> {
+        match b {
+            0x01 => Some(UploadCommand::Begin),
+            0x02 => Some(UploadCommand::Commit),
+            0x03 => Some(UploadCommand::Abort),
+            _ => None,
+        }
+    }
+}
+
+/// Addressing of the PE Set reply owed for a command: the initiator's MUID
+/// and the request ID to echo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplyTo {
+    pub muid: [u8; 4],
+    pub request_id: u8,
+}
+
+/// Status byte of a PE Set reply. 0x00–0x03 are the `PeStatus` codes; the
+/// rest report flash failures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetStatus {
+    Ok = 0x00,
+    /// Resource index outside every known range.
+    OutOfRange = 0x01,
+    /// Body did not deserialize, or the command is not valid now.
+    FormatError = 0x02,
+    /// Flash is full.
+    StorageFull = 0x04,
+    /// Flash write failed.
+    FlashError = 0x05,
+    /// The persist task's queue is full; nothing was done, retry.
+    Busy = 0x06,
+}
+
+/// Tempo and clock state changed at runtime (tap tempo, clock start/stop).
+///
+/// Stored apart from the uploaded global config and applied on top of it at
+/// boot. Uploading a global config clears it, so the uploaded values hold
+/// until the next runtime change.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RuntimeOverride {
+    pub bpm: Option<u16>,
+    pub clock_running: Option<bool>,
+}
+
+impl RuntimeOverride {
+    /// From the stored config values (0 = not overridden).
+    pub fn from_values(bpm: Option<u16>, clock: Option<u16>) -> Self {
+        Self {
+            bpm: bpm.filter(|&b| b != 0),
+            clock_running: match clock {
+                Some(1) => Some(false),
+                Some(2) => Some(true),
+                _ => None,
+            },
+        }
+    }
+
+    /// Config values to store: tempo, clock state.
+    pub fn to_values(self) -> (u16, u16) {
+        let clock = match self.clock_running {
+            None => 0,
+            Some(false) => 1,
+            Some(true) => 2,
+        };
+        (self.bpm.unwrap_or(0), clock)
+    }
+
+    pub fn apply(&self, gc: &mut midi_controller::config::GlobalConfig) {
+        if let Some(bpm) = self.bpm {
+            gc.bpm = bpm;
+        }
+        if let Some(running) = self.clock_running {
+            gc.midi_clock = running;
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 #[allow(clippy::large_enum_variant)]
 pub enum PersistCommand {
-    /// Save a preset or global config blob (resource index, data).
-    SavePreset(u8, heapless::Vec<u8, { crate::MAX_PRESET_SIZE }>),
+    /// Save a preset or global config blob (resource index, data, reply
+    /// owed once it is stored).
+    SavePreset(
+        u8,
+        heapless::Vec<u8, { crate::MAX_PRESET_SIZE }>,
+        Option<ReplyTo>,
+    ),
+    /// Begin, commit or abort an upload transaction.
+    Upload(UploadCommand, ReplyTo),
+    /// Answer a PE Get from flash (resource index, request to answer).
+    Get(u8, ReplyTo),
+    /// Stream a backup image as Get reply chunks.
+    Backup(ReplyTo),
+    /// One chunk of a backup image to restore (chunk number, chunk count,
+    /// data, reply owed once it is staged or, for the last one, applied).
+    Restore(
+        u16,
+        u16,
+        heapless::Vec<u8, { crate::MAX_PRESET_SIZE }>,
+        ReplyTo,
+    ),
+    /// Switch to a preset bank and reboot into it (reply owed once the
+    /// switch is stored, none from config mode).
+    SelectBank(u8, Option<ReplyTo>),
+    /// Store a preset's defaults (preset index, state captured by a snapshot)
+    /// and save its blob.
+    SaveDefaults(u8, midi_controller::config::InitialState),
     /// Persist the active preset index.
     SaveActivePreset(u8),
+    /// Persist the active setlist song slot.
+    SaveActiveSong(u8),
+    /// Persist a tapped tempo.
+    SaveTempo(u16),
+    /// Persist the clock running state.
+    SaveClockRunning(bool),
     /// Persist runtime state to EEPROM.
     SaveState(heapless::Vec<u8, 128>),
     /// Factory reset: erase all flash + EEPROM.
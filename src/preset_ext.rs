@@ -0,0 +1,156 @@
+//! Per-preset extensions: button behaviour the protocol crate's `Preset`
+//! cannot express (timed toggles, auto-repeat, conditional actions, device
+//! profile actions, looper control, LFO, DAW transport, ...).
+//!
+//! Each preset slot has a matching extension resource, uploaded and stored like
+//! the preset itself. A missing extension means stock behaviour.
+
+use heapless::Vec;
+use midi_controller::config::{Action, MAX_ACTIONS};
+use serde::{Deserialize, Serialize};
+
+use crate::lfo::{LfoConfig, LfoControl};
+use crate::looper::{LooperConfig, LooperRole};
+use crate::profile::SemanticAction;
+use crate::slots::{Slot, Slots};
+use crate::transport::{
+    TransportAction, TransportTrigger, MAX_TRANSPORT_ACTIONS, MAX_TRANSPORT_TRIGGERS,
+};
+
+/// Maximum number of semantic press actions per button.
+pub const MAX_SEMANTIC_ACTIONS: usize = 4;
+
+/// Number of footswitch buttons covered by an extension.
+pub const EXT_BUTTONS: usize = 6;
+
+/// Maximum number of preset extension slots (one per preset).
+pub const MAX_PRESET_EXTS: usize = 32;
+
+/// First PE resource ID of the preset extension slots (`0x20..0x3F`).
+pub const PRESET_EXT_RESOURCE_BASE: u8 = 0x20;
+
+/// Returns the preset index if `resource` addresses a preset extension slot.
+pub fn preset_ext_slot(resource: u8) -> Option<usize> {
+    let slot = resource.checked_sub(PRESET_EXT_RESOURCE_BASE)? as usize;
+    (slot < MAX_PRESET_EXTS).then_some(slot)
+}
+
+/// A time span given either absolutely or in beats of the current tempo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Length {
+    Millis(u32),
+    /// Quarter notes at the current BPM (e.g. 16 = four bars of 4/4).
+    Beats(u16),
+}
+
+impl Length {
+    /// Length in milliseconds. Beats fall back to 120 BPM when no tempo is set.
+    pub fn as_ms(&self, bpm: u16) -> u32 {
+        match *self {
+            Length::Millis(ms) => ms,
+            Length::Beats(beats) => {
+                let bpm = if bpm == 0 { 120 } else { bpm as u32 };
+                beats as u32 * 60_000 / bpm
+            }
+        }
+    }
+}
+
+/// Toggle that switches itself off again after `length`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutoOff {
+    pub length: Length,
+    /// Show the remaining time as a draining ring.
+    pub countdown: bool,
+}
+
+/// Re-fire the press action while the button is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Repeat {
+    /// Hold time before the first repeat.
+    pub delay_ms: u16,
+    /// Interval between repeats.
+    pub rate_ms: u16,
+}
+
+/// Tap-to-latch, hold-for-momentary: a tap toggles as usual, a press held for
+/// at least `hold_ms` reverts to the previous state on release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hybrid {
+    pub hold_ms: u16,
+}
+
+/// State check for a conditional press (button index 0=A..5=F).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Condition {
+    ButtonActive(u8),
+    ButtonInactive(u8),
+    PresetIs(u8),
+    /// Encoder value (0 = Vol, 1 = Gain) is at least `value`.
+    EncoderAtLeast {
+        index: u8,
+        value: u8,
+    },
+}
+
+/// Extra press actions chosen by a condition, evaluated before the press is
+/// processed. They run after the button's own `on_press`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConditionalPress {
+    pub condition: Condition,
+    pub then: Vec<Action, MAX_ACTIONS>,
+    pub otherwise: Vec<Action, MAX_ACTIONS>,
+}
+
+/// Extension settings for one button. Defaults to stock behaviour.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ButtonExt {
+    /// Only applies to `ButtonMode::Toggle` buttons.
+    pub auto_off: Option<AutoOff>,
+    /// Only applies to `ButtonMode::Momentary` buttons without a long-press action.
+    pub repeat: Option<Repeat>,
+    /// Only applies to `ButtonMode::Toggle` buttons.
+    pub hybrid: Option<Hybrid>,
+    pub conditional: Option<ConditionalPress>,
+    /// Press actions resolved through the active device profile, after `on_press`.
+    pub semantic: Vec<SemanticAction, MAX_SEMANTIC_ACTIONS>,
+    /// Looper button; replaces the button's own actions when the preset has
+    /// a `looper` setup.
+    pub looper: Option<LooperRole>,
+    /// Starts/stops the preset's `lfo`, after `on_press`.
+    pub lfo: Option<LfoControl>,
+    /// MMC / Song Position press actions, after `on_press`.
+    pub transport: Vec<TransportAction, MAX_TRANSPORT_ACTIONS>,
+    /// Saves the preset's current toggle states and encoder values as its
+    /// `defaults`, after `on_press`.
+    pub snapshot: bool,
+}
+
+/// Extension for one preset (postcard-serialized in its PE resource slot).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresetExt {
+    pub buttons: [ButtonExt; EXT_BUTTONS],
+    pub looper: Option<LooperConfig>,
+    pub lfo: Option<LfoConfig>,
+    /// Actions fired by incoming MMC or Song Position Pointer.
+    pub transport_triggers: Vec<TransportTrigger, MAX_TRANSPORT_TRIGGERS>,
+}
+
+impl PresetExt {
+    pub fn button(&self, index: usize) -> Option<&ButtonExt> {
+        self.buttons.get(index)
+    }
+}
+
+/// Extensions for all presets, indexed like `Config::presets`.
+pub type PresetExts = Slots<PresetExt, MAX_PRESET_EXTS>;
+
+/// A default extension is stock behaviour, still a valid slot.
+impl Slot for PresetExt {}
+
+impl PresetExts {
+    /// Extension of one button, if the preset has one.
+    pub fn button(&self, preset: usize, button: usize) -> Option<&ButtonExt> {
+        self.get(preset).and_then(|p| p.button(button))
+    }
+}
@@ -0,0 +1,136 @@
+//! Device profiles: named semantic actions ("scene 3", "tuner", "looper record")
+//! mapped to the MIDI a specific amp modeler expects.
+//!
+//! Profiles are uploaded as PE resources (one slot per profile, like presets) so
+//! new devices need no firmware update. The active profile is chosen in the device
+//! settings; preset extensions reference semantic actions instead of raw CCs.
+
+use heapless::Vec;
+use midi_controller::config::Label;
+use serde::{Deserialize, Serialize};
+
+use crate::slots::{Slot, Slots};
+
+/// Maximum number of stored profiles.
+pub const MAX_PROFILES: usize = 4;
+
+/// Maximum number of mappings in one profile.
+pub const MAX_PROFILE_ENTRIES: usize = 24;
+
+/// First PE resource ID of the profile slots (`0x61..0x64`).
+pub const PROFILE_RESOURCE_BASE: u8 = 0x61;
+
+/// Returns the profile index if `resource` addresses a profile slot.
+pub fn profile_slot(resource: u8) -> Option<usize> {
+    let slot = resource.checked_sub(PROFILE_RESOURCE_BASE)? as usize;
+    (slot < MAX_PROFILES).then_some(slot)
+}
+
+/// Device-independent action names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Semantic {
+    Scene,
+    Snapshot,
+    Preset,
+    Tuner,
+    TapTempo,
+    LooperRecord,
+    LooperPlay,
+    LooperOverdub,
+    LooperStop,
+    LooperUndo,
+}
+
+/// A semantic action as referenced from a preset, e.g. `Scene` with index 2
+/// for "scene 3". The index is 0-based and ignored by actions that take none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SemanticAction {
+    pub action: Semantic,
+    pub index: u8,
+}
+
+/// How a profile turns a semantic action (and its index) into a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Template {
+    /// Fixed control change.
+    Cc {
+        cc: u8,
+        value: u8,
+    },
+    /// One CC, value = `base + index` (e.g. Helix snapshots on CC 69).
+    CcValue {
+        cc: u8,
+        base: u8,
+    },
+    /// One CC per index, CC number = `base + index` (e.g. Axe-Fx scenes).
+    CcNumber {
+        base: u8,
+        value: u8,
+    },
+    /// Program Change `base + index`.
+    Program {
+        base: u8,
+    },
+    Note {
+        note: u8,
+        velocity: u8,
+    },
+}
+
+impl Template {
+    /// Raw MIDI bytes and length on `channel` (1-16).
+    pub fn message(&self, channel: u8, index: u8) -> ([u8; 3], usize) {
+        let ch = channel.saturating_sub(1) & 0x0F;
+        match *self {
+            Template::Cc { cc, value } => ([0xB0 | ch, cc & 0x7F, value & 0x7F], 3),
+            Template::CcValue { cc, base } => {
+                ([0xB0 | ch, cc & 0x7F, base.saturating_add(index) & 0x7F], 3)
+            }
+            Template::CcNumber { base, value } => (
+                [0xB0 | ch, base.saturating_add(index) & 0x7F, value & 0x7F],
+                3,
+            ),
+            Template::Program { base } => ([0xC0 | ch, base.saturating_add(index) & 0x7F, 0], 2),
+            Template::Note { note, velocity } => ([0x90 | ch, note & 0x7F, velocity & 0x7F], 3),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileEntry {
+    pub action: Semantic,
+    pub template: Template,
+}
+
+/// A device profile (postcard-serialized in its PE resource slot).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    /// Device name, e.g. "Helix". Empty = unused slot.
+    pub name: Label,
+    /// MIDI channel 1-16 the device listens on.
+    pub channel: u8,
+    pub entries: Vec<ProfileEntry, MAX_PROFILE_ENTRIES>,
+}
+
+impl Profile {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    /// MIDI for a semantic action, if this profile maps it.
+    pub fn resolve(&self, action: &SemanticAction) -> Option<([u8; 3], usize)> {
+        self.entries
+            .iter()
+            .find(|e| e.action == action.action)
+            .map(|e| e.template.message(self.channel, action.index))
+    }
+}
+
+/// All stored profiles, indexed by slot.
+pub type Profiles = Slots<Profile, MAX_PROFILES>;
+
+impl Slot for Profile {
+    fn is_empty(&self) -> bool {
+        Profile::is_empty(self)
+    }
+}
@@ -0,0 +1,95 @@
+//! Typed PE resources: which resource an index addresses and how its Set body
+//! decodes. Shared by uploads (checked when staged), the persist task (decoded
+//! again before it is stored and applied) and boot loading.
+
+use midi_controller::config::{GlobalConfig, Preset, GLOBAL_CONFIG_RESOURCE, MAX_PRESETS};
+use midi_controller::state::DefaultPresetStateStore;
+use serde::de::DeserializeOwned;
+
+use crate::bank::{Banks, BANKS_RESOURCE};
+use crate::eeprom::EEPROM_SIZE;
+use crate::persist::STATE_RESOURCE;
+use crate::preset_ext::{preset_ext_slot, PresetExt};
+use crate::profile::{profile_slot, Profile};
+use crate::setlist::{song_slot, Song};
+use crate::settings::{Settings, SETTINGS_RESOURCE};
+use crate::{FLASH_FORMAT_VERSION, LOCAL_FORMAT_VERSION};
+
+/// A resource that takes a typed Set body.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resource {
+    Preset(u8),
+    GlobalConfig,
+    Settings,
+    PresetExt(usize),
+    Profile(usize),
+    Song(usize),
+    Banks,
+    /// Runtime state image (EEPROM layout).
+    State,
+}
+
+impl Resource {
+    pub fn of(index: u8) -> Option<Self> {
+        Some(if index == SETTINGS_RESOURCE {
+            Resource::Settings
+        } else if let Some(slot) = preset_ext_slot(index) {
+            Resource::PresetExt(slot)
+        } else if let Some(slot) = profile_slot(index) {
+            Resource::Profile(slot)
+        } else if let Some(slot) = song_slot(index) {
+            Resource::Song(slot)
+        } else if index == GLOBAL_CONFIG_RESOURCE {
+            Resource::GlobalConfig
+        } else if index == BANKS_RESOURCE {
+            Resource::Banks
+        } else if index == STATE_RESOURCE {
+            Resource::State
+        } else if (index as usize) < MAX_PRESETS {
+            Resource::Preset(index)
+        } else {
+            return None;
+        })
+    }
+
+    /// Format version byte the resource is stored with on flash.
+    pub fn format_version(&self) -> u8 {
+        match self {
+            Resource::Preset(_) | Resource::GlobalConfig => FLASH_FORMAT_VERSION,
+            _ => LOCAL_FORMAT_VERSION,
+        }
+    }
+
+    /// Whether `body` is a valid Set body for this resource.
+    pub fn accepts(&self, body: &[u8]) -> bool {
+        match self {
+            Resource::Preset(_) => decode::<Preset>(body).is_some(),
+            Resource::GlobalConfig => decode::<GlobalConfig>(body).is_some(),
+            Resource::Settings => decode::<Settings>(body).is_some(),
+            Resource::PresetExt(_) => decode::<PresetExt>(body).is_some(),
+            Resource::Profile(_) => decode::<Profile>(body).is_some(),
+            Resource::Song(_) => decode::<Song>(body).is_some(),
+            Resource::Banks => decode::<Banks>(body).is_some(),
+            Resource::State => decode_state(body).is_some(),
+        }
+    }
+}
+
+/// Decode a Set body. An empty body resets the resource to its default.
+pub fn decode<T: DeserializeOwned + Default>(body: &[u8]) -> Option<T> {
+    if body.is_empty() {
+        Some(T::default())
+    } else {
+        postcard::from_bytes(body).ok()
+    }
+}
+
+/// Decode a runtime state image. An empty body is the cleared state.
+pub fn decode_state(body: &[u8]) -> Option<[u8; EEPROM_SIZE]> {
+    if body.is_empty() {
+        return Some(DefaultPresetStateStore::cleared_eeprom());
+    }
+    <[u8; EEPROM_SIZE]>::try_from(body)
+        .ok()
+        .filter(|image| DefaultPresetStateStore::from_eeprom(image).is_some())
+}
@@ -0,0 +1,98 @@
+//! Setlist: an ordered list of songs, each pointing at a preset (and optionally a scene).
+//!
+//! Songs are uploaded as independent PE resources (one slot per song, like presets),
+//! so a set can be reordered by re-uploading the small song slots without touching
+//! the preset blobs. Empty slots are skipped during navigation.
+
+use midi_controller::config::Label;
+use serde::{Deserialize, Serialize};
+
+use crate::slots::{Slot, Slots};
+
+/// Maximum number of song slots in a setlist.
+pub const MAX_SONGS: usize = 32;
+
+/// First PE resource ID of the song slots (`0x40..0x5F`).
+pub const SONG_RESOURCE_BASE: u8 = 0x40;
+
+/// Returns the song slot index if `resource` addresses a song slot.
+pub fn song_slot(resource: u8) -> Option<usize> {
+    let slot = resource.checked_sub(SONG_RESOURCE_BASE)? as usize;
+    (slot < MAX_SONGS).then_some(slot)
+}
+
+/// A single song entry (postcard-serialized in its PE resource slot).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Song {
+    /// Song name shown on the display. Empty = unused slot.
+    pub name: Label,
+    /// Preset index (0-based) to switch to when the song is selected.
+    pub preset: u8,
+    /// Optional scene: button index (0=A..5=F) engaged after the preset is entered.
+    pub scene: Option<u8>,
+}
+
+impl Song {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_empty()
+    }
+}
+
+/// Direction of a song navigation step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SongStep {
+    Next,
+    Prev,
+}
+
+/// Ordered song slots. Slot position defines the running order.
+pub type Setlist = Slots<Song, MAX_SONGS>;
+
+impl Slot for Song {
+    fn is_empty(&self) -> bool {
+        Song::is_empty(self)
+    }
+}
+
+impl Setlist {
+    /// A setlist is active when at least one slot holds a song.
+    pub fn is_active(&self) -> bool {
+        self.iter().any(|s| !s.is_empty())
+    }
+
+    /// First non-empty slot in running order.
+    pub fn first(&self) -> Option<usize> {
+        self.iter().position(|s| !s.is_empty())
+    }
+
+    /// Slot reached from `current` by one step, skipping empty slots and wrapping
+    /// around at either end. With no current song, starts at the first song.
+    pub fn step(&self, current: Option<usize>, step: SongStep) -> Option<usize> {
+        let Some(current) = current else {
+            return self.first();
+        };
+        let len = self.iter().len();
+        for offset in 1..=len {
+            let slot = match step {
+                SongStep::Next => (current + offset) % len,
+                SongStep::Prev => (current + len - (offset % len)) % len,
+            };
+            if self.get(slot).is_some() {
+                return Some(slot);
+            }
+        }
+        None
+    }
+
+    /// Song following `current` in running order (for the "up next" display).
+    pub fn up_next(&self, current: usize) -> Option<&Song> {
+        self.step(Some(current), SongStep::Next)
+            .filter(|&slot| slot != current)
+            .and_then(|slot| self.get(slot))
+    }
+
+    /// 1-based position of a slot among the non-empty songs.
+    pub fn position(&self, slot: usize) -> u8 {
+        self.iter().take(slot + 1).filter(|s| !s.is_empty()).count() as u8
+    }
+}
@@ -0,0 +1,113 @@
+//! Firmware-side device settings that have no place in the protocol crate's
+//! `GlobalConfig`.
+//!
+//! Uploaded as a separate PE resource (postcard-serialized, like presets) and
+//! stored in flash under the same blob key scheme.
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::clock_out::{PortClock, CLOCK_PORTS};
+
+/// PE resource ID of the device settings blob.
+pub const SETTINGS_RESOURCE: u8 = 0x60;
+
+/// Footswitch gesture that fires the panic action (button index 0=A..5=F).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PanicTrigger {
+    #[default]
+    None,
+    /// Pressing the button fires panic instead of its own actions.
+    Press(u8),
+    /// Holding the button for the long-press time fires panic; a short press
+    /// still runs the button's actions.
+    LongPress(u8),
+    /// Holding all buttons in the mask (bit 0 = A) at once. The member
+    /// buttons keep their own actions.
+    Chord(u8),
+}
+
+/// Maximum number of program change follow ranges.
+pub const MAX_PC_FOLLOW: usize = 16;
+
+/// Incoming Program Change range that selects presets: programs
+/// `first_program..first_program + count` select presets from `first_preset` on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PcFollow {
+    /// MIDI channel 1-16, 0 = any.
+    pub channel: u8,
+    pub first_program: u8,
+    pub count: u8,
+    pub first_preset: u8,
+}
+
+impl PcFollow {
+    /// Preset selected by `program` on `channel` (1-16), if this range covers it.
+    pub fn preset_for(&self, channel: u8, program: u8) -> Option<u8> {
+        if self.channel != 0 && self.channel != channel {
+            return None;
+        }
+        let offset = program.checked_sub(self.first_program)?;
+        (offset < self.count).then(|| self.first_preset.saturating_add(offset))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings {
+    /// Encoder `PresetScroll` only previews the target preset; the switch
+    /// happens on confirm (encoder push or footswitch) or after the timeout.
+    /// Off by default, so scrolling switches immediately as before.
+    pub scroll_preview: bool,
+    /// Auto-confirm a preview after this long without input (0 = wait for confirm).
+    pub preview_timeout_ms: u16,
+    /// Gesture for the panic action (All Notes/Sound Off, Reset Controllers).
+    pub panic: PanicTrigger,
+    /// Panic also stops the MIDI clock.
+    pub panic_stops_clock: bool,
+    /// Incoming Program Changes that switch presets (first match wins).
+    pub pc_follow: Vec<PcFollow, MAX_PC_FOLLOW>,
+    /// Incoming CC on a toggle's `listen_cc` (or a cycle button's or an
+    /// encoder's CC) sets its state instead of only lighting the ring.
+    pub sync_cc: bool,
+    /// Incoming Note On/Off sets toggles whose `on_press` sends that note.
+    pub sync_notes: bool,
+    /// Device profile slot that resolves semantic actions (None = unresolved).
+    pub profile: Option<u8>,
+    /// Clock output per port (`clock_out::PORT_DIN`, `PORT_USB`), for the
+    /// internal and forwarded external clock.
+    pub clock: [PortClock; CLOCK_PORTS],
+    /// MMC device ID for sent commands and accepted incoming ones (0x7F = all).
+    pub mmc_device: u8,
+}
+
+impl Settings {
+    pub const fn new() -> Self {
+        Self {
+            scroll_preview: false,
+            preview_timeout_ms: 2000,
+            panic: PanicTrigger::None,
+            panic_stops_clock: false,
+            pc_follow: Vec::new(),
+            sync_cc: false,
+            sync_notes: false,
+            profile: None,
+            clock: [PortClock::new(); CLOCK_PORTS],
+            mmc_device: crate::transport::MMC_ALL_DEVICES,
+        }
+    }
+}
+
+impl Settings {
+    /// Preset an incoming Program Change should switch to.
+    pub fn pc_follow_target(&self, channel: u8, program: u8) -> Option<u8> {
+        self.pc_follow
+            .iter()
+            .find_map(|f| f.preset_for(channel, program))
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,54 @@
+//! Slot collections behind the per-slot PE resources (setlist songs, device
+//! profiles, preset extensions): one entry per resource, grown on demand.
+
+use heapless::Vec;
+
+/// An entry of a [`Slots`] collection. `Default` is the cleared slot.
+pub trait Slot: Default {
+    /// Unused slot, skipped by [`Slots::get`].
+    fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+/// Up to `N` slots, indexed like their PE resources.
+#[derive(Debug, Clone, Default)]
+pub struct Slots<T, const N: usize> {
+    items: Vec<T, N>,
+    /// Bumped on every change so consumers can cheaply detect updates.
+    pub revision: u8,
+}
+
+impl<T, const N: usize> Slots<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            revision: 0,
+        }
+    }
+
+    /// All slots up to the highest one ever set, empty ones included.
+    pub fn iter(&self) -> core::slice::Iter<'_, T> {
+        self.items.iter()
+    }
+}
+
+impl<T: Slot, const N: usize> Slots<T, N> {
+    /// Store (or clear, with `T::default()`) a slot. Out-of-range slots are
+    /// ignored.
+    pub fn set(&mut self, index: usize, value: T) {
+        if index >= N {
+            return;
+        }
+        while self.items.len() <= index {
+            self.items.push(T::default()).ok();
+        }
+        self.items[index] = value;
+        self.revision = self.revision.wrapping_add(1);
+    }
+
+    /// A populated slot.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.items.get(index).filter(|item| !item.is_empty())
+    }
+}
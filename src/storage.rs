@@ -1,84 +1,75 @@
-//! Flash-based persistent configuration storage for RP2040.
+//! Persistent configuration storage: a wear-leveled key-value map
+//! (`sequential-storage`) over any NOR flash.
 //!
-//! Uses the last 64KB of flash (16 pages of 4KB each) with `sequential-storage`
-//! for wear-leveled key-value storage. Flash operations use `rp2040-flash` which
-//! executes from RAM to avoid XIP conflicts.
+//! On the device the backend is `flash::FlashStorage` (the last 64KB of the
+//! RP2040's flash); host tests use an in-memory flash.
+
+use core::ops::Range;
 
-use embedded_storage::nor_flash::{NorFlashError, NorFlashErrorKind};
 use embedded_storage_async::nor_flash::{ErrorType, MultiwriteNorFlash, NorFlash, ReadNorFlash};
+use heapless::Vec;
 use sequential_storage::cache::NoCache;
 use sequential_storage::map::{MapConfig, MapStorage, SerializationError, Value};
+use sequential_storage::Error;
+use serde::{Deserialize, Serialize};
 
-const STORAGE_ORIGIN: u32 = 0x001F_0000; // offset from flash start (2MB - 64KB)
-const STORAGE_SIZE: usize = 64 * 1024; // 16 sectors, full region (wear-leveled)
-const SECTOR_SIZE: usize = 4096;
-const PAGE_SIZE: usize = 256;
-const PRESET_KEY_BASE: u16 = 0x8000; // preset keys: 0x8000 | index
+use crate::bank::MAX_BANKS;
 
-#[derive(Debug)]
-pub struct FlashError;
+/// Scratch buffer for one map item (key + longest value, word aligned).
+const ITEM_BUF_SIZE: usize = 4096;
 
-impl NorFlashError for FlashError {
-    fn kind(&self) -> NorFlashErrorKind {
-        NorFlashErrorKind::Other
-    }
-}
+const PRESET_KEY_BASE: u16 = 0x8000; // preset keys: 0x8000 | index
+const STAGED_KEY_BASE: u16 = 0x8100; // staged upload blobs: 0x8100 | index
+const UPLOAD_STATE_KEY: u16 = 0x8200;
+const ERASE_COUNTS_KEY: u16 = 0x8201;
 
-pub struct FlashStorage;
+// Device state values. Stored as block 8 before, which `encode_key` shifted
+// out of the key, so they sit in block 0.
+/// Active preset index.
+pub const ACTIVE_PRESET_KEY: u16 = 0x0000;
+/// Active setlist song of bank 0 (other banks: `ACTIVE_SONG_KEY_BASE`).
+const ACTIVE_SONG_KEY: u16 = 0x0001;
+/// Tempo changed at runtime.
+pub const TEMPO_KEY: u16 = 0x0002;
+/// Clock started or stopped at runtime.
+pub const CLOCK_RUNNING_KEY: u16 = 0x0003;
+/// Active preset bank.
+pub const ACTIVE_BANK_KEY: u16 = 0x0004;
+const ACTIVE_SONG_KEY_BASE: u16 = 0x8300; // active song of banks 1..: 0x8300 | bank
+const BANK_KEY_BASE: u16 = 0x8400; // banks 1..: 0x8400 + 0x60 × (bank − 1) + index
+const BANK_STATE_KEY_BASE: u16 = 0x8700; // saved runtime state: 0x8700 | bank
+const STAGED_BANK_KEY_BASE: u16 = 0x8800; // banks 1..: 0x8800 + 0x80 × (bank − 1) + index
+const STAGED_BANK_KEY_END: u16 = STAGED_BANK_KEY_BASE + (BANK_SLOTS as u16 - 1) * 0x80;
 
-impl ErrorType for FlashStorage {
-    type Error = FlashError;
-}
+/// Number of resource indices that can be staged (7-bit PE resource IDs).
+pub const STAGED_SLOTS: u8 = 128;
 
-impl ReadNorFlash for FlashStorage {
-    const READ_SIZE: usize = 1;
+/// Resources stored per bank: presets, their extensions and the setlist
+/// songs (`0x00..0x5F`).
+pub const BANK_RESOURCES: u8 = 0x60;
 
-    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
-        let addr = (0x1000_0000 + STORAGE_ORIGIN + offset) as *const u8;
-        for (i, byte) in bytes.iter_mut().enumerate() {
-            *byte = unsafe { core::ptr::read_volatile(addr.add(i)) };
-        }
-        Ok(())
-    }
+/// Banks the store addresses (bank 0 uses the preset keys).
+pub const BANK_SLOTS: u8 = MAX_BANKS as u8;
 
-    fn capacity(&self) -> usize {
-        STORAGE_SIZE
-    }
-}
+// Every bank's keys fit below the next key range
+const _: () = assert!(ACTIVE_SONG_KEY_BASE + BANK_SLOTS as u16 <= BANK_KEY_BASE);
+const _: () =
+    assert!(BANK_KEY_BASE + (BANK_SLOTS as u16 - 1) * BANK_RESOURCES as u16 <= BANK_STATE_KEY_BASE);
+const _: () = assert!(BANK_STATE_KEY_BASE + BANK_SLOTS as u16 <= STAGED_BANK_KEY_BASE);
 
-impl NorFlash for FlashStorage {
-    const WRITE_SIZE: usize = 4;
-    const ERASE_SIZE: usize = SECTOR_SIZE;
+/// Blob, staged and bank keys the health walk tracks one bit each.
+const KEY_BITS: usize = (UPLOAD_STATE_KEY - PRESET_KEY_BASE) as usize
+    + (BANK_STATE_KEY_BASE - BANK_KEY_BASE) as usize
+    + (STAGED_BANK_KEY_END - STAGED_BANK_KEY_BASE) as usize;
 
-    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
-        let flash_addr = STORAGE_ORIGIN + from;
-        let len = to - from;
-        cortex_m::interrupt::free(|_| unsafe {
-            rp2040_flash::flash::flash_range_erase(flash_addr, len, true);
-        });
-        Ok(())
-    }
+/// PE resource ID of the storage health report (read-only).
+pub const HEALTH_RESOURCE: u8 = 0x68;
 
-    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
-        let mut flash_addr = STORAGE_ORIGIN + offset;
-        let mut remaining = bytes;
-        while !remaining.is_empty() {
-            let aligned_addr = flash_addr & !0xFF;
-            let start_offset = (flash_addr - aligned_addr) as usize;
-            let copy_len = remaining.len().min(PAGE_SIZE - start_offset);
-            let mut page_buf = [0xFFu8; PAGE_SIZE];
-            page_buf[start_offset..start_offset + copy_len].copy_from_slice(&remaining[..copy_len]);
-            cortex_m::interrupt::free(|_| unsafe {
-                rp2040_flash::flash::flash_range_program(aligned_addr, &page_buf, true);
-            });
-            remaining = &remaining[copy_len..];
-            flash_addr += copy_len as u32;
-        }
-        Ok(())
-    }
-}
+/// Most sectors a store can span (the device uses 16).
+pub const MAX_SECTORS: usize = 16;
 
-impl MultiwriteNorFlash for FlashStorage {}
+/// Map item header: data CRC, length, length CRC.
+const ITEM_HEADER_SIZE: usize = 8;
 
 /// Config value stored in flash (u16).
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -118,38 +109,312 @@ impl<'a> Value<'a> for PresetValue<'a> {
     }
 }
 
+/// Flash state of an upload transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UploadState {
+    /// No transaction in progress.
+    Idle,
+    /// Blobs are being staged; a transaction still open at boot is discarded.
+    Open,
+    /// Commit started; staged blobs left at boot are applied again.
+    Committing,
+}
+
+/// Why a flash write failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageError {
+    /// No room left, even after reclaiming stale items.
+    Full,
+    /// The flash itself failed or holds corrupt data.
+    Flash,
+}
+
+impl<E> From<Error<E>> for StorageError {
+    fn from(err: Error<E>) -> Self {
+        match err {
+            Error::FullStorage => StorageError::Full,
+            _ => StorageError::Flash,
+        }
+    }
+}
+
+/// Why the most recent persist write failed, as reported in `StorageHealth`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PersistError {
+    /// Flash full.
+    Full,
+    /// Flash failure or corrupt data.
+    Flash,
+    /// EEPROM write failed.
+    Eeprom,
+}
+
+impl From<StorageError> for PersistError {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::Full => PersistError::Full,
+            StorageError::Flash => PersistError::Flash,
+        }
+    }
+}
+
+/// Map items of one key range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemCount {
+    /// Newest item of each key.
+    pub live: u16,
+    /// Superseded or removed items, reclaimed by garbage collection.
+    pub stale: u16,
+}
+
+/// Storage health report, served as `HEALTH_RESOURCE` and shown in config
+/// mode. `ConfigStore::health` fills in the flash side; the persist task adds
+/// the EEPROM write count and the last error.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageHealth {
+    /// Size of the flash region in bytes.
+    pub capacity: u32,
+    /// Bytes taken by items, live and stale.
+    pub used: u32,
+    /// Bytes that can be written before a sector has to be reclaimed.
+    pub free: u32,
+    /// Config values (`save`/`load`).
+    pub config: ItemCount,
+    /// Preset and resource blobs.
+    pub blobs: ItemCount,
+    /// Blobs staged by an upload.
+    pub staged: ItemCount,
+    /// Bookkeeping: upload state, erase counts, runtime state of other banks.
+    pub other: ItemCount,
+    /// Erases per sector. Best effort: erases since the counters were last
+    /// stored are lost on power loss, and older firmware did not count.
+    pub erases: Vec<u32, MAX_SECTORS>,
+    /// A sector holds data the walk could not parse.
+    pub corrupt: bool,
+    /// EEPROM pages written since boot.
+    pub eeprom_writes: u32,
+    /// Most recent failed write since boot.
+    pub last_error: Option<PersistError>,
+}
+
+impl StorageHealth {
+    fn count_mut(&mut self, key: u16) -> &mut ItemCount {
+        match key {
+            ..PRESET_KEY_BASE => &mut self.config,
+            PRESET_KEY_BASE..STAGED_KEY_BASE => &mut self.blobs,
+            STAGED_KEY_BASE..UPLOAD_STATE_KEY => &mut self.staged,
+            BANK_KEY_BASE..BANK_STATE_KEY_BASE => &mut self.blobs,
+            STAGED_BANK_KEY_BASE..STAGED_BANK_KEY_END => &mut self.staged,
+            _ => &mut self.other,
+        }
+    }
+}
+
+/// Keys seen by the health walk.
+#[derive(Default)]
+struct KeySet {
+    /// Blob, staged and bank keys, one bit each.
+    blobs: [u128; KEY_BITS.div_ceil(128)],
+    others: Vec<u16, 32>,
+}
+
+impl KeySet {
+    /// Add `key`; false if it was seen before. Keys past capacity count as new.
+    fn insert(&mut self, key: u16) -> bool {
+        let bit = match key {
+            PRESET_KEY_BASE..UPLOAD_STATE_KEY => Some(key - PRESET_KEY_BASE),
+            BANK_KEY_BASE..BANK_STATE_KEY_BASE => Some(key - BANK_KEY_BASE + 0x200),
+            STAGED_BANK_KEY_BASE..STAGED_BANK_KEY_END => {
+                Some(key - STAGED_BANK_KEY_BASE + 0x200 + (BANK_STATE_KEY_BASE - BANK_KEY_BASE))
+            }
+            _ => None,
+        };
+        if let Some(bit) = bit.map(usize::from) {
+            let mask = 1u128 << (bit % 128);
+            let new = self.blobs[bit / 128] & mask == 0;
+            self.blobs[bit / 128] |= mask;
+            new
+        } else if self.others.contains(&key) {
+            false
+        } else {
+            self.others.push(key).ok();
+            true
+        }
+    }
+}
+
+/// Whether the page marker word at `addr` is written (at least half its
+/// first byte cleared, as sequential-storage reads it). None if unreadable.
+async fn marked<F: ReadNorFlash>(flash: &mut F, addr: u32) -> Option<bool> {
+    let mut buf = [0u8; 32];
+    flash.read(addr, &mut buf[..F::READ_SIZE]).await.ok()?;
+    let zeros: u32 = buf[..F::READ_SIZE].iter().map(|b| b.count_zeros()).sum();
+    Some(zeros >= 4)
+}
+
+/// Flash wrapper counting erases per sector, so wear can be reported.
+pub struct WearCounter<F> {
+    flash: F,
+    erases: [u32; MAX_SECTORS],
+    /// Counters changed since they were last stored.
+    dirty: bool,
+}
+
+impl<F: ErrorType> ErrorType for WearCounter<F> {
+    type Error = F::Error;
+}
+
+impl<F: ReadNorFlash> ReadNorFlash for WearCounter<F> {
+    const READ_SIZE: usize = F::READ_SIZE;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.flash.read(offset, bytes).await
+    }
+
+    fn capacity(&self) -> usize {
+        self.flash.capacity()
+    }
+}
+
+impl<F: NorFlash> NorFlash for WearCounter<F> {
+    const WRITE_SIZE: usize = F::WRITE_SIZE;
+    const ERASE_SIZE: usize = F::ERASE_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let sectors = from as usize / F::ERASE_SIZE..to as usize / F::ERASE_SIZE;
+        for count in self.erases.iter_mut().take(sectors.end).skip(sectors.start) {
+            *count = count.saturating_add(1);
+        }
+        self.dirty = true;
+        self.flash.erase(from, to).await
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.flash.write(offset, bytes).await
+    }
+}
+
+impl<F: MultiwriteNorFlash> MultiwriteNorFlash for WearCounter<F> {}
+
 /// Encode a config key: block(3 bits) | section(5 bits) | index(8 bits) = u16
 pub fn encode_key(block: u8, section: u8, index: u8) -> u16 {
     ((block as u16) << 13) | ((section as u16) << 8) | index as u16
 }
 
 /// Persistent config store wrapping sequential-storage map.
-pub struct ConfigStore {
-    map: MapStorage<u16, FlashStorage, NoCache>,
-    buf: [u8; SECTOR_SIZE],
+pub struct ConfigStore<F: NorFlash> {
+    map: MapStorage<u16, WearCounter<F>, NoCache>,
+    buf: [u8; ITEM_BUF_SIZE],
+    /// Most recent failed write, until taken.
+    last_error: Option<StorageError>,
+    /// Stored erase counts have been added to the counters.
+    erases_loaded: bool,
+    /// Bank whose resources the blob and staging calls address.
+    bank: u8,
 }
 
-impl ConfigStore {
-    pub fn try_new() -> Option<Self> {
-        let config = MapConfig::try_new(0..STORAGE_SIZE as u32)?;
+impl<F: NorFlash> ConfigStore<F> {
+    /// Store on `range` of `flash` (erase-size aligned, at least two sectors,
+    /// at most `MAX_SECTORS`).
+    pub fn new(flash: F, range: Range<u32>) -> Option<Self> {
+        if range.end as usize > MAX_SECTORS * F::ERASE_SIZE {
+            return None;
+        }
+        let config = MapConfig::try_new(range)?;
+        let flash = WearCounter {
+            flash,
+            erases: [0; MAX_SECTORS],
+            dirty: false,
+        };
         Some(Self {
-            map: MapStorage::new(FlashStorage, config, NoCache::new()),
-            buf: [0u8; SECTOR_SIZE],
+            map: MapStorage::new(flash, config, NoCache::new()),
+            buf: [0u8; ITEM_BUF_SIZE],
+            last_error: None,
+            erases_loaded: false,
+            bank: 0,
+        })
+    }
+
+    /// Address the resources and staging area of `bank` from now on.
+    /// Ignored past `BANK_SLOTS`.
+    pub fn set_bank(&mut self, bank: u8) {
+        if bank < BANK_SLOTS {
+            self.bank = bank;
+        }
+    }
+
+    /// Flash key of resource `index` in the active bank.
+    fn blob_key(&self, index: u8) -> u16 {
+        if self.bank == 0 || index >= BANK_RESOURCES {
+            PRESET_KEY_BASE | index as u16
+        } else {
+            BANK_KEY_BASE + (self.bank as u16 - 1) * BANK_RESOURCES as u16 + index as u16
+        }
+    }
+
+    /// Flash key of staged resource `index` in the active bank. Each bank
+    /// stages all resources apart, so a restore can stage every bank.
+    fn staged_key(&self, index: u8) -> u16 {
+        let index = (index & 0x7F) as u16;
+        if self.bank == 0 {
+            STAGED_KEY_BASE | index
+        } else {
+            STAGED_BANK_KEY_BASE + (self.bank as u16 - 1) * 0x80 + index
+        }
+    }
+
+    /// Flash key of the active bank's active song (bank 0 keeps config
+    /// value 1).
+    fn active_song_key(&self) -> u16 {
+        if self.bank == 0 {
+            ACTIVE_SONG_KEY
+        } else {
+            ACTIVE_SONG_KEY_BASE | self.bank as u16
+        }
+    }
+
+    /// Most recent failed write since the last call.
+    pub fn take_error(&mut self) -> Option<StorageError> {
+        self.last_error.take()
+    }
+
+    /// Convert a map result, remembering a failure for `take_error`.
+    fn track<T, E>(&mut self, result: Result<T, Error<E>>) -> Result<T, StorageError> {
+        result.map_err(|err| {
+            let err = StorageError::from(err);
+            self.last_error = Some(err);
+            err
         })
     }
 
     /// Store a config value.
-    pub async fn save(&mut self, block: u8, section: u8, index: u8, value: u16) {
-        let key = encode_key(block, section, index);
-        let _ = self
+    pub async fn save(
+        &mut self,
+        block: u8,
+        section: u8,
+        index: u8,
+        value: u16,
+    ) -> Result<(), StorageError> {
+        self.save_value(encode_key(block, section, index), value)
+            .await
+    }
+
+    /// Load a config value. Returns None if not found.
+    pub async fn load(&mut self, block: u8, section: u8, index: u8) -> Option<u16> {
+        self.load_value(encode_key(block, section, index)).await
+    }
+
+    /// Store the config value under `key` (e.g. `TEMPO_KEY`).
+    pub async fn save_value(&mut self, key: u16, value: u16) -> Result<(), StorageError> {
+        let result = self
             .map
             .store_item(&mut self.buf, &key, &ConfigValue(value))
             .await;
+        self.track(result)
     }
 
-    /// Load a config value. Returns None if not found.
-    pub async fn load(&mut self, block: u8, section: u8, index: u8) -> Option<u16> {
-        let key = encode_key(block, section, index);
+    /// Load the config value under `key`. Returns None if not found.
+    pub async fn load_value(&mut self, key: u16) -> Option<u16> {
         self.map
             .fetch_item::<ConfigValue>(&mut self.buf, &key)
             .await
@@ -158,9 +423,12 @@ impl ConfigStore {
             .map(|v| v.0)
     }
 
-    /// Erase all stored config (factory reset).
-    pub async fn erase_all(&mut self) {
-        let _ = self.map.erase_all().await;
+    /// Erase all stored config (factory reset). Erase counts are kept.
+    pub async fn erase_all(&mut self) -> Result<(), StorageError> {
+        self.load_erase_counts().await;
+        let result = self.map.erase_all().await;
+        self.track(result)?;
+        self.save_erase_counts().await
     }
 
     /// Load all stored config entries. Returns (block, section, index, value) tuples.
@@ -169,33 +437,76 @@ impl ConfigStore {
         let Ok(mut iter) = self.map.fetch_all_items(&mut self.buf).await else {
             return entries;
         };
-        let mut item_buf = [0u8; 64];
-        while let Ok(Some((key, ConfigValue(value)))) =
-            iter.next::<ConfigValue>(&mut item_buf).await
+        // Large enough for preset blobs, which are iterated over too
+        let mut item_buf = [0u8; 512];
+        while let Ok(Some((key, PresetValue(data)))) = iter.next::<PresetValue>(&mut item_buf).await
         {
             if key >= PRESET_KEY_BASE {
                 continue; // skip preset keys
             }
+            let Ok((ConfigValue(value), _)) = ConfigValue::deserialize_from(data) else {
+                continue;
+            };
             let block = ((key >> 13) & 0x07) as u8;
             let section = ((key >> 8) & 0x1F) as u8;
             let index = (key & 0xFF) as u8;
-            entries.push((block, section, index, value)).ok();
+            // Items come oldest first; a later one replaces the stale value
+            match entries
+                .iter_mut()
+                .find(|(b, s, i, _)| (*b, *s, *i) == (block, section, index))
+            {
+                Some(entry) => entry.3 = value,
+                None => {
+                    entries.push((block, section, index, value)).ok();
+                }
+            }
         }
         entries
     }
 
-    /// Store a preset blob.
-    pub async fn save_preset(&mut self, index: u8, data: &[u8]) {
-        let key = PRESET_KEY_BASE | index as u16;
-        let _ = self
+    /// Store a preset blob. Presets, extensions and songs go to the active
+    /// bank.
+    pub async fn save_preset(&mut self, index: u8, data: &[u8]) -> Result<(), StorageError> {
+        self.store_blob(self.blob_key(index), data).await
+    }
+
+    /// Load a single preset blob into the provided buffer. Returns the slice of data read.
+    pub async fn load_preset<'b>(&mut self, index: u8, out: &'b mut [u8]) -> Option<&'b [u8]> {
+        self.load_blob(self.blob_key(index), out).await
+    }
+
+    /// Store the active bank's active setlist song.
+    pub async fn save_active_song(&mut self, slot: u8) -> Result<(), StorageError> {
+        self.save_value(self.active_song_key(), slot as u16).await
+    }
+
+    /// Load the active bank's active setlist song.
+    pub async fn load_active_song(&mut self) -> Option<u8> {
+        self.load_value(self.active_song_key())
+            .await
+            .map(|v| v as u8)
+    }
+
+    /// Keep the runtime state image of `bank` while another bank is active.
+    pub async fn save_bank_state(&mut self, bank: u8, image: &[u8]) -> Result<(), StorageError> {
+        self.store_blob(BANK_STATE_KEY_BASE | bank as u16, image)
+            .await
+    }
+
+    /// Load the runtime state image kept for `bank`.
+    pub async fn load_bank_state<'b>(&mut self, bank: u8, out: &'b mut [u8]) -> Option<&'b [u8]> {
+        self.load_blob(BANK_STATE_KEY_BASE | bank as u16, out).await
+    }
+
+    async fn store_blob(&mut self, key: u16, data: &[u8]) -> Result<(), StorageError> {
+        let result = self
             .map
             .store_item(&mut self.buf, &key, &PresetValue(data))
             .await;
+        self.track(result)
     }
 
-    /// Load a single preset blob into the provided buffer. Returns the slice of data read.
-    pub async fn load_preset<'b>(&mut self, index: u8, out: &'b mut [u8]) -> Option<&'b [u8]> {
-        let key = PRESET_KEY_BASE | index as u16;
+    async fn load_blob<'b>(&mut self, key: u16, out: &'b mut [u8]) -> Option<&'b [u8]> {
         let item: Option<PresetValue<'_>> = self
             .map
             .fetch_item(&mut self.buf, &key)
@@ -211,10 +522,10 @@ impl ConfigStore {
         }
     }
 
-    /// Load all presets. Calls callback for each (index, data) found.
+    /// Load all presets of the active bank. Calls callback for each (index, data) found.
     pub async fn load_all_presets(&mut self, mut callback: impl FnMut(u8, &[u8])) {
         for idx in 0..32u8 {
-            let key = PRESET_KEY_BASE | idx as u16;
+            let key = self.blob_key(idx);
             let item: Result<Option<PresetValue<'_>>, _> =
                 self.map.fetch_item(&mut self.buf, &key).await;
             if let Ok(Some(PresetValue(data))) = item {
@@ -222,4 +533,187 @@ impl ConfigStore {
             }
         }
     }
+
+    /// Current upload transaction state.
+    pub async fn upload_state(&mut self) -> UploadState {
+        let value = self
+            .map
+            .fetch_item::<ConfigValue>(&mut self.buf, &UPLOAD_STATE_KEY)
+            .await
+            .ok()
+            .flatten();
+        match value {
+            Some(ConfigValue(1)) => UploadState::Open,
+            Some(ConfigValue(2)) => UploadState::Committing,
+            _ => UploadState::Idle,
+        }
+    }
+
+    /// Record the upload transaction state.
+    pub async fn set_upload_state(&mut self, state: UploadState) -> Result<(), StorageError> {
+        let value = match state {
+            UploadState::Idle => 0,
+            UploadState::Open => 1,
+            UploadState::Committing => 2,
+        };
+        let result = self
+            .map
+            .store_item(&mut self.buf, &UPLOAD_STATE_KEY, &ConfigValue(value))
+            .await;
+        self.track(result)
+    }
+
+    /// Stage a blob for resource `index` of the active bank until the upload
+    /// is committed. Staged blobs are stored without the flash format version
+    /// byte.
+    pub async fn stage_preset(&mut self, index: u8, data: &[u8]) -> Result<(), StorageError> {
+        self.store_blob(self.staged_key(index), data).await
+    }
+
+    /// Load the staged blob for resource `index` of the active bank.
+    pub async fn load_staged<'b>(&mut self, index: u8, out: &'b mut [u8]) -> Option<&'b [u8]> {
+        self.load_blob(self.staged_key(index), out).await
+    }
+
+    /// Add the stored erase counts to the counters, once.
+    async fn load_erase_counts(&mut self) {
+        if core::mem::replace(&mut self.erases_loaded, true) {
+            return;
+        }
+        let mut buf = [0u8; 4 * MAX_SECTORS];
+        let Some(data) = self.load_blob(ERASE_COUNTS_KEY, &mut buf).await else {
+            return;
+        };
+        let erases = &mut self.map.flash().erases;
+        for (count, stored) in erases.iter_mut().zip(data.chunks_exact(4)) {
+            let stored = u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]);
+            *count = count.saturating_add(stored);
+        }
+    }
+
+    /// Store the erase counts if they changed. Storing can itself reclaim a
+    /// sector; that erase is stored on the next call.
+    pub async fn save_erase_counts(&mut self) -> Result<(), StorageError> {
+        self.load_erase_counts().await;
+        let flash = self.map.flash();
+        if !core::mem::take(&mut flash.dirty) {
+            return Ok(());
+        }
+        let mut buf = [0u8; 4 * MAX_SECTORS];
+        for (out, count) in buf.chunks_exact_mut(4).zip(flash.erases) {
+            out.copy_from_slice(&count.to_le_bytes());
+        }
+        let result = self.store_blob(ERASE_COUNTS_KEY, &buf).await;
+        if result.is_err() {
+            self.map.flash().dirty = true;
+        }
+        result
+    }
+
+    /// Walk the flash for usage, item counts per key range and erase counts.
+    /// Reads item headers only, so it stays cheap next to any write.
+    ///
+    /// Parses sequential-storage's sector and item layout, which the crate
+    /// does not expose: it is pinned to an exact version in `Cargo.toml`, and
+    /// a host test checks the layout.
+    pub async fn health(&mut self) -> StorageHealth {
+        self.load_erase_counts().await;
+        let range = self.map.flash_range();
+        let sector = F::ERASE_SIZE as u32;
+        let word = F::READ_SIZE.max(F::WRITE_SIZE) as u32;
+        let header_size = (ITEM_HEADER_SIZE as u32).next_multiple_of(word);
+        let key_size = 2usize.next_multiple_of(F::READ_SIZE);
+
+        let mut health = StorageHealth {
+            capacity: range.end - range.start,
+            ..Default::default()
+        };
+        let mut keys = KeySet::default();
+        let mut open_sectors = 0;
+        let flash = self.map.flash();
+        for start in range.clone().step_by(F::ERASE_SIZE) {
+            let end = start + sector;
+            let started = marked(flash, start).await;
+            let closed = marked(flash, end - F::READ_SIZE as u32).await;
+            match (started, closed) {
+                (Some(false), Some(false)) => {
+                    open_sectors += 1;
+                    continue;
+                }
+                (Some(true), Some(_)) => {}
+                // Interrupted erase or unreadable
+                _ => {
+                    health.corrupt = true;
+                    continue;
+                }
+            }
+
+            let data_end = end - word;
+            let mut addr = start + word;
+            let mut buf = [0u8; 32];
+            while addr + header_size <= data_end {
+                let header = &mut buf[..header_size as usize];
+                if flash.read(addr, header).await.is_err() {
+                    health.corrupt = true;
+                    break;
+                }
+                if header.iter().all(|&b| b == 0xFF) {
+                    break;
+                }
+                let len = u16::from_le_bytes([header[4], header[5]]) as u32;
+                let next = addr + header_size + len.next_multiple_of(word);
+                let erased = header[..4] == [0; 4];
+                if len < 2
+                    || next > data_end
+                    || flash
+                        .read(addr + header_size, &mut buf[..key_size])
+                        .await
+                        .is_err()
+                {
+                    health.corrupt = true;
+                    break;
+                }
+                let key = u16::from_le_bytes([buf[0], buf[1]]);
+                let count = health.count_mut(key);
+                if !erased && keys.insert(key) {
+                    count.live += 1;
+                } else {
+                    count.stale += 1;
+                }
+                health.used += next - addr;
+                addr = next;
+            }
+            if closed == Some(false) {
+                health.free += data_end - addr;
+            }
+        }
+        // One erased sector always stays free as the reclaim target
+        let sector_data = sector - 2 * word;
+        health.free += (open_sectors as u32).saturating_sub(1) * sector_data;
+
+        let sectors = range.start as usize / F::ERASE_SIZE..range.end as usize / F::ERASE_SIZE;
+        health.erases = Vec::from_slice(&flash.erases[sectors]).unwrap_or_default();
+        health
+    }
+}
+
+impl<F: MultiwriteNorFlash> ConfigStore<F> {
+    /// Remove the staged blob for resource `index` of the active bank.
+    pub async fn remove_staged(&mut self, index: u8) -> Result<(), StorageError> {
+        let key = self.staged_key(index);
+        let result = self.map.remove_item(&mut self.buf, &key).await;
+        self.track(result)
+    }
+
+    /// Drop the staged blobs in `mask` (bit = resource index) of the active
+    /// bank and end the transaction. On failure the transaction stays open, so the next boot
+    /// discards it.
+    pub async fn discard_staged(&mut self, mask: u128) -> Result<(), StorageError> {
+        for index in 0..STAGED_SLOTS {
+            if mask & (1 << index) != 0 {
+                self.remove_staged(index).await?;
+            }
+        }
+        self.set_upload_state(UploadState::Idle).await
+    }
 }
@@ -0,0 +1,166 @@
+//! DAW transport control: MIDI Machine Control (MMC) commands and Song
+//! Position Pointer (SPP), sent by button actions and matched as triggers.
+//!
+//! MMC is SysEx (`F0 7F <device> 06 <command> F7`) and only goes out over USB.
+//! SPP goes out with the MIDI clock: it follows the per-port transport settings
+//! and starts the internal clock with Continue instead of Start.
+
+use heapless::Vec;
+use midi_controller::config::{Action, MAX_ACTIONS};
+use serde::{Deserialize, Serialize};
+
+/// MMC device ID that addresses all devices.
+pub const MMC_ALL_DEVICES: u8 = 0x7F;
+
+/// Longest MMC message sent (Locate).
+pub const MAX_MMC_LEN: usize = 13;
+
+/// Maximum number of transport actions per button.
+pub const MAX_TRANSPORT_ACTIONS: usize = 2;
+
+/// Maximum number of transport triggers per preset.
+pub const MAX_TRANSPORT_TRIGGERS: usize = 4;
+
+/// MMC command byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum MmcCommand {
+    Stop = 0x01,
+    Play = 0x02,
+    DeferredPlay = 0x03,
+    FastForward = 0x04,
+    Rewind = 0x05,
+    RecordStrobe = 0x06,
+    RecordExit = 0x07,
+    Pause = 0x09,
+}
+
+impl MmcCommand {
+    pub fn from_byte(b: u8) -> Option<Self> {
+        Some(match b {
+            0x01 => MmcCommand::Stop,
+            0x02 => MmcCommand::Play,
+            0x03 => MmcCommand::DeferredPlay,
+            0x04 => MmcCommand::FastForward,
+            0x05 => MmcCommand::Rewind,
+            0x06 => MmcCommand::RecordStrobe,
+            0x07 => MmcCommand::RecordExit,
+            0x09 => MmcCommand::Pause,
+            _ => return None,
+        })
+    }
+}
+
+/// Transport press action of a button (see `ButtonExt::transport`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransportAction {
+    Mmc(MmcCommand),
+    /// MMC Locate to a time code position.
+    Locate {
+        hours: u8,
+        minutes: u8,
+        seconds: u8,
+        frames: u8,
+    },
+    /// Song Position Pointer (in sixteenth notes) followed by Continue.
+    SongPosition(u16),
+}
+
+/// Transport message received from the DAW.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportEvent {
+    Mmc(MmcCommand),
+    Locate,
+    SongPosition(u16),
+}
+
+/// What a transport trigger reacts to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransportMatch {
+    Mmc(MmcCommand),
+    /// Any MMC Locate.
+    Locate,
+    /// Song Position Pointer: any (None) or one position.
+    SongPosition(Option<u16>),
+}
+
+impl TransportMatch {
+    pub fn matches(&self, event: &TransportEvent) -> bool {
+        match (self, event) {
+            (TransportMatch::Mmc(a), TransportEvent::Mmc(b)) => a == b,
+            (TransportMatch::Locate, TransportEvent::Locate) => true,
+            (TransportMatch::SongPosition(want), TransportEvent::SongPosition(pos)) => {
+                want.is_none_or(|w| w == *pos)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Actions run when an incoming transport message matches (see
+/// `PresetExt::transport_triggers`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransportTrigger {
+    pub on: TransportMatch,
+    pub actions: Vec<Action, MAX_ACTIONS>,
+}
+
+/// MMC SysEx for a command.
+pub fn mmc_command(device: u8, command: MmcCommand) -> Vec<u8, MAX_MMC_LEN> {
+    Vec::from_slice(&[0xF0, 0x7F, device & 0x7F, 0x06, command as u8, 0xF7]).unwrap_or_default()
+}
+
+/// MMC Locate SysEx (time code target, 30 fps, no subframes).
+pub fn mmc_locate(
+    device: u8,
+    hours: u8,
+    minutes: u8,
+    seconds: u8,
+    frames: u8,
+) -> Vec<u8, MAX_MMC_LEN> {
+    Vec::from_slice(&[
+        0xF0,
+        0x7F,
+        device & 0x7F,
+        0x06,
+        0x44,
+        0x06,
+        0x01,
+        // Hours byte carries the frame rate in bits 5-6 (11 = 30 fps)
+        0x60 | (hours & 0x1F),
+        minutes.min(59),
+        seconds.min(59),
+        frames.min(29),
+        0x00,
+        0xF7,
+    ])
+    .unwrap_or_default()
+}
+
+/// Song Position Pointer message for `position` sixteenth notes.
+pub fn song_position(position: u16) -> [u8; 3] {
+    let position = position & 0x3FFF;
+    [0xF2, (position & 0x7F) as u8, (position >> 7) as u8]
+}
+
+/// Parse an MMC command or Locate addressed to `device` (or all devices).
+pub fn parse_mmc(sysex: &[u8], device: u8) -> Option<TransportEvent> {
+    match sysex {
+        [0xF0, 0x7F, id, 0x06, rest @ ..] if *id == MMC_ALL_DEVICES || *id == device => {
+            match rest {
+                [0x44, ..] => Some(TransportEvent::Locate),
+                [cmd, 0xF7] => MmcCommand::from_byte(*cmd).map(TransportEvent::Mmc),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Parse a Song Position Pointer message.
+pub fn parse_song_position(raw: &[u8]) -> Option<u16> {
+    match raw {
+        [0xF2, lsb, msb, ..] => Some((*lsb as u16 & 0x7F) | ((*msb as u16 & 0x7F) << 7)),
+        _ => None,
+    }
+}
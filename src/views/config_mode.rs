@@ -15,6 +15,7 @@ use embedded_text::{
 use heapless::String;
 
 use crate::config_mode::InfoScreen;
+use crate::storage::{PersistError, StorageHealth};
 
 const DISPLAY_SIZE: u32 = 128;
 
@@ -112,6 +113,144 @@ pub fn draw_info_right<D: DrawTarget<Color = Gray4>>(
     Ok(())
 }
 
+/// Draw the storage health screen (left display: flash usage + item counts,
+/// right display: sector wear + EEPROM writes + last error).
+pub fn draw_storage_left<D: DrawTarget<Color = Gray4>>(
+    display: &mut D,
+    health: &StorageHealth,
+) -> Result<(), D::Error> {
+    let style = MonoTextStyle::new(&FONT_6X9, Gray4::WHITE);
+    let textbox_style = TextBoxStyleBuilder::new()
+        .alignment(HorizontalAlignment::Left)
+        .build();
+
+    let mut buf: String<192> = String::new();
+    writeln!(buf, "Flash:").ok();
+    writeln!(buf, "  Size: {}", health.capacity).ok();
+    writeln!(buf, "  Used: {}", health.used).ok();
+    writeln!(buf, "  Free: {}", health.free).ok();
+    writeln!(buf).ok();
+    writeln!(buf, "Items live/stale:").ok();
+    for (name, count) in [
+        ("Config", health.config),
+        ("Blobs", health.blobs),
+        ("Staged", health.staged),
+        ("Other", health.other),
+    ] {
+        writeln!(buf, "  {}: {}/{}", name, count.live, count.stale).ok();
+    }
+    if health.corrupt {
+        writeln!(buf, "CORRUPT SECTOR").ok();
+    }
+
+    let bounds = Rectangle::new(
+        Point::new(4, 4),
+        Size::new(DISPLAY_SIZE - 8, DISPLAY_SIZE - 8),
+    );
+    TextBox::with_textbox_style(buf.as_str(), bounds, style, textbox_style).draw(display)?;
+    Ok(())
+}
+
+pub fn draw_storage_right<D: DrawTarget<Color = Gray4>>(
+    display: &mut D,
+    health: &StorageHealth,
+) -> Result<(), D::Error> {
+    let style = MonoTextStyle::new(&FONT_6X9, Gray4::WHITE);
+    let textbox_style = TextBoxStyleBuilder::new()
+        .alignment(HorizontalAlignment::Left)
+        .build();
+
+    let mut buf: String<192> = String::new();
+    writeln!(buf, "Erases/sector:").ok();
+    // Four sectors per row, unindented so 4-digit counts fit
+    for row in health.erases.chunks(4) {
+        for (i, count) in row.iter().enumerate() {
+            let sep = if i == 0 { "" } else { " " };
+            write!(buf, "{}{}", sep, count).ok();
+        }
+        writeln!(buf).ok();
+    }
+    writeln!(buf).ok();
+    writeln!(buf, "EEPROM writes:").ok();
+    writeln!(buf, "  {}", health.eeprom_writes).ok();
+    writeln!(buf, "Last error:").ok();
+    let error = match health.last_error {
+        None => "none",
+        Some(PersistError::Full) => "flash full",
+        Some(PersistError::Flash) => "flash",
+        Some(PersistError::Eeprom) => "EEPROM",
+    };
+    writeln!(buf, "  {}", error).ok();
+
+    let bounds = Rectangle::new(
+        Point::new(4, 4),
+        Size::new(DISPLAY_SIZE - 8, DISPLAY_SIZE - 8),
+    );
+    TextBox::with_textbox_style(buf.as_str(), bounds, style, textbox_style).draw(display)?;
+    Ok(())
+}
+
+/// Draw the bank picked for switching (left display: bank number + name,
+/// right display: what happens on exit).
+pub fn draw_bank_left<D: DrawTarget<Color = Gray4>>(
+    display: &mut D,
+    bank: u8,
+    name: &str,
+) -> Result<(), D::Error> {
+    let title_style = MonoTextStyle::new(&FONT_10X20, Gray4::WHITE);
+    let detail_style = MonoTextStyle::new(&FONT_6X9, Gray4::WHITE);
+    let centered = TextBoxStyleBuilder::new()
+        .alignment(HorizontalAlignment::Center)
+        .vertical_alignment(VerticalAlignment::Middle)
+        .build();
+
+    // Bank number (1-based) in top half, name in bottom half.
+    let top = Rectangle::new(Point::zero(), Size::new(DISPLAY_SIZE, 64));
+    let mut title: String<8> = String::new();
+    write!(title, "Bank {}", bank + 1).ok();
+    TextBox::with_textbox_style(title.as_str(), top, title_style, centered).draw(display)?;
+    let bottom = Rectangle::new(Point::new(0, 64), Size::new(DISPLAY_SIZE, 64));
+    TextBox::with_textbox_style(name, bottom, detail_style, centered).draw(display)?;
+    Ok(())
+}
+
+pub fn draw_bank_right<D: DrawTarget<Color = Gray4>>(
+    display: &mut D,
+    active: bool,
+) -> Result<(), D::Error> {
+    let style = MonoTextStyle::new(&FONT_6X9, Gray4::WHITE);
+    let textbox_style = TextBoxStyleBuilder::new()
+        .alignment(HorizontalAlignment::Center)
+        .vertical_alignment(VerticalAlignment::Middle)
+        .build();
+    let text = if active {
+        "Active bank"
+    } else {
+        "Exit config mode\nto switch\n(reboots)"
+    };
+    let bounds = Rectangle::new(Point::zero(), Size::new(DISPLAY_SIZE, DISPLAY_SIZE));
+    TextBox::with_textbox_style(text, bounds, style, textbox_style).draw(display)?;
+    Ok(())
+}
+
+/// Draw the snapshot confirmation.
+pub fn draw_snapshot<D: DrawTarget<Color = Gray4>>(display: &mut D) -> Result<(), D::Error> {
+    let style = MonoTextStyle::new(&FONT_6X9, Gray4::WHITE);
+    let textbox_style = TextBoxStyleBuilder::new()
+        .alignment(HorizontalAlignment::Center)
+        .vertical_alignment(VerticalAlignment::Middle)
+        .build();
+    let bounds = Rectangle::new(Point::zero(), Size::new(DISPLAY_SIZE, DISPLAY_SIZE));
+    TextBox::with_textbox_style(
+        "Current state saved\nas preset defaults",
+        bounds,
+        style,
+        textbox_style,
+    )
+    .draw(display)?;
+    Ok(())
+}
+
 /// Draw button press feedback (centered, large button name + action detail).
 pub fn draw_button_press<D: DrawTarget<Color = Gray4>>(
     display: &mut D,
@@ -110,6 +110,48 @@ pub fn draw_name<D: DrawTarget<Color = Gray4>>(
     Ok(())
 }
 
+/// Draw the "up next" song name (setlist mode) with a dimmed caption above it
+pub fn draw_up_next<D: DrawTarget<Color = Gray4>>(
+    display: &mut D,
+    name: &str,
+) -> Result<(), D::Error> {
+    draw_captioned(display, "Next", name)
+}
+
+/// Draw a text with a dimmed caption above it
+pub fn draw_captioned<D: DrawTarget<Color = Gray4>>(
+    display: &mut D,
+    caption: &str,
+    text: &str,
+) -> Result<(), D::Error> {
+    use embedded_graphics::primitives::Rectangle;
+    use embedded_text::{
+        alignment::{HorizontalAlignment, VerticalAlignment},
+        style::TextBoxStyleBuilder,
+        TextBox,
+    };
+
+    let centered = TextBoxStyleBuilder::new()
+        .alignment(HorizontalAlignment::Center)
+        .vertical_alignment(VerticalAlignment::Middle)
+        .build();
+
+    // Caption at top
+    let caption_style = MonoTextStyle::new(&FONT_10X20, Gray4::new(0x8));
+    let top = Rectangle::new(Point::zero(), Size::new(DISPLAY_SIZE, 30));
+    TextBox::with_textbox_style(caption, top, caption_style, centered).draw(display)?;
+
+    // Text in the remaining area
+    let name_style = MonoTextStyle::new(&FONT_10X20, Gray4::WHITE);
+    let rest = Rectangle::new(
+        Point::new(0, 30),
+        Size::new(DISPLAY_SIZE, DISPLAY_SIZE - 30),
+    );
+    TextBox::with_textbox_style(text, rest, name_style, centered).draw(display)?;
+
+    Ok(())
+}
+
 /// Draw a long-press hint with inverted background (white bg, dark text)
 pub fn draw_long_press_hint<D: DrawTarget<Color = Gray4>>(
     display: &mut D,
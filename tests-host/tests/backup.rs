@@ -0,0 +1,158 @@
+// Host-side tests for src/backup.rs
+
+/// Match the firmware's MAX_PRESET_SIZE constant.
+pub const MAX_PRESET_SIZE: usize = 256;
+
+#[path = "../../src/eeprom.rs"]
+mod eeprom;
+
+#[path = "../../src/backup.rs"]
+mod backup;
+
+use backup::{chunk_count, entry_header, image_size, Reader, RestoreError, Writer, CHUNK_SIZE};
+
+type Entry = (u8, u8, Vec<u8>);
+
+/// Backup of `entries`, cut into chunks.
+fn image(entries: &[Entry]) -> Vec<Vec<u8>> {
+    let mut writer = Writer::new(entries.len() as u16);
+    let mut chunks = Vec::new();
+    for (bank, resource, body) in entries {
+        let header = entry_header(*bank, *resource, body.len());
+        for mut data in [&header[..], &body[..]] {
+            while let Some(chunk) = writer.write(&mut data) {
+                chunks.push(chunk.to_vec());
+            }
+        }
+    }
+    chunks.extend(writer.finish().iter().map(|c| c.to_vec()));
+    chunks
+}
+
+/// Restore `chunks` in order, collecting the entries.
+fn restore(chunks: &[Vec<u8>]) -> Result<Vec<Entry>, RestoreError> {
+    let count = chunks.len() as u16;
+    let mut reader = Reader::new(count);
+    let mut entries = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        reader.start_chunk(i as u16 + 1, count)?;
+        for &byte in chunk {
+            if let Some((bank, resource, body)) = reader.push(byte)? {
+                entries.push((bank, resource, body.to_vec()));
+            }
+        }
+    }
+    reader.finish()?;
+    Ok(entries)
+}
+
+/// A device's worth of entries: full-size, small and empty bodies, in more
+/// than one bank.
+fn entries() -> Vec<Entry> {
+    (0..40u8)
+        .map(|r| {
+            let len = match r % 4 {
+                0 => MAX_PRESET_SIZE,
+                1 => 0,
+                _ => 17 * r as usize % 200,
+            };
+            (
+                r % 3,
+                r,
+                (0..len).map(|i| (i as u8).wrapping_mul(r) ^ 0x80).collect(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn image_round_trips_across_chunks() {
+    let entries = entries();
+    let chunks = image(&entries);
+    let bodies = entries.iter().map(|(_, _, b)| b.len()).sum();
+    let size = image_size(entries.len() as u16, bodies);
+    assert_eq!(chunks.len(), chunk_count(size) as usize);
+    assert_eq!(chunks.iter().map(Vec::len).sum::<usize>(), size);
+    assert!(chunks[..chunks.len() - 1]
+        .iter()
+        .all(|c| c.len() == CHUNK_SIZE));
+    assert_eq!(restore(&chunks), Ok(entries));
+}
+
+#[test]
+fn every_bank_fits_in_one_image() {
+    // Far more entries than a byte can count
+    let entries: Vec<Entry> = (0..8u8)
+        .flat_map(|bank| (0..0x61u8).map(move |r| (bank, r, vec![bank ^ r; r as usize % 5])))
+        .collect();
+    assert!(entries.len() > 255);
+    assert_eq!(restore(&image(&entries)), Ok(entries));
+}
+
+#[test]
+fn empty_image_round_trips() {
+    let chunks = image(&[]);
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(restore(&chunks), Ok(vec![]));
+}
+
+#[test]
+fn checksum_exactly_filling_a_chunk() {
+    // Image header, a full entry, a second entry and the CRC fill two chunks
+    let body = vec![0x55; 2 * CHUNK_SIZE - 5 - (4 + 256) - 4 - 2];
+    let entries = vec![(0, 0x10, vec![0xAA; 256]), (0, 0x11, body)];
+    let chunks = image(&entries);
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[1].len(), CHUNK_SIZE);
+    assert_eq!(restore(&chunks), Ok(entries));
+}
+
+#[test]
+fn corrupted_image_is_rejected() {
+    let mut chunks = image(&entries());
+    chunks[2][100] ^= 0x01;
+    assert!(restore(&chunks).is_err());
+
+    // A flipped body byte only shows in the checksum
+    let entries = vec![(0, 0x00, vec![1, 2, 3, 4])];
+    let mut chunks = image(&entries);
+    chunks[0][5 + 4 + 1] ^= 0x40;
+    assert_eq!(restore(&chunks), Err(RestoreError::Checksum));
+}
+
+#[test]
+fn missing_or_reordered_chunks_are_rejected() {
+    let chunks = image(&entries());
+    let count = chunks.len() as u16;
+
+    // Last chunk never arrives
+    let mut reader = Reader::new(count);
+    for (i, chunk) in chunks[..chunks.len() - 1].iter().enumerate() {
+        reader.start_chunk(i as u16 + 1, count).unwrap();
+        for &byte in chunk {
+            reader.push(byte).unwrap();
+        }
+    }
+    assert_eq!(reader.finish(), Err(RestoreError::Truncated));
+
+    let mut reader = Reader::new(count);
+    reader.start_chunk(1, count).unwrap();
+    assert_eq!(reader.start_chunk(3, count), Err(RestoreError::Chunk));
+    assert_eq!(reader.start_chunk(2, count + 1), Err(RestoreError::Chunk));
+}
+
+#[test]
+fn foreign_image_is_rejected() {
+    let mut chunks = image(&[(0, 0x00, vec![1])]);
+    // Layout version
+    chunks[0][2] = chunks[0][2].wrapping_add(1);
+    assert_eq!(restore(&chunks), Err(RestoreError::Format));
+
+    // Entry longer than any resource body
+    let mut reader = Reader::new(1);
+    reader.start_chunk(1, 1).unwrap();
+    let mut data = vec![b'P', b'B', backup::BACKUP_VERSION, 1, 0];
+    data.extend_from_slice(&entry_header(0, 0x00, MAX_PRESET_SIZE + 1));
+    let result: Result<Vec<_>, _> = data.iter().map(|&b| reader.push(b).map(|_| ())).collect();
+    assert_eq!(result, Err(RestoreError::Format));
+}
@@ -0,0 +1,170 @@
+// Host-side tests for src/clock_out.rs
+
+#[path = "../../src/clock_out.rs"]
+mod clock_out;
+
+use clock_out::{
+    swing_offset_us, ClockRate, ClockShaper, ClockSource, PortClock, CLOCK_PORTS, PORT_DIN,
+    PORT_USB,
+};
+use midi_controller::routing::MidiPort;
+
+fn ports() -> [PortClock; CLOCK_PORTS] {
+    [PortClock::new(); CLOCK_PORTS]
+}
+
+/// Drain everything due at `now`, counting ticks per port (DIN, USB).
+fn drain(shaper: &mut ClockShaper, now: u32) -> (u32, u32) {
+    let (mut din, mut usb) = (0, 0);
+    loop {
+        let due = shaper.poll(now);
+        if due.is_empty() {
+            return (din, usb);
+        }
+        din += due.contains(MidiPort::DIN) as u32;
+        usb += due.contains(MidiPort::USB) as u32;
+    }
+}
+
+#[test]
+fn normal_rate_passes_ticks_through() {
+    let mut s = ClockShaper::new();
+    s.tick(&ports(), MidiPort::all(), 0, 1000);
+    assert_eq!(drain(&mut s, 0), (1, 1));
+    assert_eq!(s.next_due_in(0), None);
+}
+
+#[test]
+fn divide_sends_every_nth_tick() {
+    let mut cfg = ports();
+    cfg[PORT_DIN].rate = ClockRate::Divide(2);
+    let mut s = ClockShaper::new();
+    let mut din = 0;
+    let mut usb = 0;
+    for k in 0..8u32 {
+        s.tick(&cfg, MidiPort::all(), k * 1000, 1000);
+        let (d, u) = drain(&mut s, k * 1000);
+        din += d;
+        usb += u;
+    }
+    assert_eq!((din, usb), (4, 8));
+}
+
+#[test]
+fn multiply_spreads_ticks_over_the_interval() {
+    let mut cfg = ports();
+    cfg[PORT_USB].rate = ClockRate::Multiply(2);
+    let mut s = ClockShaper::new();
+    s.tick(&cfg, MidiPort::all(), 0, 1000);
+    assert_eq!(drain(&mut s, 0), (1, 1));
+    assert_eq!(s.next_due_in(0), Some(500));
+    assert_eq!(drain(&mut s, 499), (0, 0));
+    assert_eq!(drain(&mut s, 500), (0, 1));
+}
+
+#[test]
+fn swing_delays_offbeat_sixteenth() {
+    assert_eq!(swing_offset_us(6, 1000, 0), 0);
+    // 75% swing: the off-beat sixteenth starts 3 ticks late
+    assert_eq!(swing_offset_us(0, 1000, 25), 0);
+    assert_eq!(swing_offset_us(3, 1000, 25), 1500);
+    assert_eq!(swing_offset_us(6, 1000, 25), 3000);
+    assert_eq!(swing_offset_us(12, 1000, 25), 0);
+    // Ticks stay in order
+    let times: Vec<u32> = (0..24)
+        .map(|i| i * 1000 + swing_offset_us(i, 1000, 16))
+        .collect();
+    assert!(times.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[test]
+fn swung_tick_is_queued() {
+    let mut cfg = ports();
+    cfg[PORT_DIN].swing = 25;
+    let mut s = ClockShaper::new();
+    for k in 0..7u32 {
+        s.tick(&cfg, MidiPort::DIN, k * 1000, 1000);
+    }
+    // Tick 6 is due at 6000 + 3000
+    let mut sent = 0;
+    for now in (0..=9000).step_by(500) {
+        sent += drain(&mut s, now).0;
+        if now < 9000 {
+            assert!(sent < 7, "swung tick sent early at {now}");
+        }
+    }
+    assert_eq!(sent, 7);
+}
+
+#[test]
+fn disabled_port_and_dest_filter() {
+    let mut cfg = ports();
+    cfg[PORT_DIN].enabled = false;
+    let mut s = ClockShaper::new();
+    s.tick(&cfg, MidiPort::all(), 0, 1000);
+    assert_eq!(drain(&mut s, 0), (0, 1));
+    s.tick(&ports(), MidiPort::DIN, 1000, 1000);
+    assert_eq!(drain(&mut s, 1000), (1, 0));
+}
+
+#[test]
+fn transport_per_port() {
+    let mut cfg = ports();
+    cfg[PORT_DIN].transport = false;
+    let mut s = ClockShaper::new();
+    assert_eq!(s.transport(&cfg, MidiPort::all(), 0xFA), MidiPort::USB);
+    cfg[PORT_DIN].transport = true;
+    assert_eq!(s.transport(&cfg, MidiPort::all(), 0xFC), MidiPort::all());
+}
+
+#[test]
+fn start_restarts_division_grid() {
+    let mut cfg = ports();
+    cfg[PORT_DIN].rate = ClockRate::Divide(2);
+    let mut s = ClockShaper::new();
+    s.tick(&cfg, MidiPort::DIN, 0, 1000);
+    drain(&mut s, 0);
+    s.transport(&cfg, MidiPort::all(), 0xFA);
+    // First tick after Start is on the grid again
+    s.tick(&cfg, MidiPort::DIN, 1000, 1000);
+    assert_eq!(drain(&mut s, 1000), (1, 0));
+}
+
+#[test]
+fn external_clock_measures_interval() {
+    let mut cfg = ports();
+    cfg[PORT_USB].rate = ClockRate::Multiply(2);
+    let mut s = ClockShaper::new();
+    // Interval unknown on the first tick: a single tick, no burst
+    s.external_tick(&cfg, MidiPort::USB, 10_000);
+    assert_eq!(drain(&mut s, 10_000), (0, 1));
+    assert_eq!(s.next_due_in(10_000), None);
+    s.external_tick(&cfg, MidiPort::USB, 12_000);
+    assert_eq!(drain(&mut s, 12_000), (0, 1));
+    assert_eq!(s.next_due_in(12_000), Some(1000));
+}
+
+#[test]
+fn external_clock_reuses_interval_after_stop() {
+    let mut cfg = ports();
+    cfg[PORT_USB].rate = ClockRate::Multiply(2);
+    let mut s = ClockShaper::new();
+    s.external_tick(&cfg, MidiPort::USB, 10_000);
+    s.external_tick(&cfg, MidiPort::USB, 12_000);
+    drain(&mut s, 13_000);
+    s.transport(&cfg, MidiPort::all(), 0xFC);
+    s.transport(&cfg, MidiPort::all(), 0xFA);
+    s.external_tick(&cfg, MidiPort::USB, 500_000);
+    assert_eq!(drain(&mut s, 500_000), (0, 1));
+    assert_eq!(s.next_due_in(500_000), Some(1000), "spread, not a burst");
+}
+
+#[test]
+fn external_clock_takes_over_from_internal() {
+    let mut src = ClockSource::new();
+    assert!(src.internal_tick(0));
+    assert!(src.external_tick(10));
+    assert!(!src.internal_tick(20), "external clock drives");
+    assert!(!src.internal_tick(260));
+    assert!(src.internal_tick(261), "external clock stopped");
+}
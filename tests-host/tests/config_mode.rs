@@ -1,15 +1,20 @@
 // Host-side tests for src/config_mode.rs
 
+#[path = "../../src/bank.rs"]
+mod bank;
+
 #[path = "../../src/events.rs"]
 mod events;
 
 #[path = "../../src/config_mode.rs"]
 mod config_mode;
 
+use bank::Banks;
 use config_mode::{ButtonAction, ConfigContext, ConfigDisplayEvent, ConfigMode};
 use events::{Edge, InputEvent, Pulse};
 
 fn test_context() -> ConfigContext<'static> {
+    static BANKS: Banks = Banks::new();
     static ACTIONS: [ButtonAction; 6] = [
         ButtonAction {
             summary: heapless::String::new(),
@@ -43,6 +48,7 @@ fn test_context() -> ConfigContext<'static> {
         button_actions: &ACTIONS,
         encoder_configs: [config_mode::EncoderInfo::default(), config_mode::EncoderInfo::default()],
         analog_configs: [config_mode::AnalogInfo::default(), config_mode::AnalogInfo::default()],
+        banks: &BANKS,
     }
 }
 
@@ -232,3 +238,128 @@ fn info_screen_on_entry() {
 
     assert!(result.iter().any(|e| matches!(e, ConfigDisplayEvent::Info(info) if info.preset_count == 5)));
 }
+
+#[test]
+fn vol_click_toggles_storage_screen() {
+    let mut cm = ConfigMode::new();
+    let ctx = test_context();
+
+    // Enter config mode and let go.
+    let events = [
+        InputEvent::VolButton(Edge::Activate),
+        InputEvent::GainButton(Edge::Activate),
+    ];
+    cm.process_events(&events, 0, &ctx);
+    cm.process_events(&[], 1000, &ctx);
+    let events = [
+        InputEvent::GainButton(Edge::Deactivate),
+        InputEvent::VolButton(Edge::Deactivate),
+    ];
+    assert!(cm.process_events(&events, 1100, &ctx).is_empty());
+
+    // Click Vol: storage screen, click again: back to info.
+    cm.process_events(&[InputEvent::VolButton(Edge::Activate)], 1200, &ctx);
+    let result = cm.process_events(&[InputEvent::VolButton(Edge::Deactivate)], 1300, &ctx);
+    assert!(matches!(result[..], [ConfigDisplayEvent::Storage]));
+    cm.process_events(&[InputEvent::VolButton(Edge::Activate)], 1400, &ctx);
+    let result = cm.process_events(&[InputEvent::VolButton(Edge::Deactivate)], 1500, &ctx);
+    assert!(matches!(result[..], [ConfigDisplayEvent::Info(_)]));
+}
+
+#[test]
+fn gain_click_picks_bank_switched_to_on_exit() {
+    let mut cm = ConfigMode::new();
+    let mut banks = Banks::new();
+    for name in ["Band A", "", "Band C"] {
+        banks.names.push(name.try_into().unwrap()).unwrap();
+    }
+    let ctx = ConfigContext {
+        banks: &banks,
+        ..test_context()
+    };
+    let enter_or_exit = |cm: &mut ConfigMode, now: u32| {
+        let events = [
+            InputEvent::VolButton(Edge::Activate),
+            InputEvent::GainButton(Edge::Activate),
+        ];
+        cm.process_events(&events, now, &ctx);
+        cm.process_events(&[], now + 1000, &ctx);
+        let events = [
+            InputEvent::GainButton(Edge::Deactivate),
+            InputEvent::VolButton(Edge::Deactivate),
+        ];
+        cm.process_events(&events, now + 1100, &ctx);
+    };
+    let click_gain = |cm: &mut ConfigMode, now: u32| {
+        cm.process_events(&[InputEvent::GainButton(Edge::Activate)], now, &ctx);
+        cm.process_events(&[InputEvent::GainButton(Edge::Deactivate)], now + 100, &ctx)
+    };
+
+    // Entering and leaving does not switch
+    enter_or_exit(&mut cm, 0);
+    assert!(cm.is_active());
+    enter_or_exit(&mut cm, 2000);
+    assert!(!cm.is_active());
+    assert_eq!(cm.take_bank_switch(), None);
+
+    // Clicks skip the unnamed bank and wrap around
+    enter_or_exit(&mut cm, 4000);
+    let result = click_gain(&mut cm, 6000);
+    assert!(matches!(
+        &result[..],
+        [ConfigDisplayEvent::Bank { bank: 2, name, active: false }] if name == "Band C"
+    ));
+    let result = click_gain(&mut cm, 6200);
+    assert!(matches!(
+        result[..],
+        [ConfigDisplayEvent::Bank {
+            bank: 0,
+            active: true,
+            ..
+        }]
+    ));
+    click_gain(&mut cm, 6400);
+    assert_eq!(cm.take_bank_switch(), None);
+    enter_or_exit(&mut cm, 7000);
+    assert_eq!(cm.take_bank_switch(), Some(2));
+    assert_eq!(cm.take_bank_switch(), None);
+
+    // Bank back to the active one: no switch
+    enter_or_exit(&mut cm, 9000);
+    click_gain(&mut cm, 11000);
+    click_gain(&mut cm, 11200);
+    enter_or_exit(&mut cm, 12000);
+    assert_eq!(cm.take_bank_switch(), None);
+}
+
+#[test]
+fn vol_hold_requests_snapshot() {
+    let mut cm = ConfigMode::new();
+    let ctx = test_context();
+
+    // Enter config mode and let go.
+    let events = [
+        InputEvent::VolButton(Edge::Activate),
+        InputEvent::GainButton(Edge::Activate),
+    ];
+    cm.process_events(&events, 0, &ctx);
+    cm.process_events(&[], 1000, &ctx);
+    let events = [
+        InputEvent::GainButton(Edge::Deactivate),
+        InputEvent::VolButton(Edge::Deactivate),
+    ];
+    cm.process_events(&events, 1100, &ctx);
+    assert!(!cm.take_snapshot());
+
+    // Hold Vol alone for a second: snapshot instead of the storage screen.
+    cm.process_events(&[InputEvent::VolButton(Edge::Activate)], 2000, &ctx);
+    let result = cm.process_events(&[InputEvent::VolButton(Edge::Deactivate)], 3000, &ctx);
+    assert!(matches!(result[..], [ConfigDisplayEvent::Snapshot]));
+    assert!(cm.take_snapshot());
+    assert!(!cm.take_snapshot());
+
+    // A short click does not take one.
+    cm.process_events(&[InputEvent::VolButton(Edge::Activate)], 4000, &ctx);
+    cm.process_events(&[InputEvent::VolButton(Edge::Deactivate)], 4100, &ctx);
+    assert!(!cm.take_snapshot());
+}
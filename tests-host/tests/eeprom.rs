@@ -0,0 +1,272 @@
+// Host-side tests for src/eeprom.rs
+
+#[path = "../../src/eeprom.rs"]
+mod eeprom;
+
+use eeprom::{Eeprom, EEPROM_ADDR, EEPROM_SIZE, PAGE_SIZE};
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, Operation};
+use futures::executor::block_on;
+use midi_controller::state::{DefaultPresetState, DefaultPresetStateStore};
+
+/// AT24CS01 model: a write sets the word address and then stores bytes,
+/// wrapping within the 8-byte page; reads continue from the word address.
+struct MockEeprom {
+    mem: [u8; EEPROM_SIZE],
+    addr: usize,
+    /// Bytes that can still be written before power is cut (None = no cut).
+    power_budget: Option<usize>,
+    /// Power comes back after the cut (a bus glitch rather than a reboot).
+    recover: bool,
+    page_writes: usize,
+}
+
+impl MockEeprom {
+    fn new() -> Self {
+        Self {
+            mem: [0xFF; EEPROM_SIZE],
+            addr: 0,
+            power_budget: None,
+            recover: false,
+            page_writes: 0,
+        }
+    }
+}
+
+impl ErrorType for MockEeprom {
+    type Error = ErrorKind;
+}
+
+impl I2c for MockEeprom {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        assert_eq!(address, EEPROM_ADDR);
+        for op in operations {
+            match op {
+                Operation::Write(bytes) => {
+                    let Some((&addr, data)) = bytes.split_first() else {
+                        continue;
+                    };
+                    self.addr = addr as usize % EEPROM_SIZE;
+                    if !data.is_empty() {
+                        self.page_writes += 1;
+                    }
+                    let page = self.addr - self.addr % PAGE_SIZE;
+                    for (i, &b) in data.iter().enumerate() {
+                        match self.power_budget {
+                            Some(0) => {
+                                if self.recover {
+                                    self.power_budget = None;
+                                }
+                                return Err(ErrorKind::Other);
+                            }
+                            Some(ref mut left) => *left -= 1,
+                            None => {}
+                        }
+                        self.mem[page + (self.addr + i) % PAGE_SIZE] = b;
+                    }
+                }
+                Operation::Read(buf) => {
+                    for b in buf.iter_mut() {
+                        *b = self.mem[self.addr];
+                        self.addr = (self.addr + 1) % EEPROM_SIZE;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// State image with preset `active` selected and some toggles, cycles and
+/// encoder values set.
+fn image(active: u8, seed: u8) -> [u8; EEPROM_SIZE] {
+    let mut store = DefaultPresetStateStore::new();
+    for i in 0..midi_controller::state::EEPROM_MAX_PRESETS {
+        let mut state = DefaultPresetState::default();
+        state.button_active[i % 6] = true;
+        state.cycle_index[(i + 1) % 6] = (seed + i as u8) % 12;
+        state.encoder_values = [seed, 127 - i as u8];
+        store.set_state(i, state);
+    }
+    let mut buf = [0u8; EEPROM_SIZE];
+    store.to_eeprom(&mut buf);
+    buf[1] = active;
+    buf
+}
+
+fn save(eeprom: &mut Eeprom<&mut MockEeprom>, image: &[u8; EEPROM_SIZE]) -> Result<(), ErrorKind> {
+    block_on(eeprom.save(image, || async {}))
+}
+
+/// Boot: a fresh driver over the same memory.
+fn boot(mock: &mut MockEeprom) -> Option<[u8; EEPROM_SIZE]> {
+    Eeprom::new(mock).load().unwrap()
+}
+
+#[test]
+fn blank_eeprom_has_no_state() {
+    let mut mock = MockEeprom::new();
+    assert_eq!(boot(&mut mock), None);
+}
+
+#[test]
+fn saved_state_survives_reboot() {
+    let mut mock = MockEeprom::new();
+    let want = image(3, 5);
+    save(&mut Eeprom::new(&mut mock), &want).unwrap();
+    assert_eq!(boot(&mut mock), Some(want));
+    // One 64-byte slot in 8-byte pages
+    assert_eq!(mock.page_writes, 8);
+}
+
+#[test]
+fn saves_alternate_slots_and_newest_wins() {
+    let mut mock = MockEeprom::new();
+    let mut eeprom = Eeprom::new(&mut mock);
+    save(&mut eeprom, &image(1, 1)).unwrap();
+    save(&mut eeprom, &image(2, 2)).unwrap();
+    save(&mut eeprom, &image(4, 3)).unwrap();
+    assert_eq!(boot(&mut mock), Some(image(4, 3)));
+
+    // Keeps alternating after a reboot
+    let mut eeprom = Eeprom::new(&mut mock);
+    eeprom.load().unwrap();
+    save(&mut eeprom, &image(5, 4)).unwrap();
+    assert_eq!(boot(&mut mock), Some(image(5, 4)));
+}
+
+#[test]
+fn sequence_number_wraps() {
+    let mut mock = MockEeprom::new();
+    let mut eeprom = Eeprom::new(&mut mock);
+    for n in 0..300u32 {
+        save(&mut eeprom, &image((n % 32) as u8, (n % 100) as u8)).unwrap();
+    }
+    assert_eq!(boot(&mut mock), Some(image((299 % 32) as u8, 99)));
+}
+
+#[test]
+fn power_loss_mid_write_keeps_previous_state() {
+    for cut in [0, 1, 20, 40, 63] {
+        let mut mock = MockEeprom::new();
+        let mut eeprom = Eeprom::new(&mut mock);
+        save(&mut eeprom, &image(1, 1)).unwrap();
+        save(&mut eeprom, &image(2, 2)).unwrap();
+
+        mock.power_budget = Some(cut);
+        let mut eeprom = Eeprom::new(&mut mock);
+        eeprom.load().unwrap();
+        assert!(save(&mut eeprom, &image(7, 9)).is_err());
+
+        mock.power_budget = None;
+        assert_eq!(boot(&mut mock), Some(image(2, 2)), "cut after {cut} bytes");
+    }
+}
+
+#[test]
+fn corrupt_slot_falls_back_to_the_other() {
+    let mut mock = MockEeprom::new();
+    let mut eeprom = Eeprom::new(&mut mock);
+    save(&mut eeprom, &image(1, 1)).unwrap();
+    save(&mut eeprom, &image(2, 2)).unwrap();
+    // Second save went to the upper slot
+    mock.mem[64 + 10] ^= 0x04;
+    assert_eq!(boot(&mut mock), Some(image(1, 1)));
+}
+
+#[test]
+fn legacy_unslotted_image_is_read() {
+    let mut mock = MockEeprom::new();
+    mock.mem = image(6, 4);
+    assert_eq!(boot(&mut mock), Some(image(6, 4)));
+
+    // The next save replaces it with a slot
+    let mut eeprom = Eeprom::new(&mut mock);
+    eeprom.load().unwrap();
+    save(&mut eeprom, &image(0, 0)).unwrap();
+    assert_eq!(boot(&mut mock), Some(image(0, 0)));
+}
+
+#[test]
+fn slot_records_the_bank() {
+    let mut mock = MockEeprom::new();
+    let mut eeprom = Eeprom::new(&mut mock);
+    eeprom.set_bank(3);
+    save(&mut eeprom, &image(2, 2)).unwrap();
+    let mut eeprom = Eeprom::new(&mut mock);
+    assert_eq!(eeprom.load().unwrap(), Some(image(2, 2)));
+    assert_eq!(eeprom.bank(), 3);
+
+    // Same state for another bank is a change
+    eeprom.set_bank(0);
+    save(&mut eeprom, &image(2, 2)).unwrap();
+    assert_eq!(eeprom.writes(), 8);
+    let mut eeprom = Eeprom::new(&mut mock);
+    eeprom.load().unwrap();
+    assert_eq!(eeprom.bank(), 0);
+
+    // Older firmware's images belong to bank 0
+    mock.mem = image(6, 4);
+    let mut eeprom = Eeprom::new(&mut mock);
+    eeprom.set_bank(5);
+    eeprom.load().unwrap();
+    assert_eq!(eeprom.bank(), 0);
+}
+
+#[test]
+fn unchanged_state_is_not_rewritten() {
+    let mut mock = MockEeprom::new();
+    save(&mut Eeprom::new(&mut mock), &image(3, 5)).unwrap();
+
+    let mut eeprom = Eeprom::new(&mut mock);
+    eeprom.load().unwrap();
+    save(&mut eeprom, &image(3, 5)).unwrap();
+    save(&mut eeprom, &image(3, 5)).unwrap();
+    assert_eq!(eeprom.writes(), 0);
+    assert_eq!(mock.page_writes, 8);
+}
+
+#[test]
+fn small_change_writes_only_dirty_pages() {
+    let mut mock = MockEeprom::new();
+    let mut eeprom = Eeprom::new(&mut mock);
+    save(&mut eeprom, &image(3, 5)).unwrap();
+    save(&mut eeprom, &image(4, 5)).unwrap();
+    mock.page_writes = 0;
+
+    // Back into the first slot with one toggle flipped
+    let mut want = image(3, 5);
+    let offset = 2 + 8 * DefaultPresetState::SIZE;
+    let mut state = DefaultPresetState::from_bytes(&want[offset..]);
+    state.button_active[5] = !state.button_active[5];
+    state.to_bytes(&mut want[offset..]);
+
+    let mut eeprom = Eeprom::new(&mut mock);
+    eeprom.load().unwrap();
+    save(&mut eeprom, &want).unwrap();
+    // Header, the toggled page and the CRC
+    assert!(mock.page_writes <= 3, "{} pages", mock.page_writes);
+    assert_eq!(boot(&mut mock), Some(want));
+}
+
+#[test]
+fn failed_write_is_retried() {
+    let mut mock = MockEeprom::new();
+    let mut eeprom = Eeprom::new(&mut mock);
+    save(&mut eeprom, &image(1, 1)).unwrap();
+    save(&mut eeprom, &image(2, 2)).unwrap();
+
+    // One write glitches partway, the retry goes through
+    mock.power_budget = Some(20);
+    mock.recover = true;
+    let mut eeprom = Eeprom::new(&mut mock);
+    eeprom.load().unwrap();
+    assert!(save(&mut eeprom, &image(1, 2)).is_err());
+    assert!(eeprom.take_error());
+    save(&mut eeprom, &image(1, 2)).unwrap();
+    assert!(!eeprom.take_error());
+    assert_eq!(boot(&mut mock), Some(image(1, 2)));
+}
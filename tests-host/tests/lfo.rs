@@ -0,0 +1,151 @@
+// Host-side tests for src/lfo.rs
+
+#[path = "../../src/lfo.rs"]
+mod lfo;
+
+use lfo::{shape, Division, Lfo, LfoConfig, LfoControl, Waveform, PPQN};
+
+fn config(waveform: Waveform, division: Division) -> LfoConfig {
+    LfoConfig {
+        channel: 2,
+        cc: 74,
+        waveform,
+        division,
+        min: 0,
+        max: 127,
+    }
+}
+
+fn started(cfg: LfoConfig) -> Lfo {
+    let mut lfo = Lfo::new();
+    lfo.configure(Some(cfg));
+    lfo.control(LfoControl::Start, 3);
+    lfo
+}
+
+/// Values sent over `ticks` clock ticks (None = no change sent).
+fn run(lfo: &mut Lfo, ticks: usize) -> Vec<Option<u8>> {
+    (0..ticks).map(|_| lfo.tick().map(|m| m[2])).collect()
+}
+
+#[test]
+fn division_ticks() {
+    assert_eq!(Division::Quarter.ticks(), PPQN);
+    assert_eq!(Division::Sixteenth.ticks(), 6);
+    assert_eq!(Division::QuarterTriplet.ticks(), 16);
+    assert_eq!(Division::DottedQuarter.ticks(), 36);
+    assert_eq!(Division::FourBars.ticks(), 384);
+}
+
+#[test]
+fn sine_shape() {
+    assert_eq!(shape(Waveform::Sine, 0, 24, 0), 64);
+    assert_eq!(shape(Waveform::Sine, 6, 24, 0), 127);
+    assert_eq!(shape(Waveform::Sine, 12, 24, 0), 64);
+    assert_eq!(shape(Waveform::Sine, 18, 24, 0), 0);
+    // Interpolated between table entries on long cycles
+    assert_eq!(shape(Waveform::Sine, 3, 384, 0), 67);
+}
+
+#[test]
+fn triangle_and_square_shapes() {
+    assert_eq!(shape(Waveform::Triangle, 0, 24, 0), 0);
+    assert_eq!(shape(Waveform::Triangle, 6, 24, 0), 63);
+    assert_eq!(shape(Waveform::Triangle, 12, 24, 0), 127);
+    assert_eq!(shape(Waveform::Triangle, 18, 24, 0), 64);
+    assert_eq!(shape(Waveform::Square, 11, 24, 0), 127);
+    assert_eq!(shape(Waveform::Square, 12, 24, 0), 0);
+    assert_eq!(shape(Waveform::SampleHold, 5, 24, 99), 99);
+}
+
+#[test]
+fn tick_sends_cc_on_configured_channel() {
+    let mut lfo = started(config(Waveform::Sine, Division::Quarter));
+    assert_eq!(lfo.tick(), Some([0xB1, 74, 64]));
+    assert_eq!(lfo.button(), Some(3));
+}
+
+#[test]
+fn square_sends_only_changes() {
+    let mut lfo = started(config(Waveform::Square, Division::Eighth));
+    let sent: Vec<_> = run(&mut lfo, 24).into_iter().flatten().collect();
+    // Two cycles of an eighth note at 24 ppqn: high/low twice
+    assert_eq!(sent, [127, 0, 127, 0]);
+}
+
+#[test]
+fn sample_hold_changes_once_per_cycle() {
+    let mut lfo = started(config(Waveform::SampleHold, Division::Sixteenth));
+    let out = run(&mut lfo, 24);
+    for (i, v) in out.iter().enumerate() {
+        if i % 6 != 0 {
+            assert_eq!(*v, None, "tick {i}");
+        }
+    }
+    assert!(out[0].is_some());
+}
+
+#[test]
+fn range_scales_and_inverts() {
+    let mut cfg = config(Waveform::Square, Division::Quarter);
+    cfg.min = 20;
+    cfg.max = 100;
+    let mut lfo = started(cfg);
+    let sent: Vec<_> = run(&mut lfo, 24).into_iter().flatten().collect();
+    assert_eq!(sent, [100, 20]);
+
+    cfg.min = 100;
+    cfg.max = 20;
+    let mut lfo = started(cfg);
+    let sent: Vec<_> = run(&mut lfo, 24).into_iter().flatten().collect();
+    assert_eq!(sent, [20, 100]);
+}
+
+#[test]
+fn toggle_and_stop() {
+    let mut lfo = Lfo::new();
+    // Nothing to start without a setup
+    lfo.control(LfoControl::Toggle, 0);
+    assert!(!lfo.is_running());
+
+    lfo.configure(Some(config(Waveform::Triangle, Division::Quarter)));
+    lfo.control(LfoControl::Toggle, 0);
+    assert!(lfo.is_running());
+    assert!(lfo.tick().is_some());
+    lfo.control(LfoControl::Toggle, 0);
+    assert!(!lfo.is_running());
+    assert_eq!(lfo.tick(), None);
+    assert_eq!(lfo.button(), None);
+}
+
+#[test]
+fn new_setup_stops_same_setup_keeps_running() {
+    let cfg = config(Waveform::Sine, Division::Half);
+    let mut lfo = started(cfg);
+    lfo.configure(Some(cfg));
+    assert!(lfo.is_running());
+    lfo.configure(Some(config(Waveform::Square, Division::Half)));
+    assert!(!lfo.is_running());
+}
+
+#[test]
+fn fill_follows_waveform() {
+    let mut lfo = started(config(Waveform::Triangle, Division::Quarter));
+    lfo.tick();
+    assert_eq!(lfo.fill(), 0);
+    run(&mut lfo, 12);
+    assert_eq!(lfo.fill(), 12);
+}
+
+#[test]
+fn ring_updates_only_on_fill_change() {
+    let mut lfo = started(config(Waveform::Square, Division::Quarter));
+    lfo.tick();
+    assert_eq!(lfo.ring_update(), Some((3, 12)));
+    lfo.tick();
+    assert_eq!(lfo.ring_update(), None, "same fill");
+    run(&mut lfo, 11);
+    assert_eq!(lfo.ring_update(), Some((3, 0)));
+    lfo.stop();
+    assert_eq!(lfo.ring_update(), None);
+}
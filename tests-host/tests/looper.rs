@@ -0,0 +1,168 @@
+// Host-side tests for src/looper.rs
+
+#[path = "../../src/looper.rs"]
+mod looper;
+
+use looper::{Looper, LooperConfig, LooperMessages, LooperRole, LooperState};
+
+fn config(sync_beats: u8) -> LooperConfig {
+    LooperConfig {
+        messages: LooperMessages {
+            record: Some([0xB0, 60, 127]),
+            play: Some([0xB0, 61, 127]),
+            overdub: Some([0xB0, 62, 127]),
+            stop: Some([0xB0, 63, 127]),
+            undo: Some([0xB0, 64, 127]),
+            redo: Some([0xB0, 65, 127]),
+            clear: None,
+        },
+        record_to_overdub: false,
+        sync_beats,
+    }
+}
+
+#[test]
+fn main_button_cycles_record_play_overdub() {
+    let cfg = config(0);
+    let mut l = Looper::new();
+    let t = l.press(&cfg, LooperRole::Main, 0).unwrap();
+    assert_eq!(t.to, LooperState::Recording);
+    assert_eq!(t.message, Some([0xB0, 60, 127]));
+    assert_eq!(
+        l.press(&cfg, LooperRole::Main, 100).unwrap().to,
+        LooperState::Playing
+    );
+    assert_eq!(
+        l.press(&cfg, LooperRole::Main, 200).unwrap().to,
+        LooperState::Overdubbing
+    );
+    assert_eq!(
+        l.press(&cfg, LooperRole::Main, 300).unwrap().to,
+        LooperState::Playing
+    );
+}
+
+#[test]
+fn record_to_overdub_skips_playback() {
+    let mut cfg = config(0);
+    cfg.record_to_overdub = true;
+    let mut l = Looper::new();
+    l.press(&cfg, LooperRole::Main, 0);
+    assert_eq!(
+        l.press(&cfg, LooperRole::Main, 100).unwrap().to,
+        LooperState::Overdubbing
+    );
+}
+
+#[test]
+fn stop_then_main_restarts_playback() {
+    let cfg = config(0);
+    let mut l = Looper::new();
+    assert!(
+        l.press(&cfg, LooperRole::Stop, 0).is_none(),
+        "nothing to stop"
+    );
+    l.press(&cfg, LooperRole::Main, 0);
+    let t = l.press(&cfg, LooperRole::Stop, 100).unwrap();
+    assert_eq!(
+        (t.from, t.to),
+        (LooperState::Recording, LooperState::Stopped)
+    );
+    assert_eq!(
+        l.press(&cfg, LooperRole::Main, 200).unwrap().to,
+        LooperState::Playing
+    );
+}
+
+#[test]
+fn undo_redo_alternate_after_overdub() {
+    let cfg = config(0);
+    let mut l = Looper::new();
+    l.press(&cfg, LooperRole::Main, 0);
+    l.press(&cfg, LooperRole::Main, 10);
+    assert!(
+        l.press(&cfg, LooperRole::UndoRedo, 20).is_none(),
+        "no layer yet"
+    );
+    l.press(&cfg, LooperRole::Main, 30);
+    l.press(&cfg, LooperRole::Main, 40);
+    let undo = l.press(&cfg, LooperRole::UndoRedo, 50).unwrap();
+    assert_eq!(undo.message, Some([0xB0, 64, 127]));
+    assert_eq!(undo.to, LooperState::Playing);
+    let redo = l.press(&cfg, LooperRole::UndoRedo, 60).unwrap();
+    assert_eq!(redo.message, Some([0xB0, 65, 127]));
+}
+
+#[test]
+fn clear_resets_to_empty() {
+    let cfg = config(0);
+    let mut l = Looper::new();
+    l.press(&cfg, LooperRole::Main, 0);
+    let t = l.press(&cfg, LooperRole::Clear, 10).unwrap();
+    assert_eq!(t.to, LooperState::Empty);
+    assert_eq!(t.message, None);
+}
+
+/// Feed `count` clock ticks at 20 ms (125 BPM) from `start_ms`, returning the
+/// first transition and the tick that fired it.
+fn run_clock(
+    l: &mut Looper,
+    cfg: &LooperConfig,
+    start_ms: u32,
+    count: u32,
+) -> Option<(u32, looper::Transition)> {
+    (1..=count).find_map(|n| l.clock_tick(cfg, start_ms + n * 20).map(|t| (n, t)))
+}
+
+#[test]
+fn bar_sync_defers_to_next_bar() {
+    // 4/4: a bar is 96 ticks from the start of the recording
+    let cfg = config(4);
+    let mut l = Looper::new();
+    l.clock_tick(&cfg, 0);
+    l.press(&cfg, LooperRole::Main, 0).unwrap();
+    assert!(run_clock(&mut l, &cfg, 0, 150).is_none());
+    assert!(l.press(&cfg, LooperRole::Main, 3000).is_none());
+    assert!(l.is_pending());
+    assert!(l.tick(&cfg, 3010).is_none(), "clock still running");
+    let (n, t) = run_clock(&mut l, &cfg, 3000, 96).unwrap();
+    assert_eq!(n, 192 - 150, "fires on the second bar line");
+    assert_eq!(t.to, LooperState::Playing);
+    assert!(!l.is_pending());
+}
+
+#[test]
+fn bar_sync_second_press_cancels() {
+    let cfg = config(4);
+    let mut l = Looper::new();
+    l.clock_tick(&cfg, 0);
+    l.press(&cfg, LooperRole::Main, 0);
+    l.press(&cfg, LooperRole::Main, 10);
+    l.press(&cfg, LooperRole::Main, 15);
+    assert!(!l.is_pending());
+    assert!(run_clock(&mut l, &cfg, 0, 96).is_none());
+    assert_eq!(l.state(), LooperState::Recording);
+}
+
+#[test]
+fn bar_sync_without_clock_fires_immediately() {
+    let cfg = config(4);
+    let mut l = Looper::new();
+    l.press(&cfg, LooperRole::Main, 0);
+    assert_eq!(
+        l.press(&cfg, LooperRole::Main, 100).unwrap().to,
+        LooperState::Playing
+    );
+}
+
+#[test]
+fn bar_sync_fires_when_clock_stops() {
+    let cfg = config(4);
+    let mut l = Looper::new();
+    l.clock_tick(&cfg, 0);
+    l.press(&cfg, LooperRole::Main, 0);
+    l.press(&cfg, LooperRole::Main, 100);
+    assert!(l.is_pending());
+    assert!(l.tick(&cfg, 250).is_none());
+    assert_eq!(l.tick(&cfg, 251).unwrap().to, LooperState::Playing);
+}
@@ -0,0 +1,230 @@
+// Host-side tests for src/migrate.rs
+//
+// Fixtures of legacy layouts are frozen bytes as stored in flash, written by
+// firmware built against the midi-controller release named next to each.
+// Never regenerate them from the legacy structs.
+
+pub const FLASH_FORMAT_VERSION: u8 = midi_controller::config::PRESET_SCHEMA_VERSION;
+pub const LOCAL_FORMAT_VERSION: u8 = 1;
+
+#[path = "../../src/bank.rs"]
+mod bank;
+
+#[path = "../../src/clock_out.rs"]
+mod clock_out;
+
+#[path = "../../src/lfo.rs"]
+mod lfo;
+
+#[path = "../../src/looper.rs"]
+mod looper;
+
+#[path = "../../src/preset_ext.rs"]
+mod preset_ext;
+
+#[path = "../../src/profile.rs"]
+mod profile;
+
+#[path = "../../src/setlist.rs"]
+mod setlist;
+
+#[path = "../../src/settings.rs"]
+mod settings;
+
+#[path = "../../src/slots.rs"]
+mod slots;
+
+#[path = "../../src/transport.rs"]
+mod transport;
+
+#[path = "../../src/migrate.rs"]
+mod migrate;
+
+use midi_controller::config::{
+    Action, ButtonConfig, ButtonMode, Color, EncoderAction, EncoderConfig, GlobalConfig,
+    InitialState, Label, LedConfig, LedRenderer, Preset,
+};
+use migrate::{
+    encode, load, Loaded, GLOBAL_CONFIG_LAYOUTS, PRESET_EXT_LAYOUTS, PRESET_LAYOUTS,
+    SETTINGS_LAYOUTS,
+};
+use preset_ext::PresetExt;
+use settings::Settings;
+
+/// Schema 6 preset from midi-controller 0.7.1 (no preset tempo yet): "Live"
+/// with a "Drive" toggle on CC 80, a "Vol" encoder on CC 7 with a blue fill
+/// ring and PC 3 on channel 2 on enter.
+const PRESET_V6: &[u8] = &[
+    0x06, // version byte
+    0x04, 0x4c, 0x69, 0x76, 0x65, 0x01, 0x05, 0x44, 0x72, 0x69, 0x76, 0x65, 0x01, 0x00, 0x00, 0x00,
+    0x00, 0x01, 0x01, 0x00, 0xb0, 0x50, 0x7f, 0x03, 0x01, 0x00, 0xb0, 0x50, 0x00, 0x03, 0x00, 0x00,
+    0x00, 0x01, 0x03, 0x56, 0x6f, 0x6c, 0x00, 0x07, 0x01, 0x00, 0x7f, 0x03, 0x00, 0x00, 0x01, 0x00,
+    0x00, 0x01, 0x01, 0x01, 0x64, 0x01, 0x00, 0xc1, 0x03, 0x00, 0x02, 0x00, 0x00,
+];
+
+/// Schema 5 preset from midi-controller 0.4.1: `PRESET_V6` before encoders
+/// had a ring config.
+const PRESET_V5: &[u8] = &[
+    0x05, // version byte
+    0x04, 0x4c, 0x69, 0x76, 0x65, 0x01, 0x05, 0x44, 0x72, 0x69, 0x76, 0x65, 0x01, 0x00, 0x00, 0x00,
+    0x00, 0x01, 0x01, 0x00, 0xb0, 0x50, 0x7f, 0x03, 0x01, 0x00, 0xb0, 0x50, 0x00, 0x03, 0x00, 0x00,
+    0x00, 0x01, 0x03, 0x56, 0x6f, 0x6c, 0x00, 0x07, 0x01, 0x00, 0x7f, 0x00, 0x01, 0x01, 0x01, 0x64,
+    0x01, 0x00, 0xc1, 0x03, 0x00, 0x02, 0x00, 0x00,
+];
+
+/// Schema 5 global config from midi-controller 0.4.1: clock on at 140 BPM,
+/// USB to DIN thru, calibrated expression pedal 1.
+const GLOBAL_CONFIG_V5: &[u8] = &[
+    0x05, // version byte
+    0x01, 0x00, 0x01, 0x00, 0x01, 0x8c, 0x01, 0xb4, 0x01, 0xf4, 0x1c, 0x00, 0xa6, 0x1d,
+];
+
+fn vec<T, const N: usize>(items: impl IntoIterator<Item = T>) -> heapless::Vec<T, N> {
+    items.into_iter().collect()
+}
+
+/// `PRESET_V6` in the current layout, with `ring` for the encoder.
+fn upgraded_preset(ring: LedConfig) -> Preset {
+    Preset {
+        name: Label::try_from("Live").unwrap(),
+        buttons: vec([ButtonConfig {
+            label: Label::try_from("Drive").unwrap(),
+            color: LedConfig {
+                on: Color::Red,
+                ..LedConfig::default()
+            },
+            mode: ButtonMode::Toggle,
+            on_press: vec([Action::cc(80, 127, 1).unwrap()]),
+            on_release: vec([Action::cc(80, 0, 1).unwrap()]),
+            on_long_press: heapless::Vec::new(),
+            cycle_values: heapless::Vec::new(),
+            listen_cc: None,
+        }]),
+        encoders: vec([EncoderConfig {
+            label: Label::try_from("Vol").unwrap(),
+            action: EncoderAction::Cc {
+                cc: 7,
+                channel: 1,
+                min: 0,
+                max: 127,
+            },
+            color: ring,
+        }]),
+        analog: heapless::Vec::new(),
+        defaults: InitialState {
+            button_active: vec([true]),
+            encoder_values: vec([100]),
+        },
+        on_enter: vec([Action::program_change(3, 2).unwrap()]),
+        on_exit: heapless::Vec::new(),
+        triggers: heapless::Vec::new(),
+        bpm: 0,
+    }
+}
+
+#[test]
+fn current_layout_is_not_upgraded() {
+    let mut value = Settings::new();
+    value.mmc_device = 0x10;
+    let mut buf = [0u8; 257];
+    let stored = encode(&value, LOCAL_FORMAT_VERSION, &mut buf)
+        .unwrap()
+        .to_vec();
+    assert_eq!(stored[0], LOCAL_FORMAT_VERSION);
+    assert_eq!(
+        load(&stored, LOCAL_FORMAT_VERSION, SETTINGS_LAYOUTS),
+        Loaded::Current(value)
+    );
+
+    let stored = encode(&PresetExt::default(), LOCAL_FORMAT_VERSION, &mut buf)
+        .unwrap()
+        .to_vec();
+    assert_eq!(
+        load(&stored, LOCAL_FORMAT_VERSION, PRESET_EXT_LAYOUTS),
+        Loaded::Current(PresetExt::default())
+    );
+}
+
+#[test]
+fn schema_6_preset_is_upgraded_and_rewritten() {
+    let want = upgraded_preset(LedConfig {
+        on: Color::Blue,
+        renderer: LedRenderer::Fill,
+        ..LedConfig::default()
+    });
+    assert_eq!(
+        load(PRESET_V6, FLASH_FORMAT_VERSION, PRESET_LAYOUTS),
+        Loaded::Upgraded(want.clone())
+    );
+
+    let mut buf = [0u8; 257];
+    let stored = encode(&want, FLASH_FORMAT_VERSION, &mut buf)
+        .unwrap()
+        .to_vec();
+    assert_eq!(stored[0], FLASH_FORMAT_VERSION);
+    assert_eq!(
+        load(&stored, FLASH_FORMAT_VERSION, PRESET_LAYOUTS),
+        Loaded::Current(want)
+    );
+}
+
+#[test]
+fn schema_5_preset_is_upgraded() {
+    // Encoders gain the schema 6 default: a heatmap ring
+    let want = upgraded_preset(EncoderConfig::default().color);
+    assert_eq!(want.encoders[0].color.renderer, LedRenderer::Heatmap);
+    assert_eq!(
+        load(PRESET_V5, FLASH_FORMAT_VERSION, PRESET_LAYOUTS),
+        Loaded::Upgraded(want)
+    );
+}
+
+#[test]
+fn schema_5_global_config_is_upgraded() {
+    let want = GlobalConfig {
+        din_enabled: true,
+        din_to_usb_thru: false,
+        usb_to_din_thru: true,
+        usb_to_usb_thru: false,
+        midi_clock: true,
+        bpm: 140,
+        exp1_min: 180,
+        exp1_max: 3700,
+        exp2_min: 0,
+        exp2_max: 3750,
+    };
+    assert_eq!(
+        load(
+            GLOBAL_CONFIG_V5,
+            FLASH_FORMAT_VERSION,
+            GLOBAL_CONFIG_LAYOUTS
+        ),
+        Loaded::Upgraded(want)
+    );
+}
+
+#[test]
+fn unknown_version_and_corrupt_payload() {
+    let data = [FLASH_FORMAT_VERSION + 1, 0x00];
+    assert_eq!(
+        load::<Preset>(&data, FLASH_FORMAT_VERSION, PRESET_LAYOUTS),
+        Loaded::Unsupported(data[0])
+    );
+    // A known legacy version that does not decode
+    let truncated = &PRESET_V5[..PRESET_V5.len() - 4];
+    assert_eq!(
+        load::<Preset>(truncated, FLASH_FORMAT_VERSION, PRESET_LAYOUTS),
+        Loaded::Corrupt
+    );
+    // Legacy layouts must consume the whole payload
+    let mut padded = PRESET_V5.to_vec();
+    padded.push(0x00);
+    assert_eq!(
+        load::<Preset>(&padded, FLASH_FORMAT_VERSION, PRESET_LAYOUTS),
+        Loaded::Corrupt
+    );
+    assert_eq!(
+        load::<Settings>(&[], LOCAL_FORMAT_VERSION, SETTINGS_LAYOUTS),
+        Loaded::Corrupt
+    );
+}
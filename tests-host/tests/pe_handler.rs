@@ -6,15 +6,43 @@ mod events;
 #[path = "../../src/action.rs"]
 mod action;
 
+#[path = "../../src/clock_out.rs"]
+mod clock_out;
+
 #[path = "../../src/ledring.rs"]
 mod ledring;
 
+#[path = "../../src/lfo.rs"]
+mod lfo;
+
+#[path = "../../src/looper.rs"]
+mod looper;
+
+#[path = "../../src/preset_ext.rs"]
+mod preset_ext;
+
+#[path = "../../src/profile.rs"]
+mod profile;
+
+#[path = "../../src/setlist.rs"]
+mod setlist;
+
+#[path = "../../src/settings.rs"]
+mod settings;
+
+#[path = "../../src/slots.rs"]
+mod slots;
+
+#[path = "../../src/transport.rs"]
+mod transport;
+
 #[path = "../../src/pe_handler.rs"]
 mod pe_handler;
 
 use events::{Edge, InputEvent, Pulse};
 use heapless::Vec;
-use pe_handler::{MidiStep, PeHandler};
+use pe_handler::{panic_messages, MidiStep, PeHandler, StepQueue};
+use setlist::{Setlist, Song};
 use midi_controller::config::*;
 
 fn make_config() -> Config {
@@ -25,6 +53,47 @@ fn make_config() -> Config {
     Config { global: midi_controller::config::GlobalConfig::default(), presets }
 }
 
+/// Handler on preset 0 of `make_config()`, with a test's changes to the
+/// config, preset 0's extension and the settings.
+struct Setup {
+    config: Config,
+    ext: preset_ext::PresetExt,
+    settings: settings::Settings,
+}
+
+impl Setup {
+    fn new() -> Self {
+        Self {
+            config: make_config(),
+            ext: preset_ext::PresetExt::default(),
+            settings: settings::Settings::new(),
+        }
+    }
+
+    fn config(mut self, f: impl FnOnce(&mut Config)) -> Self {
+        f(&mut self.config);
+        self
+    }
+
+    fn ext(mut self, f: impl FnOnce(&mut preset_ext::PresetExt)) -> Self {
+        f(&mut self.ext);
+        self
+    }
+
+    fn settings(mut self, f: impl FnOnce(&mut settings::Settings)) -> Self {
+        f(&mut self.settings);
+        self
+    }
+
+    fn build(self) -> (Config, PeHandler) {
+        let mut h = PeHandler::new();
+        h.set_settings(&self.settings);
+        h.set_preset_ext(0, self.ext);
+        h.switch_to(0, &self.config);
+        (self.config, h)
+    }
+}
+
 fn make_test_preset() -> Preset {
     let mut buttons: Vec<ButtonConfig, MAX_BUTTONS> = Vec::new();
     // Button A: NoteOn/NoteOff (no long_press)
@@ -390,3 +459,1006 @@ fn switch_to_returns_preset_changed() {
     let r = h.switch_to(0, &config);
     assert!(r.preset_changed, "switch_to should set preset_changed flag");
 }
+
+fn make_setlist() -> Setlist {
+    let mut setlist = Setlist::new();
+    let song = |name: &str, preset: u8| Song {
+        name: Label::try_from(name).unwrap(),
+        preset,
+        scene: None,
+    };
+    // Slot 1 left empty: navigation skips it.
+    setlist.set(0, song("Intro", 1));
+    setlist.set(2, song("Outro", 0));
+    setlist
+}
+
+#[test]
+fn setlist_long_press_steps_through_songs() {
+    let config = make_config();
+    let mut h = PeHandler::new();
+    h.set_setlist(&make_setlist());
+    assert_eq!(h.active_song(), None);
+
+    h.handle_events(&config, &[InputEvent::ButtonB(Edge::Activate)], 0);
+    let mut changed = false;
+    for i in 1..=501u32 {
+        let r = h.handle_events(&config, &[], i);
+        if r.song_changed {
+            changed = true;
+            break;
+        }
+    }
+    assert!(changed);
+    assert_eq!(h.active_song(), Some(0));
+    assert_eq!(h.active_preset(), 1);
+    h.handle_events(&config, &[InputEvent::ButtonB(Edge::Deactivate)], 600);
+
+    let r = h.step_song(setlist::SongStep::Next, &config, 700);
+    assert!(r.song_changed);
+    assert_eq!(h.active_song(), Some(2));
+    assert_eq!(h.active_preset(), 0);
+}
+
+#[test]
+fn setlist_short_press_keeps_on_press() {
+    let config = make_config();
+    let mut h = PeHandler::new();
+    h.set_setlist(&make_setlist());
+    h.handle_events(&config, &[InputEvent::ButtonB(Edge::Activate)], 0);
+    let r = h.handle_events(&config, &[InputEvent::ButtonB(Edge::Deactivate)], 100);
+    assert!(!r.song_changed);
+    assert_eq!(h.active_song(), None);
+    assert!(r
+        .midi
+        .iter()
+        .any(|m| matches!(m, MidiStep::Send(d, _, _) if *d == [0xB0, 10, 127])));
+}
+
+fn make_scroll_config() -> Config {
+    let mut config = make_config();
+    config.presets[0].encoders[0].action = EncoderAction::PresetScroll;
+    config
+}
+
+fn preview_handler() -> PeHandler {
+    let mut h = PeHandler::new();
+    let mut s = settings::Settings::new();
+    s.scroll_preview = true;
+    h.set_settings(&s);
+    h
+}
+
+#[test]
+fn preset_scroll_previews_without_switching() {
+    let config = make_scroll_config();
+    let mut h = preview_handler();
+    h.switch_to(0, &config);
+    let r = h.handle_events(&config, &[InputEvent::Vol(Pulse::Clockwise)], 0);
+    assert!(r.preview_changed);
+    assert!(!r.preset_changed);
+    assert!(r.midi.is_empty(), "browsing must not send MIDI");
+    assert_eq!(h.preview(), Some(1));
+    assert_eq!(h.active_preset(), 0);
+}
+
+#[test]
+fn encoder_push_confirms_preview() {
+    let config = make_scroll_config();
+    let mut h = preview_handler();
+    h.switch_to(0, &config);
+    h.handle_events(&config, &[InputEvent::Vol(Pulse::Clockwise)], 0);
+    let r = h.handle_events(&config, &[InputEvent::VolButton(Edge::Activate)], 10);
+    assert!(r.preview_changed);
+    assert!(r.preset_changed);
+    assert_eq!(h.preview(), None);
+    assert_eq!(h.active_preset(), 1);
+}
+
+#[test]
+fn footswitch_confirms_preview_and_is_swallowed() {
+    let config = make_scroll_config();
+    let mut h = preview_handler();
+    h.switch_to(0, &config);
+    h.handle_events(&config, &[InputEvent::Vol(Pulse::Clockwise)], 0);
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 10);
+    assert!(r.preset_changed);
+    assert!(!r
+        .midi
+        .iter()
+        .any(|m| matches!(m, MidiStep::Send(d, _, _) if d[0] == 0x90)));
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 20);
+    assert!(r.midi.is_empty());
+}
+
+#[test]
+fn preview_auto_confirms_after_timeout() {
+    let config = make_scroll_config();
+    let mut h = PeHandler::new();
+    let mut s = settings::Settings::new();
+    s.scroll_preview = true;
+    s.preview_timeout_ms = 1000;
+    h.set_settings(&s);
+    h.switch_to(0, &config);
+    h.handle_events(&config, &[InputEvent::Vol(Pulse::Clockwise)], 0);
+    assert!(h.any_active());
+    let r = h.handle_events(&config, &[], 999);
+    assert!(!r.preset_changed);
+    let r = h.handle_events(&config, &[], 1000);
+    assert!(r.preset_changed);
+    assert_eq!(h.active_preset(), 1);
+}
+
+#[test]
+fn scrolling_back_to_current_preset_does_not_switch() {
+    let config = make_scroll_config();
+    let mut h = preview_handler();
+    h.switch_to(0, &config);
+    h.handle_events(&config, &[InputEvent::Vol(Pulse::Clockwise)], 0);
+    h.handle_events(&config, &[InputEvent::Vol(Pulse::CounterClockwise)], 5);
+    assert_eq!(h.preview(), Some(0));
+    let r = h.handle_events(&config, &[InputEvent::VolButton(Edge::Activate)], 10);
+    assert!(r.preview_changed);
+    assert!(!r.preset_changed);
+}
+
+#[test]
+fn scroll_preview_is_opt_in() {
+    let config = make_scroll_config();
+    let mut h = PeHandler::new();
+    h.switch_to(0, &config);
+    let r = h.handle_events(&config, &[InputEvent::Vol(Pulse::Clockwise)], 0);
+    assert!(!r.preview_changed);
+    assert_eq!(h.preview(), None);
+}
+
+fn panic_handler(trigger: settings::PanicTrigger, stops_clock: bool) -> PeHandler {
+    let mut h = PeHandler::new();
+    let mut s = settings::Settings::new();
+    s.panic = trigger;
+    s.panic_stops_clock = stops_clock;
+    h.set_settings(&s);
+    h
+}
+
+#[test]
+fn panic_messages_cover_all_channels() {
+    let msgs: std::vec::Vec<[u8; 3]> = panic_messages().collect();
+    assert_eq!(msgs.len(), 48);
+    for ch in 0..16u8 {
+        for cc in [120u8, 121, 123] {
+            assert!(msgs.contains(&[0xB0 | ch, cc, 0]));
+        }
+    }
+}
+
+#[test]
+fn panic_press_replaces_button_actions() {
+    let config = make_config();
+    let mut h = panic_handler(settings::PanicTrigger::Press(0), true);
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    assert!(r.panic);
+    assert_eq!(r.clock_running, Some(false));
+    assert!(r.midi.is_empty());
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 10);
+    assert!(!r.panic);
+    assert!(
+        r.midi.is_empty(),
+        "release of the panic button is swallowed"
+    );
+}
+
+#[test]
+fn panic_long_press_fires_after_hold() {
+    let config = make_config();
+    let mut h = panic_handler(settings::PanicTrigger::LongPress(0), false);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    let mut fired = false;
+    for i in 1..=500u32 {
+        let r = h.handle_events(&config, &[], i);
+        if r.panic {
+            assert_eq!(r.clock_running, None);
+            fired = true;
+            break;
+        }
+    }
+    assert!(fired);
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 600);
+    assert!(r.midi.is_empty());
+}
+
+#[test]
+fn panic_long_press_short_release_is_not_panic() {
+    let config = make_config();
+    let mut h = panic_handler(settings::PanicTrigger::LongPress(0), false);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 100);
+    assert!(!r.panic);
+    assert!(!h.any_active());
+}
+
+#[test]
+fn panic_chord_fires_when_all_held() {
+    let config = make_config();
+    let mut h = panic_handler(settings::PanicTrigger::Chord(0b11), false);
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    assert!(!r.panic);
+    let r = h.handle_events(&config, &[InputEvent::ButtonB(Edge::Activate)], 5);
+    assert!(r.panic);
+    assert!(!h.any_active(), "chord members are released");
+
+    // Releasing the chord runs no actions (no Note Off from button A)
+    for (edge, now) in [
+        (InputEvent::ButtonA(Edge::Deactivate), 10),
+        (InputEvent::ButtonB(Edge::Deactivate), 15),
+    ] {
+        let r = h.handle_events(&config, &[edge], now);
+        assert!(r.midi.is_empty());
+    }
+    // Later presses behave normally again
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 20);
+    assert!(!r.panic);
+    assert!(matches!(&r.midi[0], MidiStep::Send(d, _, _) if *d == [0x90, 60, 127]));
+}
+
+#[test]
+fn step_queue_holds_steps_behind_delay() {
+    use midi_controller::routing::MidiPort;
+    let mut q = StepQueue::<8>::new();
+    q.extend(&[
+        MidiStep::Send([0xB0, 1, 1], 3, MidiPort::USB),
+        MidiStep::Delay(100),
+        MidiStep::Send([0xB0, 2, 2], 3, MidiPort::USB),
+    ]);
+    assert!(matches!(q.pop_ready(0), Some(MidiStep::Send(d, _, _)) if d[1] == 1));
+    assert_eq!(q.pop_ready(0), None);
+    assert!(!q.is_empty());
+    assert_eq!(q.pop_ready(99), None);
+    assert!(matches!(q.pop_ready(100), Some(MidiStep::Send(d, _, _)) if d[1] == 2));
+    assert!(q.is_empty());
+}
+
+#[test]
+fn step_queue_clear_cancels_pending_delay() {
+    use midi_controller::routing::MidiPort;
+    let mut q = StepQueue::<8>::new();
+    q.extend(&[
+        MidiStep::Delay(100),
+        MidiStep::Send([0x80, 60, 0], 3, MidiPort::DIN),
+    ]);
+    assert_eq!(q.pop_ready(0), None);
+    q.clear();
+    assert!(q.is_empty());
+    assert_eq!(q.pop_ready(200), None);
+}
+
+#[test]
+fn step_queue_counts_dropped_steps() {
+    use midi_controller::routing::MidiPort;
+    let mut q = StepQueue::<2>::new();
+    let step = MidiStep::Send([0xB0, 1, 1], 3, MidiPort::USB);
+    assert_eq!(q.extend(&[MidiStep::Delay(10), step.clone()]), 0);
+    assert_eq!(q.extend(&[step.clone(), step]), 2);
+    assert_eq!(q.dropped(), 2);
+}
+
+fn timed_toggle_handler(length: preset_ext::Length) -> (Config, PeHandler) {
+    let mut config = make_config();
+    config.presets[0].buttons[0].mode = ButtonMode::Toggle;
+    config.presets[0].buttons[0].color.on = Color::Red;
+    let mut ext = preset_ext::PresetExt::default();
+    ext.buttons[0].auto_off = Some(preset_ext::AutoOff {
+        length,
+        countdown: true,
+    });
+    let mut h = PeHandler::new();
+    h.set_preset_ext(0, ext);
+    h.switch_to(0, &config);
+    (config, h)
+}
+
+#[test]
+fn length_beats_follow_bpm() {
+    assert_eq!(preset_ext::Length::Beats(16).as_ms(120), 8000);
+    assert_eq!(preset_ext::Length::Beats(4).as_ms(0), 2000);
+    assert_eq!(preset_ext::Length::Millis(300).as_ms(90), 300);
+}
+
+#[test]
+fn timed_toggle_reverts_after_length() {
+    let (config, mut h) = timed_toggle_handler(preset_ext::Length::Millis(1000));
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 10);
+    assert!(h.button_active()[0]);
+    assert!(h.any_active(), "running timer keeps the tick alive");
+    h.handle_events(&config, &[], 999);
+    assert!(h.button_active()[0]);
+    let r = h.handle_events(&config, &[], 1000);
+    assert!(r.leds_changed);
+    assert!(!h.button_active()[0]);
+    assert!(!h.any_active());
+}
+
+#[test]
+fn timed_toggle_manual_off_cancels_timer() {
+    let (config, mut h) = timed_toggle_handler(preset_ext::Length::Millis(1000));
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 10);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 500);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 510);
+    assert!(!h.button_active()[0]);
+    assert!(!h.any_active());
+}
+
+#[test]
+fn timed_toggle_countdown_drains_ring() {
+    let (config, mut h) = timed_toggle_handler(preset_ext::Length::Millis(1200));
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    let full = h.led_state(&config.presets[0])[0];
+    let mut rings_changed = false;
+    for t in 1..=600u32 {
+        rings_changed |= h.handle_events(&config, &[], t).rings_changed;
+    }
+    assert!(rings_changed);
+    let half = h.led_state(&config.presets[0])[0];
+    assert_ne!(full, half);
+}
+
+fn repeat_handler(config: &Config) -> PeHandler {
+    let mut ext = preset_ext::PresetExt::default();
+    ext.buttons[0].repeat = Some(preset_ext::Repeat {
+        delay_ms: 400,
+        rate_ms: 100,
+    });
+    let mut h = PeHandler::new();
+    for i in 0..config.presets.len() {
+        h.set_preset_ext(i, ext.clone());
+    }
+    h.switch_to(0, config);
+    h
+}
+
+fn note_ons(r: &pe_handler::HandleResult) -> usize {
+    r.midi
+        .iter()
+        .filter(|s| matches!(s, MidiStep::Send(data, _, _) if data[0] & 0xF0 == 0x90))
+        .count()
+}
+
+#[test]
+fn repeat_refires_press_while_held() {
+    let config = make_config();
+    let mut h = repeat_handler(&config);
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    assert_eq!(note_ons(&r), 1);
+    let mut repeats = 0;
+    for t in 1..=399u32 {
+        repeats += note_ons(&h.handle_events(&config, &[], t));
+    }
+    assert_eq!(repeats, 0, "nothing before the initial delay");
+    for t in 400..=700u32 {
+        repeats += note_ons(&h.handle_events(&config, &[], t));
+    }
+    assert_eq!(repeats, 4, "at 400, 500, 600 and 700 ms");
+    assert!(h.any_active());
+
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 750);
+    assert!(!h.any_active());
+    for t in 751..=1000u32 {
+        repeats += note_ons(&h.handle_events(&config, &[], t));
+    }
+    assert_eq!(repeats, 4);
+}
+
+#[test]
+fn repeat_ignores_buttons_with_long_press() {
+    let config = make_config();
+    let mut h = repeat_handler(&config);
+    let mut ext = preset_ext::PresetExt::default();
+    ext.buttons[1].repeat = Some(preset_ext::Repeat {
+        delay_ms: 100,
+        rate_ms: 100,
+    });
+    h.set_preset_ext(0, ext);
+    h.handle_events(&config, &[InputEvent::ButtonB(Edge::Activate)], 0);
+    let mut cc = 0;
+    for t in 1..=300u32 {
+        let r = h.handle_events(&config, &[], t);
+        cc += r
+            .midi
+            .iter()
+            .filter(|s| matches!(s, MidiStep::Send(data, _, _) if data[0] & 0xF0 == 0xB0))
+            .count();
+    }
+    assert_eq!(cc, 0);
+}
+
+#[test]
+fn repeat_steps_setlist_while_held() {
+    let mut config = make_config();
+    for preset in config.presets.iter_mut() {
+        let btn = &mut preset.buttons[0];
+        btn.on_press.clear();
+        btn.on_press.push(Action::PresetNext).ok();
+        btn.on_release.clear();
+    }
+    let mut h = repeat_handler(&config);
+    h.set_setlist(&make_setlist());
+
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    assert!(r.song_changed);
+    assert_eq!(h.active_song(), Some(0));
+    let mut songs = heapless::Vec::<u8, 8>::new();
+    for t in 1..=500u32 {
+        if h.handle_events(&config, &[], t).song_changed {
+            songs.push(h.active_song().unwrap()).ok();
+        }
+    }
+    // Repeats at 400 and 500 ms: Outro, then wrap to Intro
+    assert_eq!(songs.as_slice(), &[2, 0]);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 510);
+    assert!(!h.any_active());
+}
+
+#[test]
+fn hybrid_tap_latches() {
+    let (config, mut h) = Setup::new()
+        .config(|c| c.presets[0].buttons[0].mode = ButtonMode::Toggle)
+        .ext(|e| e.buttons[0].hybrid = Some(preset_ext::Hybrid { hold_ms: 400 }))
+        .build();
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 150);
+    assert!(h.button_active()[0]);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 1000);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 1100);
+    assert!(!h.button_active()[0]);
+}
+
+#[test]
+fn hybrid_hold_is_momentary() {
+    let (config, mut h) = Setup::new()
+        .config(|c| c.presets[0].buttons[0].mode = ButtonMode::Toggle)
+        .ext(|e| e.buttons[0].hybrid = Some(preset_ext::Hybrid { hold_ms: 400 }))
+        .build();
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    assert!(h.button_active()[0], "on while held");
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 400);
+    assert!(r.leds_changed);
+    assert!(!h.button_active()[0]);
+}
+
+#[test]
+fn hybrid_hold_on_latched_button_restores_on() {
+    let (config, mut h) = Setup::new()
+        .config(|c| c.presets[0].buttons[0].mode = ButtonMode::Toggle)
+        .ext(|e| e.buttons[0].hybrid = Some(preset_ext::Hybrid { hold_ms: 400 }))
+        .build();
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 100);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 1000);
+    assert!(!h.button_active()[0], "muted while held");
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 2000);
+    assert!(h.button_active()[0]);
+}
+
+fn conditional_handler(condition: preset_ext::Condition) -> (Config, PeHandler) {
+    let mut config = make_config();
+    config.presets[0].buttons[2].mode = ButtonMode::Toggle;
+    let mut ext = preset_ext::PresetExt::default();
+    let mut then = Vec::new();
+    then.push(Action::cc(20, 1, 1).unwrap()).ok();
+    let mut otherwise = Vec::new();
+    otherwise.push(Action::cc(20, 2, 1).unwrap()).ok();
+    ext.buttons[0].conditional = Some(preset_ext::ConditionalPress {
+        condition,
+        then,
+        otherwise,
+    });
+    let mut h = PeHandler::new();
+    h.set_preset_ext(0, ext);
+    h.switch_to(0, &config);
+    (config, h)
+}
+
+fn cc20_value(r: &pe_handler::HandleResult) -> Option<u8> {
+    r.midi.iter().find_map(|s| match s {
+        MidiStep::Send(data, _, _) if data[0] == 0xB0 && data[1] == 20 => Some(data[2]),
+        _ => None,
+    })
+}
+
+#[test]
+fn conditional_press_follows_button_state() {
+    let (config, mut h) = conditional_handler(preset_ext::Condition::ButtonActive(2));
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    assert_eq!(cc20_value(&r), Some(2));
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 10);
+
+    // Engage button C, then the same footswitch takes the other branch
+    h.handle_events(&config, &[InputEvent::ButtonC(Edge::Activate)], 20);
+    h.handle_events(&config, &[InputEvent::ButtonC(Edge::Deactivate)], 30);
+    assert!(h.button_active()[2]);
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 40);
+    assert_eq!(cc20_value(&r), Some(1));
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 50);
+    assert_eq!(cc20_value(&r), None, "release runs no branch");
+}
+
+#[test]
+fn conditional_press_checks_preset() {
+    let (config, mut h) = conditional_handler(preset_ext::Condition::PresetIs(0));
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    assert_eq!(cc20_value(&r), Some(1));
+}
+
+#[test]
+fn conditional_press_checks_encoder_value() {
+    let (config, mut h) = conditional_handler(preset_ext::Condition::EncoderAtLeast {
+        index: 0,
+        value: 64,
+    });
+    h.set_encoder_value(0, 10);
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    assert_eq!(cc20_value(&r), Some(2));
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 10);
+    h.set_encoder_value(0, 100);
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 20);
+    assert_eq!(cc20_value(&r), Some(1));
+}
+
+/// Programs 10 and 11 on channel 1 select presets 0 and 1.
+const PC_FOLLOW: settings::PcFollow = settings::PcFollow {
+    channel: 1,
+    first_program: 10,
+    count: 2,
+    first_preset: 0,
+};
+
+#[test]
+fn pc_follow_switches_mapped_preset() {
+    use midi_controller::routing::MidiPort;
+    let (config, mut h) = Setup::new()
+        .config(|c| {
+            let on_enter = &mut c.presets[1].on_enter;
+            on_enter.push(Action::program_change(5, 1).unwrap()).ok();
+            on_enter.push(Action::program_change(3, 2).unwrap()).ok();
+        })
+        .settings(|s| s.pc_follow.push(PC_FOLLOW).unwrap())
+        .build();
+    let r = h.process_incoming_midi(&config, &[0xC0, 11]);
+    assert!(r.preset_changed);
+    assert_eq!(h.active_preset(), 1);
+    assert!(
+        r.routed.iter().all(|out| !out.dest.contains(MidiPort::USB)),
+        "followed PC is not echoed back"
+    );
+    let port_of = |status: u8| {
+        r.midi.iter().find_map(|s| match s {
+            MidiStep::Send(data, _, ports) if data[0] == status => Some(*ports),
+            _ => None,
+        })
+    };
+    let same_channel = port_of(0xC0).expect("on_enter PC still sent");
+    assert!(!same_channel.contains(MidiPort::USB));
+    assert!(same_channel.contains(MidiPort::DIN));
+    assert!(port_of(0xC1).unwrap().contains(MidiPort::USB));
+}
+
+#[test]
+fn pc_follow_from_din_keeps_usb_output() {
+    use midi_controller::routing::MidiPort;
+    let (config, mut h) = Setup::new()
+        .config(|c| {
+            c.presets[1]
+                .on_enter
+                .push(Action::program_change(5, 1).unwrap())
+                .ok();
+        })
+        .settings(|s| s.pc_follow.push(PC_FOLLOW).unwrap())
+        .build();
+    let r = h.process_midi_from(&config, &[0xC0, 11], MidiPort::DIN, 0);
+    assert!(r.preset_changed);
+    let ports = r
+        .midi
+        .iter()
+        .find_map(|s| match s {
+            MidiStep::Send(data, _, ports) if data[0] == 0xC0 => Some(*ports),
+            _ => None,
+        })
+        .expect("on_enter PC still sent");
+    assert!(ports.contains(MidiPort::USB));
+    assert!(!ports.contains(MidiPort::DIN), "not echoed back to DIN");
+}
+
+#[test]
+fn pc_follow_ignores_unmapped_program_and_channel() {
+    let (config, mut h) = Setup::new()
+        .settings(|s| s.pc_follow.push(PC_FOLLOW).unwrap())
+        .build();
+    assert!(!h.process_incoming_midi(&config, &[0xC0, 12]).preset_changed);
+    assert!(!h.process_incoming_midi(&config, &[0xC1, 11]).preset_changed);
+    assert_eq!(h.active_preset(), 0);
+}
+
+#[test]
+fn pc_follow_range_maps_offsets() {
+    let f = settings::PcFollow {
+        channel: 0,
+        first_program: 5,
+        count: 3,
+        first_preset: 10,
+    };
+    assert_eq!(f.preset_for(16, 4), None);
+    assert_eq!(f.preset_for(16, 5), Some(10));
+    assert_eq!(f.preset_for(3, 7), Some(12));
+    assert_eq!(f.preset_for(3, 8), None);
+}
+
+fn sync_handler(config: &Config) -> PeHandler {
+    let mut s = settings::Settings::new();
+    s.sync_cc = true;
+    s.sync_notes = true;
+    let mut h = PeHandler::new();
+    h.set_settings(&s);
+    h.switch_to(0, config);
+    h
+}
+
+#[test]
+fn note_feedback_sets_toggle_without_output() {
+    let mut config = make_config();
+    config.presets[0].buttons[0].mode = ButtonMode::Toggle;
+    let mut h = sync_handler(&config);
+
+    let r = h.process_incoming_midi(&config, &[0x90, 60, 100]);
+    assert!(h.button_active()[0]);
+    assert!(r.leds_changed);
+    assert!(r.midi.is_empty(), "sync must not echo the toggle's actions");
+
+    // Repeated feedback is a no-op
+    let r = h.process_incoming_midi(&config, &[0x90, 60, 100]);
+    assert!(!r.leds_changed);
+
+    h.process_incoming_midi(&config, &[0x80, 60, 0]);
+    assert!(!h.button_active()[0]);
+}
+
+#[test]
+fn note_feedback_ignored_when_sync_disabled() {
+    let mut config = make_config();
+    config.presets[0].buttons[0].mode = ButtonMode::Toggle;
+    let mut h = PeHandler::new();
+    h.switch_to(0, &config);
+    h.process_incoming_midi(&config, &[0x90, 60, 100]);
+    assert!(!h.button_active()[0]);
+}
+
+#[test]
+fn cc_feedback_sets_encoder_value() {
+    let config = make_config();
+    let mut h = sync_handler(&config);
+    let before = h.led_state(&config.presets[0])[6];
+    let r = h.process_incoming_midi(&config, &[0xB0, 7, 127]);
+    assert!(r.leds_changed, "synced value is saved like a turn");
+    assert_ne!(h.led_state(&config.presets[0])[6], before);
+    assert!(r
+        .routed
+        .iter()
+        .filter(|out| out.bytes()[0] == 0xB0)
+        .all(|out| !out.dest.contains(midi_controller::routing::MidiPort::USB)));
+}
+
+/// Config whose button A cycles CC 50 through 0, 32, 64, 127.
+fn cycle_config(reverse: bool) -> Config {
+    let mut config = make_config();
+    let button = &mut config.presets[0].buttons[0];
+    button.on_press.clear();
+    button.on_release.clear();
+    button
+        .on_press
+        .push(Action::CcCycle {
+            cc: 50,
+            channel: 1,
+            reverse,
+        })
+        .ok();
+    button.cycle_values = [0, 32, 64, 127].into_iter().collect();
+    config
+}
+
+fn cycle_sent(r: &pe_handler::HandleResult) -> Option<u8> {
+    r.midi.iter().find_map(|s| match s {
+        MidiStep::Send([0xB0, 50, value], 3, _) => Some(*value),
+        _ => None,
+    })
+}
+
+#[test]
+fn cc_feedback_sets_cycle_position() {
+    for (reverse, next) in [(false, 127), (true, 32)] {
+        let config = cycle_config(reverse);
+        let mut h = sync_handler(&config);
+        let r = h.process_incoming_midi(&config, &[0xB0, 50, 64]);
+        assert!(r.leds_changed, "synced position is saved");
+        assert_eq!(cycle_sent(&r), None, "sync must not echo the cycle's CC");
+
+        let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+        assert_eq!(cycle_sent(&r), Some(next), "press continues after 64");
+        h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 10);
+    }
+}
+
+#[test]
+fn cc_feedback_ignores_value_outside_cycle() {
+    let config = cycle_config(false);
+    let mut h = sync_handler(&config);
+    let r = h.process_incoming_midi(&config, &[0xB0, 50, 65]);
+    assert!(!r.leds_changed);
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    assert_eq!(cycle_sent(&r), Some(0));
+}
+
+#[test]
+fn semantic_press_resolves_through_selected_profile() {
+    let config = make_config();
+    let mut p = profile::Profile {
+        name: Label::try_from("Helix").unwrap(),
+        channel: 1,
+        ..Default::default()
+    };
+    p.entries
+        .push(profile::ProfileEntry {
+            action: profile::Semantic::Scene,
+            template: profile::Template::CcValue { cc: 69, base: 0 },
+        })
+        .ok();
+    let mut profiles = profile::Profiles::new();
+    profiles.set(0, p);
+    let mut ext = preset_ext::PresetExt::default();
+    ext.buttons[2]
+        .semantic
+        .push(profile::SemanticAction {
+            action: profile::Semantic::Scene,
+            index: 2,
+        })
+        .ok();
+
+    let mut h = PeHandler::new();
+    h.set_preset_ext(0, ext);
+    h.set_profiles(&profiles);
+    let scene = |r: &pe_handler::HandleResult| {
+        r.midi
+            .iter()
+            .any(|s| matches!(s, MidiStep::Send([0xB0, 69, 2], 3, _)))
+    };
+
+    // No profile selected: nothing to resolve against
+    let r = h.handle_events(&config, &[InputEvent::ButtonC(Edge::Activate)], 0);
+    assert!(!scene(&r));
+    h.handle_events(&config, &[InputEvent::ButtonC(Edge::Deactivate)], 10);
+
+    let mut s = settings::Settings::new();
+    s.profile = Some(0);
+    h.set_settings(&s);
+    let r = h.handle_events(&config, &[InputEvent::ButtonC(Edge::Activate)], 20);
+    assert!(scene(&r));
+}
+
+/// Looper that records with CC 60 and plays with CC 61, synced to 4/4 bars.
+fn looper_setup() -> looper::LooperConfig {
+    looper::LooperConfig {
+        messages: looper::LooperMessages {
+            record: Some([0xB0, 60, 127]),
+            play: Some([0xB0, 61, 127]),
+            ..Default::default()
+        },
+        record_to_overdub: false,
+        sync_beats: 4,
+    }
+}
+
+#[test]
+fn looper_button_replaces_own_actions() {
+    let (config, mut h) = Setup::new()
+        .ext(|e| {
+            e.looper = Some(looper_setup());
+            e.buttons[0].looper = Some(looper::LooperRole::Main);
+        })
+        .build();
+    assert_eq!(h.looper_state(), Some(looper::LooperState::Empty));
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    assert!(r.looper_changed);
+    assert_eq!(r.midi.len(), 1, "record only, no Note On from on_press");
+    assert!(matches!(r.midi[0], MidiStep::Send([0xB0, 60, 127], 3, _)));
+    let r = h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 10);
+    assert!(r.midi.is_empty(), "release swallowed");
+    assert_eq!(h.looper_state(), Some(looper::LooperState::Recording));
+}
+
+#[test]
+fn looper_bar_sync_fires_on_tick() {
+    let (config, mut h) = Setup::new()
+        .ext(|e| {
+            e.looper = Some(looper_setup());
+            e.buttons[0].looper = Some(looper::LooperRole::Main);
+        })
+        .build();
+    h.internal_clock_tick(0);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 10);
+    for n in 1..=50 {
+        h.internal_clock_tick(n * 20);
+    }
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 1000);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 1010);
+    assert!(h.any_active(), "pending transition keeps the tick alive");
+    let fired_at = (51..=120).find(|n| h.internal_clock_tick(n * 20).looper_changed);
+    assert_eq!(fired_at, Some(96), "first bar line of 4/4");
+    assert_eq!(h.looper_state(), Some(looper::LooperState::Playing));
+}
+
+#[test]
+fn looper_follows_external_clock_over_internal() {
+    let (config, mut h) = Setup::new()
+        .ext(|e| {
+            e.looper = Some(looper_setup());
+            e.buttons[0].looper = Some(looper::LooperRole::Main);
+        })
+        .build();
+    let tick = [0xF8, 0, 0];
+    let din = midi_controller::routing::MidiPort::DIN;
+    h.process_midi_from(&config, &tick, din, 0);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 0);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Deactivate)], 10);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 20);
+    for n in 1..96 {
+        assert!(
+            !h.internal_clock_tick(n * 20).looper_changed,
+            "internal clock ignored"
+        );
+        h.process_midi_from(&config, &tick, din, n * 20);
+    }
+    let r = h.process_midi_from(&config, &tick, din, 96 * 20);
+    assert!(r.looper_changed);
+    assert_eq!(h.looper_state(), Some(looper::LooperState::Playing));
+}
+
+#[test]
+fn clock_tick_follows_clock_in_charge() {
+    let config = make_config();
+    let mut h = PeHandler::new();
+    assert!(h.internal_clock_tick(0).clock_tick);
+    let din = midi_controller::routing::MidiPort::DIN;
+    let r = h.process_midi_from(&config, &[0xF8, 0, 0], din, 10);
+    assert!(r.clock_tick, "incoming clock takes over");
+    assert!(!h.internal_clock_tick(20).clock_tick);
+    let r = h.process_midi_from(&config, &[0xB0, 7, 1], din, 30);
+    assert!(!r.clock_tick);
+}
+
+#[test]
+fn looper_state_none_without_setup() {
+    assert_eq!(PeHandler::new().looper_state(), None);
+}
+
+#[test]
+fn lfo_button_reports_control_with_preset_setup() {
+    let config = make_config();
+    let setup = lfo::LfoConfig {
+        channel: 1,
+        cc: 74,
+        waveform: lfo::Waveform::Triangle,
+        division: lfo::Division::Half,
+        min: 0,
+        max: 127,
+    };
+    let mut ext = preset_ext::PresetExt::default();
+    ext.lfo = Some(setup);
+    ext.buttons[4].lfo = Some(lfo::LfoControl::Toggle);
+    let mut h = PeHandler::new();
+    h.set_preset_ext(0, ext);
+    assert_eq!(h.lfo_config(), Some(setup));
+
+    let r = h.handle_events(&config, &[InputEvent::ButtonE(Edge::Activate)], 0);
+    assert_eq!(r.lfo, Some((lfo::LfoControl::Toggle, 4)));
+    assert!(r.rings_changed);
+    let r = h.handle_events(&config, &[InputEvent::ButtonE(Edge::Deactivate)], 10);
+    assert_eq!(r.lfo, None);
+}
+
+/// Transport trigger sending CC 85 on `on`.
+fn cc85_trigger(on: transport::TransportMatch) -> transport::TransportTrigger {
+    let mut actions = Vec::new();
+    actions
+        .push(Action::Midi {
+            data: [0xB0, 85, 127],
+            len: 3,
+        })
+        .ok();
+    transport::TransportTrigger { on, actions }
+}
+
+fn sends_cc85(r: &pe_handler::HandleResult) -> bool {
+    r.midi
+        .iter()
+        .any(|s| matches!(s, MidiStep::Send([0xB0, 85, 127], 3, _)))
+}
+
+#[test]
+fn transport_press_sends_mmc_and_spp() {
+    use transport::{MmcCommand, TransportAction};
+    let (config, mut h) = Setup::new()
+        .ext(|e| {
+            let play = TransportAction::Mmc(MmcCommand::Play);
+            e.buttons[1].transport.push(play).ok();
+            let locate = TransportAction::SongPosition(16);
+            e.buttons[2].transport.push(locate).ok();
+        })
+        .build();
+    let r = h.handle_events(&config, &[InputEvent::ButtonB(Edge::Activate)], 0);
+    assert!(r
+        .midi
+        .iter()
+        .any(|s| matches!(s, MidiStep::SysEx(m) if m[..] == [0xF0, 0x7F, 0x7F, 0x06, 0x02, 0xF7])));
+    h.handle_events(&config, &[InputEvent::ButtonB(Edge::Deactivate)], 10);
+
+    // Song position goes out with the clock, which continues from there
+    let r = h.handle_events(&config, &[InputEvent::ButtonC(Edge::Activate)], 20);
+    assert_eq!(r.song_position, Some(16));
+    assert_eq!(r.clock_running, Some(true));
+    assert!(!r
+        .midi
+        .iter()
+        .any(|s| matches!(s, MidiStep::Send([0xF2 | 0xFB, ..], _, _))));
+}
+
+#[test]
+fn mmc_and_spp_fire_transport_triggers() {
+    use transport::{MmcCommand, TransportMatch};
+    let (config, mut h) = Setup::new()
+        .ext(|e| {
+            let strobe = cc85_trigger(TransportMatch::Mmc(MmcCommand::RecordStrobe));
+            e.transport_triggers.push(strobe).ok();
+            let start = cc85_trigger(TransportMatch::SongPosition(Some(0)));
+            e.transport_triggers.push(start).ok();
+        })
+        .build();
+    let r = h.process_transport(
+        &config,
+        transport::TransportEvent::Mmc(transport::MmcCommand::RecordStrobe),
+    );
+    assert!(sends_cc85(&r));
+    let r = h.process_transport(
+        &config,
+        transport::TransportEvent::Mmc(transport::MmcCommand::Stop),
+    );
+    assert!(!sends_cc85(&r));
+
+    assert!(sends_cc85(&h.process_incoming_midi(&config, &[0xF2, 0, 0])));
+    assert!(!sends_cc85(
+        &h.process_incoming_midi(&config, &[0xF2, 8, 0])
+    ));
+}
+
+#[test]
+fn snapshot_button_reports_current_state_as_defaults() {
+    let mut config = make_config();
+    config.presets[0].buttons[1].mode = ButtonMode::Toggle;
+    let mut ext = preset_ext::PresetExt::default();
+    ext.buttons[2].snapshot = true;
+    let mut h = PeHandler::new();
+    h.set_preset_ext(0, ext);
+    h.set_encoder_value(0, 90);
+
+    let r = h.handle_events(&config, &[InputEvent::ButtonB(Edge::Activate)], 0);
+    assert!(!r.snapshot);
+    h.handle_events(&config, &[InputEvent::ButtonB(Edge::Deactivate)], 10);
+    h.handle_events(&config, &[InputEvent::ButtonA(Edge::Activate)], 20);
+    let r = h.handle_events(&config, &[InputEvent::ButtonC(Edge::Activate)], 30);
+    assert!(r.snapshot);
+
+    // Held momentary button A is not stored as on
+    let defaults = h.defaults_snapshot(&config);
+    assert_eq!(defaults.button_active[..], [false, true, false]);
+    assert_eq!(defaults.encoder_values[..], [90]);
+}
@@ -3,19 +3,55 @@
 /// Match the firmware's MAX_PRESET_SIZE constant.
 pub const MAX_PRESET_SIZE: usize = 256;
 
+/// Match the firmware's MAX_PE_REPLY_SIZE constant.
+pub const MAX_PE_REPLY_SIZE: usize = 350;
+
+#[path = "../../src/bank.rs"]
+mod bank;
+
 #[path = "../../src/persist.rs"]
 mod persist;
 
 #[path = "../../src/pe_sysex.rs"]
 mod pe_sysex;
 
+use bank::BANK_SELECT_RESOURCE;
 use midi_controller::config::{GLOBAL_CONFIG_RESOURCE, SYSTEM_COMMAND_RESOURCE};
 use midi_controller::property_exchange;
-use pe_sysex::handle_set;
-use persist::PersistCommand;
+use pe_sysex::{build_get_chunk, build_get_error, build_reply, handle_set};
+use persist::{
+    PersistCommand, ReplyTo, SetStatus, UploadCommand, BACKUP_RESOURCE, UPLOAD_RESOURCE,
+};
 
 const SRC_MUID: [u8; 4] = [0x10, 0x20, 0x30, 0x40];
-const DST_MUID: [u8; 4] = [0x01, 0x02, 0x03, 0x04];
+const DST_MUID: [u8; 4] = pe_sysex::DEVICE_MUID;
+
+/// Status byte of a Set or Get reply, checked against the crate's parser
+/// for the codes `PeStatus` knows.
+fn reply_status(reply: &[u8]) -> u8 {
+    // Header length, then the header starting with the status
+    let status = reply[17];
+    assert!(reply[15] | reply[16] != 0);
+    assert_eq!(
+        property_exchange::extract_reply_status(reply),
+        property_exchange::PeStatus::from_byte(status)
+    );
+    status
+}
+
+/// Set inquiry announcing chunk `chunk` of `chunks`.
+fn set_chunk_inquiry(resource: u8, chunks: u16, chunk: u16, body: &[u8]) -> Vec<u8> {
+    let mut msg =
+        property_exchange::build_set_inquiry(SRC_MUID, DST_MUID, 0x05, resource, body).to_vec();
+    // Chunk fields follow the 1-byte header
+    msg[18..22].copy_from_slice(&[
+        (chunks & 0x7F) as u8,
+        (chunks >> 7) as u8,
+        (chunk & 0x7F) as u8,
+        (chunk >> 7) as u8,
+    ]);
+    msg
+}
 
 #[test]
 fn handle_set_reboot_command() {
@@ -79,12 +115,21 @@ fn handle_set_preset_upload() {
         property_exchange::build_set_inquiry(SRC_MUID, DST_MUID, 0x01, resource, &preset_data);
     let result = handle_set(&msg).expect("should parse valid set property");
     match result.command {
-        Some(PersistCommand::SavePreset(idx, ref blob)) => {
+        Some(PersistCommand::SavePreset(idx, ref blob, reply_to)) => {
             assert_eq!(idx, 0);
             assert_eq!(blob.as_slice(), &preset_data);
+            assert_eq!(
+                reply_to,
+                Some(ReplyTo {
+                    muid: SRC_MUID,
+                    request_id: 0x01,
+                })
+            );
         }
         other => panic!("expected SavePreset(0, ...), got {:?}", other),
     }
+    // Not acknowledged until the persist task has stored it
+    assert!(result.reply.is_none());
 }
 
 #[test]
@@ -99,7 +144,7 @@ fn handle_set_global_config() {
     );
     let result = handle_set(&msg).expect("should parse valid set property");
     match result.command {
-        Some(PersistCommand::SavePreset(idx, ref blob)) => {
+        Some(PersistCommand::SavePreset(idx, ref blob, _)) => {
             assert_eq!(idx, GLOBAL_CONFIG_RESOURCE);
             assert_eq!(blob.as_slice(), &config_data);
         }
@@ -110,6 +155,70 @@ fn handle_set_global_config() {
     }
 }
 
+#[test]
+fn handle_set_upload_transaction_commands() {
+    for (byte, want) in [
+        (0x01u8, UploadCommand::Begin),
+        (0x02, UploadCommand::Commit),
+        (0x03, UploadCommand::Abort),
+    ] {
+        let msg = property_exchange::build_set_inquiry(
+            SRC_MUID,
+            DST_MUID,
+            0x01,
+            UPLOAD_RESOURCE,
+            &[byte],
+        );
+        let result = handle_set(&msg).expect("should parse valid set property");
+        match result.command {
+            Some(PersistCommand::Upload(cmd, reply_to)) => {
+                assert_eq!(cmd, want);
+                assert_eq!(reply_to.muid, SRC_MUID);
+            }
+            other => panic!("expected Upload({:?}), got {:?}", want, other),
+        }
+    }
+    // Unknown command byte is rejected right away
+    let msg =
+        property_exchange::build_set_inquiry(SRC_MUID, DST_MUID, 0x01, UPLOAD_RESOURCE, &[0x09]);
+    let result = handle_set(&msg).unwrap();
+    assert!(result.command.is_none());
+    assert_eq!(
+        reply_status(&result.reply.unwrap()),
+        SetStatus::FormatError as u8
+    );
+}
+
+#[test]
+fn handle_set_bank_select() {
+    let msg =
+        property_exchange::build_set_inquiry(SRC_MUID, DST_MUID, 0x02, BANK_SELECT_RESOURCE, &[3]);
+    let result = handle_set(&msg).expect("should parse valid set property");
+    // Replied to by the persist task once the switch is stored
+    assert!(result.reply.is_none());
+    match result.command {
+        Some(PersistCommand::SelectBank(3, Some(reply_to))) => {
+            assert_eq!(reply_to.muid, SRC_MUID);
+            assert_eq!(reply_to.request_id, 0x02);
+        }
+        other => panic!("expected SelectBank(3), got {:?}", other),
+    }
+    // The body is exactly one byte
+    let msg = property_exchange::build_set_inquiry(
+        SRC_MUID,
+        DST_MUID,
+        0x02,
+        BANK_SELECT_RESOURCE,
+        &[1, 2],
+    );
+    let result = handle_set(&msg).unwrap();
+    assert!(result.command.is_none());
+    assert_eq!(
+        reply_status(&result.reply.unwrap()),
+        SetStatus::FormatError as u8
+    );
+}
+
 #[test]
 fn handle_set_invalid_sysex() {
     let garbage: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 0x05];
@@ -130,6 +239,110 @@ fn handle_set_reply_contains_correct_request_id() {
     );
     let result = handle_set(&msg).expect("should parse valid set property");
     // request_id is at offset 14 in the reply SysEx
-    let reply_req_id = property_exchange::request_id(&result.reply);
+    let reply = result
+        .reply
+        .expect("system commands are acknowledged right away");
+    let reply_req_id = property_exchange::request_id(&reply);
     assert_eq!(reply_req_id, req_id, "reply should echo the request_id");
+    assert_eq!(reply_status(&reply), SetStatus::Ok as u8);
+}
+
+#[test]
+fn build_reply_carries_status() {
+    let to = ReplyTo {
+        muid: SRC_MUID,
+        request_id: 0x17,
+    };
+    for status in [
+        SetStatus::Ok,
+        SetStatus::OutOfRange,
+        SetStatus::FormatError,
+        SetStatus::StorageFull,
+        SetStatus::FlashError,
+        SetStatus::Busy,
+    ] {
+        let reply = build_reply(to, status);
+        assert_eq!(reply_status(&reply), status as u8);
+        assert_eq!(property_exchange::request_id(&reply), 0x17);
+    }
+    // Codes shared with PeStatus match it
+    assert_eq!(
+        SetStatus::FormatError as u8,
+        property_exchange::PeStatus::FormatError as u8
+    );
+}
+
+#[test]
+fn handle_set_restore_chunk() {
+    let body = [0x50u8, 0x42, 0x01, 0x00, 0xFF];
+    let msg = set_chunk_inquiry(BACKUP_RESOURCE, 130, 129, &body);
+    let result = handle_set(&msg).unwrap();
+    match result.command {
+        Some(PersistCommand::Restore(chunk, chunks, ref data, reply_to)) => {
+            assert_eq!((chunk, chunks), (129, 130));
+            assert_eq!(data.as_slice(), &body);
+            assert_eq!(reply_to.request_id, 0x05);
+        }
+        other => panic!("expected Restore, got {:?}", other),
+    }
+    assert!(result.reply.is_none());
+
+    // Chunk numbers start at 1 and stay within the count
+    for (chunks, chunk) in [(3, 0), (3, 4)] {
+        let msg = set_chunk_inquiry(BACKUP_RESOURCE, chunks, chunk, &body);
+        let result = handle_set(&msg).unwrap();
+        assert!(result.command.is_none());
+        assert_eq!(
+            reply_status(&result.reply.unwrap()),
+            SetStatus::FormatError as u8
+        );
+    }
+}
+
+#[test]
+fn build_get_chunk_announces_chunk() {
+    let to = ReplyTo {
+        muid: SRC_MUID,
+        request_id: 0x21,
+    };
+    let body = [0x80u8; 256];
+    let reply = build_get_chunk(to, BACKUP_RESOURCE, 200, 150, &body);
+    assert!(property_exchange::is_get_reply(&reply));
+    assert_eq!(property_exchange::request_id(&reply), 0x21);
+    assert_eq!(
+        property_exchange::extract_reply_status(&reply),
+        Some(property_exchange::PeStatus::Ok)
+    );
+
+    // num_chunks, chunk_num, body_len precede the body the crate's parser finds
+    let encoded = property_exchange::extract_get_body(&reply).unwrap();
+    let body_pos = encoded.as_ptr() as usize - reply.as_ptr() as usize;
+    assert_eq!(
+        reply[body_pos - 6..body_pos - 2],
+        [200 & 0x7F, 200 >> 7, 150 & 0x7F, 150 >> 7]
+    );
+    let mut decoded = [0u8; 300];
+    let len = property_exchange::decode_mcoded7(encoded, &mut decoded);
+    assert_eq!(decoded[..len], body);
+}
+
+#[test]
+fn build_get_error_carries_status() {
+    let to = ReplyTo {
+        muid: SRC_MUID,
+        request_id: 0x22,
+    };
+    let reply = build_get_error(to, BACKUP_RESOURCE, SetStatus::Busy);
+    assert!(property_exchange::is_get_reply(&reply));
+    assert_eq!(property_exchange::request_id(&reply), 0x22);
+    assert_eq!(reply_status(&reply), SetStatus::Busy as u8);
+    assert_eq!(reply[18], BACKUP_RESOURCE);
+    assert_eq!(property_exchange::extract_get_body(&reply), Some(&[][..]));
+
+    let reply = build_get_error(to, BACKUP_RESOURCE, SetStatus::FormatError);
+    assert_eq!(
+        property_exchange::extract_reply_status(&reply),
+        Some(property_exchange::PeStatus::FormatError)
+    );
+    assert_eq!(property_exchange::extract_get_body(&reply), Some(&[][..]));
 }
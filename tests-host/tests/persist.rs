@@ -0,0 +1,50 @@
+// Host-side tests for src/persist.rs
+
+/// Match the firmware's MAX_PRESET_SIZE constant.
+pub const MAX_PRESET_SIZE: usize = 256;
+
+#[path = "../../src/persist.rs"]
+mod persist;
+
+use midi_controller::config::GlobalConfig;
+use persist::RuntimeOverride;
+
+#[test]
+fn runtime_override_round_trips_through_config_values() {
+    for bpm in [None, Some(30), Some(300)] {
+        for clock_running in [None, Some(false), Some(true)] {
+            let runtime = RuntimeOverride { bpm, clock_running };
+            let (bpm, clock) = runtime.to_values();
+            assert_eq!(
+                RuntimeOverride::from_values(Some(bpm), Some(clock)),
+                runtime
+            );
+        }
+    }
+    // Nothing stored yet
+    assert_eq!(
+        RuntimeOverride::from_values(None, None),
+        RuntimeOverride::default()
+    );
+}
+
+#[test]
+fn runtime_override_only_replaces_what_changed() {
+    let uploaded = GlobalConfig {
+        bpm: 90,
+        midi_clock: true,
+        ..Default::default()
+    };
+
+    let mut gc = uploaded.clone();
+    RuntimeOverride::default().apply(&mut gc);
+    assert_eq!(gc, uploaded);
+
+    let runtime = RuntimeOverride {
+        bpm: Some(128),
+        clock_running: None,
+    };
+    runtime.apply(&mut gc);
+    assert_eq!(gc.bpm, 128);
+    assert!(gc.midi_clock);
+}
@@ -0,0 +1,94 @@
+// Host-side tests for src/profile.rs
+
+#[path = "../../src/profile.rs"]
+mod profile;
+
+#[path = "../../src/slots.rs"]
+mod slots;
+
+use midi_controller::config::Label;
+use profile::{
+    profile_slot, Profile, ProfileEntry, Profiles, Semantic, SemanticAction, Template,
+    PROFILE_RESOURCE_BASE,
+};
+
+fn helix() -> Profile {
+    let mut p = Profile {
+        name: Label::try_from("Helix").unwrap(),
+        channel: 2,
+        ..Default::default()
+    };
+    p.entries
+        .push(ProfileEntry {
+            action: Semantic::Snapshot,
+            template: Template::CcValue { cc: 69, base: 0 },
+        })
+        .ok();
+    p.entries
+        .push(ProfileEntry {
+            action: Semantic::Tuner,
+            template: Template::Cc { cc: 68, value: 127 },
+        })
+        .ok();
+    p
+}
+
+#[test]
+fn profile_slot_range() {
+    assert_eq!(profile_slot(PROFILE_RESOURCE_BASE), Some(0));
+    assert_eq!(profile_slot(PROFILE_RESOURCE_BASE + 3), Some(3));
+    assert_eq!(profile_slot(PROFILE_RESOURCE_BASE + 4), None);
+    assert_eq!(profile_slot(0x60), None);
+}
+
+#[test]
+fn resolve_indexed_action_on_profile_channel() {
+    let p = helix();
+    let snapshot3 = SemanticAction {
+        action: Semantic::Snapshot,
+        index: 2,
+    };
+    assert_eq!(p.resolve(&snapshot3), Some(([0xB1, 69, 2], 3)));
+    let tuner = SemanticAction {
+        action: Semantic::Tuner,
+        index: 0,
+    };
+    assert_eq!(p.resolve(&tuner), Some(([0xB1, 68, 127], 3)));
+}
+
+#[test]
+fn resolve_unmapped_action_is_none() {
+    let looper = SemanticAction {
+        action: Semantic::LooperRecord,
+        index: 0,
+    };
+    assert_eq!(helix().resolve(&looper), None);
+}
+
+#[test]
+fn templates_build_messages() {
+    assert_eq!(
+        Template::CcNumber {
+            base: 34,
+            value: 127
+        }
+        .message(1, 2),
+        ([0xB0, 36, 127], 3)
+    );
+    assert_eq!(
+        Template::Program { base: 10 }.message(16, 1),
+        ([0xCF, 11, 0], 2)
+    );
+}
+
+#[test]
+fn profiles_skip_empty_slots() {
+    let mut profiles = Profiles::new();
+    profiles.set(1, helix());
+    assert!(profiles.get(0).is_none());
+    assert_eq!(profiles.get(1).unwrap().name.as_str(), "Helix");
+    let rev = profiles.revision;
+    profiles.set(1, Profile::default());
+    assert!(profiles.get(1).is_none());
+    assert_ne!(profiles.revision, rev);
+}
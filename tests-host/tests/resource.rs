@@ -0,0 +1,121 @@
+// Host-side tests for src/resource.rs
+
+/// Match the firmware's MAX_PRESET_SIZE constant.
+pub const MAX_PRESET_SIZE: usize = 256;
+pub const FLASH_FORMAT_VERSION: u8 = midi_controller::config::PRESET_SCHEMA_VERSION;
+pub const LOCAL_FORMAT_VERSION: u8 = 1;
+
+#[path = "../../src/bank.rs"]
+mod bank;
+
+#[path = "../../src/clock_out.rs"]
+mod clock_out;
+
+#[path = "../../src/eeprom.rs"]
+mod eeprom;
+
+#[path = "../../src/lfo.rs"]
+mod lfo;
+
+#[path = "../../src/looper.rs"]
+mod looper;
+
+#[path = "../../src/persist.rs"]
+mod persist;
+
+#[path = "../../src/preset_ext.rs"]
+mod preset_ext;
+
+#[path = "../../src/profile.rs"]
+mod profile;
+
+#[path = "../../src/setlist.rs"]
+mod setlist;
+
+#[path = "../../src/settings.rs"]
+mod settings;
+
+#[path = "../../src/slots.rs"]
+mod slots;
+
+#[path = "../../src/transport.rs"]
+mod transport;
+
+#[path = "../../src/resource.rs"]
+mod resource;
+
+use midi_controller::config::GLOBAL_CONFIG_RESOURCE;
+use midi_controller::state::DefaultPresetStateStore;
+use resource::{decode, decode_state, Resource};
+
+#[test]
+fn resource_indices_map_to_their_kind() {
+    assert_eq!(Resource::of(0x00), Some(Resource::Preset(0)));
+    assert_eq!(Resource::of(0x1F), Some(Resource::Preset(31)));
+    assert_eq!(Resource::of(0x21), Some(Resource::PresetExt(1)));
+    assert_eq!(Resource::of(0x42), Some(Resource::Song(2)));
+    assert_eq!(Resource::of(0x60), Some(Resource::Settings));
+    assert_eq!(Resource::of(0x61), Some(Resource::Profile(0)));
+    assert_eq!(Resource::of(0x67), Some(Resource::State));
+    assert_eq!(Resource::of(0x69), Some(Resource::Banks));
+    assert_eq!(
+        Resource::of(GLOBAL_CONFIG_RESOURCE),
+        Some(Resource::GlobalConfig)
+    );
+    // Upload control and read-only resources take no typed body
+    assert_eq!(Resource::of(persist::UPLOAD_RESOURCE), None);
+    assert_eq!(Resource::of(0x68), None);
+}
+
+#[test]
+fn local_resources_have_their_own_format_version() {
+    assert_eq!(Resource::Preset(0).format_version(), FLASH_FORMAT_VERSION);
+    assert_eq!(
+        Resource::GlobalConfig.format_version(),
+        FLASH_FORMAT_VERSION
+    );
+    for resource in [
+        Resource::Settings,
+        Resource::PresetExt(0),
+        Resource::Profile(0),
+        Resource::Song(0),
+        Resource::Banks,
+    ] {
+        assert_eq!(resource.format_version(), LOCAL_FORMAT_VERSION);
+    }
+}
+
+#[test]
+fn empty_body_resets_to_default() {
+    assert_eq!(decode::<settings::Settings>(&[]), Some(Default::default()));
+    for index in [0x00, 0x20, 0x40, 0x60, 0x61, 0x67, 0x69] {
+        assert!(Resource::of(index).unwrap().accepts(&[]), "{index:#x}");
+    }
+}
+
+#[test]
+fn bodies_are_checked_against_their_resource() {
+    let mut buf = [0u8; MAX_PRESET_SIZE];
+    let song = setlist::Song {
+        name: midi_controller::config::Label::try_from("Intro").unwrap(),
+        preset: 3,
+        scene: None,
+    };
+    let body = postcard::to_slice(&song, &mut buf).unwrap();
+    assert!(Resource::Song(0).accepts(body));
+    assert_eq!(decode::<setlist::Song>(body), Some(song));
+
+    let garbage = [0xFF; 8];
+    assert!(!Resource::Settings.accepts(&garbage));
+    assert!(!Resource::PresetExt(0).accepts(&garbage));
+    assert!(!Resource::Banks.accepts(&garbage));
+}
+
+#[test]
+fn state_image_must_be_a_valid_eeprom_layout() {
+    let cleared = DefaultPresetStateStore::cleared_eeprom();
+    assert_eq!(decode_state(&[]), Some(cleared));
+    assert_eq!(decode_state(&cleared), Some(cleared));
+    assert_eq!(decode_state(&cleared[..64]), None);
+    assert_eq!(decode_state(&[0u8; 128]), None);
+}
@@ -0,0 +1,85 @@
+// Host-side tests for src/setlist.rs
+
+#[path = "../../src/setlist.rs"]
+mod setlist;
+
+#[path = "../../src/slots.rs"]
+mod slots;
+
+use midi_controller::config::Label;
+use setlist::{song_slot, Setlist, Song, SongStep, SONG_RESOURCE_BASE};
+
+fn song(name: &str, preset: u8) -> Song {
+    Song {
+        name: Label::try_from(name).unwrap(),
+        preset,
+        scene: None,
+    }
+}
+
+fn make_setlist() -> Setlist {
+    let mut s = Setlist::new();
+    s.set(0, song("One", 3));
+    s.set(2, song("Three", 1));
+    s.set(3, song("Four", 1));
+    s
+}
+
+#[test]
+fn song_slot_range() {
+    assert_eq!(song_slot(SONG_RESOURCE_BASE), Some(0));
+    assert_eq!(song_slot(SONG_RESOURCE_BASE + 31), Some(31));
+    assert_eq!(song_slot(SONG_RESOURCE_BASE + 32), None);
+    assert_eq!(song_slot(0x05), None);
+}
+
+#[test]
+fn empty_setlist_is_inactive() {
+    let s = Setlist::new();
+    assert!(!s.is_active());
+    assert_eq!(s.step(None, SongStep::Next), None);
+}
+
+#[test]
+fn step_skips_empty_slots_and_wraps() {
+    let s = make_setlist();
+    assert!(s.is_active());
+    assert_eq!(s.step(None, SongStep::Next), Some(0));
+    assert_eq!(s.step(Some(0), SongStep::Next), Some(2));
+    assert_eq!(s.step(Some(3), SongStep::Next), Some(0));
+    assert_eq!(s.step(Some(0), SongStep::Prev), Some(3));
+    assert_eq!(s.step(Some(2), SongStep::Prev), Some(0));
+}
+
+#[test]
+fn clearing_a_slot_removes_it_from_the_order() {
+    let mut s = make_setlist();
+    let rev = s.revision;
+    s.set(2, Song::default());
+    assert_ne!(s.revision, rev);
+    assert_eq!(s.get(2), None);
+    assert_eq!(s.step(Some(0), SongStep::Next), Some(3));
+}
+
+#[test]
+fn up_next_and_position() {
+    let s = make_setlist();
+    assert_eq!(s.up_next(0).map(|x| x.name.as_str()), Some("Three"));
+    assert_eq!(s.up_next(3).map(|x| x.name.as_str()), Some("One"));
+    assert_eq!(s.position(0), 1);
+    assert_eq!(s.position(3), 3);
+
+    let mut single = Setlist::new();
+    single.set(4, song("Only", 0));
+    assert!(single.up_next(4).is_none());
+}
+
+#[test]
+fn song_roundtrips_through_postcard() {
+    let mut s = song("Encore", 7);
+    s.scene = Some(2);
+    let mut buf = [0u8; 64];
+    let bytes = postcard::to_slice(&s, &mut buf).unwrap();
+    let back: Song = postcard::from_bytes(bytes).unwrap();
+    assert_eq!(back, s);
+}
@@ -0,0 +1,458 @@
+// Host-side tests for src/storage.rs
+
+#[path = "../../src/bank.rs"]
+mod bank;
+
+#[path = "../../src/storage.rs"]
+mod storage;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use embedded_storage_async::nor_flash::{
+    ErrorType, MultiwriteNorFlash, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use futures::executor::block_on;
+use storage::{encode_key, ConfigStore, ItemCount, StorageError, UploadState};
+
+const SECTOR_SIZE: usize = 4096;
+const SECTORS: usize = 4;
+
+struct Memory {
+    data: Vec<u8>,
+    /// Bytes that can still be programmed before power is cut (None = no cut).
+    power_budget: Option<usize>,
+}
+
+/// RAM-backed NOR flash: erase sets bytes to 0xFF, writes can only clear
+/// bits. Clones share the memory, so a new store on a clone is a reboot.
+#[derive(Clone)]
+struct RamFlash(Rc<RefCell<Memory>>);
+
+impl RamFlash {
+    fn new() -> Self {
+        Self(Rc::new(RefCell::new(Memory {
+            data: vec![0xFF; SECTOR_SIZE * SECTORS],
+            power_budget: None,
+        })))
+    }
+
+    /// Cut power after `bytes` more programmed bytes: the write in progress
+    /// stops halfway and everything after it fails.
+    fn cut_power_after(&self, bytes: usize) {
+        self.0.borrow_mut().power_budget = Some(bytes);
+    }
+
+    fn restore_power(&self) {
+        self.0.borrow_mut().power_budget = None;
+    }
+
+    fn flip_bit(&self, offset: usize, bit: u8) {
+        self.0.borrow_mut().data[offset] ^= 1 << bit;
+    }
+
+    /// Offset of the first occurrence of `needle`.
+    fn find(&self, needle: &[u8]) -> Option<usize> {
+        self.0
+            .borrow()
+            .data
+            .windows(needle.len())
+            .position(|w| w == needle)
+    }
+}
+
+#[derive(Debug)]
+struct PowerLoss;
+
+impl NorFlashError for PowerLoss {
+    fn kind(&self) -> NorFlashErrorKind {
+        NorFlashErrorKind::Other
+    }
+}
+
+impl ErrorType for RamFlash {
+    type Error = PowerLoss;
+}
+
+impl ReadNorFlash for RamFlash {
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let mem = self.0.borrow();
+        let start = offset as usize;
+        bytes.copy_from_slice(&mem.data[start..start + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        SECTOR_SIZE * SECTORS
+    }
+}
+
+impl NorFlash for RamFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = SECTOR_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let mut mem = self.0.borrow_mut();
+        if mem.power_budget == Some(0) {
+            return Err(PowerLoss);
+        }
+        mem.data[from as usize..to as usize].fill(0xFF);
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let mut mem = self.0.borrow_mut();
+        for (i, b) in bytes.iter().enumerate() {
+            match mem.power_budget {
+                Some(0) => return Err(PowerLoss),
+                Some(ref mut left) => *left -= 1,
+                None => {}
+            }
+            mem.data[offset as usize + i] &= b;
+        }
+        Ok(())
+    }
+}
+
+impl MultiwriteNorFlash for RamFlash {}
+
+fn open(flash: &RamFlash) -> ConfigStore<RamFlash> {
+    ConfigStore::new(flash.clone(), 0..(SECTOR_SIZE * SECTORS) as u32).unwrap()
+}
+
+fn preset(store: &mut ConfigStore<RamFlash>, index: u8) -> Option<Vec<u8>> {
+    let mut buf = [0u8; 300];
+    block_on(store.load_preset(index, &mut buf)).map(|d| d.to_vec())
+}
+
+#[test]
+fn key_encoding() {
+    assert_eq!(encode_key(1, 2, 3), 0x2203);
+    assert_eq!(encode_key(3, 31, 255), 0x7FFF);
+}
+
+#[test]
+fn device_state_keys_stay_where_firmware_wrote_them() {
+    // Written as block 8, which wrapped to block 0
+    assert_eq!(storage::ACTIVE_PRESET_KEY, encode_key(0, 0, 0));
+    assert_eq!(storage::TEMPO_KEY, encode_key(0, 0, 2));
+    assert_eq!(storage::CLOCK_RUNNING_KEY, encode_key(0, 0, 3));
+    assert_eq!(storage::ACTIVE_BANK_KEY, encode_key(0, 0, 4));
+
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    block_on(store.save_value(storage::TEMPO_KEY, 140)).unwrap();
+    block_on(store.save(0, 0, 4, 3)).unwrap();
+    block_on(store.save_active_song(5)).unwrap();
+
+    let mut store = open(&flash);
+    assert_eq!(block_on(store.load(0, 0, 2)), Some(140));
+    assert_eq!(block_on(store.load(0, 0, 1)), Some(5));
+    assert_eq!(
+        block_on(store.load_value(storage::ACTIVE_BANK_KEY)),
+        Some(3)
+    );
+    assert_eq!(block_on(store.load_value(storage::CLOCK_RUNNING_KEY)), None);
+    assert_eq!(block_on(store.load_value(storage::ACTIVE_PRESET_KEY)), None);
+}
+
+#[test]
+fn config_values_survive_reboot() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    block_on(store.save(1, 0, 2, 500)).unwrap();
+    block_on(store.save_preset(0, &[1, 2, 3])).unwrap();
+    block_on(store.save_preset(1, &[])).unwrap();
+    block_on(store.save_preset(2, &[7; 200])).unwrap();
+    block_on(store.save(1, 0, 2, 600)).unwrap();
+    block_on(store.save(2, 1, 0, 7)).unwrap();
+
+    let mut store = open(&flash);
+    assert_eq!(block_on(store.load(1, 0, 2)), Some(600));
+    assert_eq!(block_on(store.load(1, 0, 3)), None);
+    // Only the latest value per key; preset blobs are not config entries
+    assert_eq!(
+        block_on(store.load_all())[..],
+        [(1, 0, 2, 600), (2, 1, 0, 7)]
+    );
+}
+
+#[test]
+fn preset_blobs_load_latest_and_truncate() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    block_on(store.save_preset(3, &[1, 2, 3])).unwrap();
+    block_on(store.save_preset(3, &[4, 5])).unwrap();
+    block_on(store.save_preset(7, &[9; 40])).unwrap();
+
+    let mut store = open(&flash);
+    assert_eq!(preset(&mut store, 3), Some(vec![4, 5]));
+    assert_eq!(preset(&mut store, 4), None);
+    let mut small = [0u8; 8];
+    assert_eq!(
+        block_on(store.load_preset(7, &mut small)).map(|d| d.len()),
+        Some(8)
+    );
+
+    let mut found = Vec::new();
+    block_on(store.load_all_presets(|idx, data| found.push((idx, data.len()))));
+    assert_eq!(found, [(3, 2), (7, 40)]);
+}
+
+#[test]
+fn erase_all_clears_everything() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    block_on(store.save(0, 1, 0, 42)).unwrap();
+    block_on(store.save_preset(0, &[1; 16])).unwrap();
+    block_on(store.erase_all()).unwrap();
+
+    let mut store = open(&flash);
+    assert_eq!(block_on(store.load(0, 1, 0)), None);
+    assert_eq!(preset(&mut store, 0), None);
+}
+
+#[test]
+fn staged_upload_is_separate_from_live_blobs() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    block_on(store.save_preset(2, &[1; 8])).unwrap();
+    block_on(store.set_upload_state(UploadState::Open)).unwrap();
+    block_on(store.stage_preset(2, &[2; 8])).unwrap();
+    block_on(store.stage_preset(0x40, &[])).unwrap();
+
+    // Staging leaves the live blob alone and survives a reboot
+    let mut store = open(&flash);
+    assert_eq!(block_on(store.upload_state()), UploadState::Open);
+    assert_eq!(preset(&mut store, 2), Some(vec![1; 8]));
+    let mut buf = [0u8; 16];
+    assert_eq!(
+        block_on(store.load_staged(2, &mut buf)),
+        Some(&[2u8; 8][..])
+    );
+    assert_eq!(block_on(store.load_staged(0x40, &mut buf)), Some(&[][..]));
+
+    block_on(store.discard_staged(u128::MAX)).unwrap();
+    assert_eq!(block_on(store.upload_state()), UploadState::Idle);
+    assert_eq!(block_on(store.load_staged(2, &mut buf)), None);
+    assert_eq!(block_on(store.load_staged(0x40, &mut buf)), None);
+    assert_eq!(preset(&mut store, 2), Some(vec![1; 8]));
+}
+
+#[test]
+fn banks_keep_presets_apart() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    block_on(store.save_preset(0, &[1; 8])).unwrap();
+    block_on(store.save_preset(0x20, &[2; 8])).unwrap();
+    block_on(store.save_preset(0x40, &[3; 8])).unwrap();
+    block_on(store.save_preset(0x60, &[6; 8])).unwrap();
+
+    // Presets, extensions and songs are per bank, other resources shared
+    store.set_bank(2);
+    assert_eq!(preset(&mut store, 0), None);
+    assert_eq!(preset(&mut store, 0x20), None);
+    assert_eq!(preset(&mut store, 0x40), None);
+    assert_eq!(preset(&mut store, 0x60), Some(vec![6; 8]));
+    block_on(store.save_preset(0, &[4; 8])).unwrap();
+    block_on(store.save_preset(0x60, &[5; 8])).unwrap();
+    let mut seen = Vec::new();
+    block_on(store.load_all_presets(|idx, data| seen.push((idx, data.to_vec()))));
+    assert_eq!(seen, vec![(0, vec![4; 8])]);
+
+    let mut store = open(&flash);
+    assert_eq!(preset(&mut store, 0), Some(vec![1; 8]));
+    assert_eq!(preset(&mut store, 0x20), Some(vec![2; 8]));
+    assert_eq!(preset(&mut store, 0x40), Some(vec![3; 8]));
+    assert_eq!(preset(&mut store, 0x60), Some(vec![5; 8]));
+
+    // Out of the key layout: stays on the current bank
+    store.set_bank(storage::BANK_SLOTS);
+    assert_eq!(preset(&mut store, 0), Some(vec![1; 8]));
+
+    // Runtime state kept per bank
+    let mut buf = [0u8; 128];
+    assert_eq!(block_on(store.load_bank_state(1, &mut buf)), None);
+    block_on(store.save_bank_state(1, &[7; 128])).unwrap();
+    assert_eq!(
+        block_on(store.load_bank_state(1, &mut buf)),
+        Some(&[7u8; 128][..])
+    );
+    assert_eq!(block_on(store.load_bank_state(0, &mut buf)), None);
+
+    // Bank blobs count as blobs in the health report
+    let health = block_on(store.health());
+    assert_eq!(health.blobs, ItemCount { live: 5, stale: 1 });
+    assert_eq!(health.other, ItemCount { live: 1, stale: 0 });
+}
+
+#[test]
+fn active_song_and_staging_are_per_bank() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    block_on(store.save_active_song(3)).unwrap();
+    block_on(store.stage_preset(0x40, &[1; 4])).unwrap();
+    store.set_bank(5);
+    assert_eq!(block_on(store.load_active_song()), None);
+    block_on(store.save_active_song(7)).unwrap();
+    block_on(store.stage_preset(0x40, &[2; 4])).unwrap();
+
+    let mut buf = [0u8; 16];
+    assert_eq!(
+        block_on(store.load_staged(0x40, &mut buf)),
+        Some(&[2u8; 4][..])
+    );
+    block_on(store.remove_staged(0x40)).unwrap();
+    assert_eq!(block_on(store.load_staged(0x40, &mut buf)), None);
+
+    let mut store = open(&flash);
+    assert_eq!(block_on(store.load_active_song()), Some(3));
+    assert_eq!(
+        block_on(store.load_staged(0x40, &mut buf)),
+        Some(&[1u8; 4][..])
+    );
+    store.set_bank(5);
+    assert_eq!(block_on(store.load_active_song()), Some(7));
+
+    // Bank staging counts as staged in the health report
+    block_on(store.stage_preset(0x67, &[3; 4])).unwrap();
+    assert_eq!(
+        block_on(store.health()).staged,
+        ItemCount { live: 2, stale: 1 }
+    );
+}
+
+#[test]
+fn power_loss_mid_write_keeps_previous_blob() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    block_on(store.save_preset(0, &[0xAA; 100])).unwrap();
+
+    flash.cut_power_after(40);
+    assert_eq!(
+        block_on(store.save_preset(0, &[0xBB; 100])),
+        Err(StorageError::Flash)
+    );
+
+    // Reboot: the torn write is ignored and the store keeps working
+    flash.restore_power();
+    let mut store = open(&flash);
+    assert_eq!(preset(&mut store, 0), Some(vec![0xAA; 100]));
+    block_on(store.save_preset(0, &[0xCC; 100])).unwrap();
+    let mut store = open(&flash);
+    assert_eq!(preset(&mut store, 0), Some(vec![0xCC; 100]));
+}
+
+#[test]
+fn bit_flip_is_never_returned_as_data() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    block_on(store.save_preset(5, &[0x11; 64])).unwrap();
+    block_on(store.save_preset(6, &[0x22; 64])).unwrap();
+
+    let offset = flash.find(&[0x11; 64]).unwrap();
+    flash.flip_bit(offset + 10, 3);
+
+    let mut store = open(&flash);
+    assert_eq!(preset(&mut store, 5), None);
+    // Other blobs are unaffected
+    assert_eq!(preset(&mut store, 6), Some(vec![0x22; 64]));
+}
+
+#[test]
+fn full_storage_is_reported() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    let err = (0..=255u8)
+        .map(|i| block_on(store.save_preset(i, &[i; 250])))
+        .find(Result::is_err);
+    assert_eq!(err, Some(Err(StorageError::Full)));
+    assert_eq!(store.take_error(), Some(StorageError::Full));
+    assert_eq!(store.take_error(), None);
+    // What was stored before stays readable
+    assert_eq!(preset(&mut store, 0), Some(vec![0; 250]));
+}
+
+#[test]
+fn health_reports_usage_per_key_range() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    let sector_data = (SECTOR_SIZE - 2 * 4) as u32;
+    let health = block_on(store.health());
+    assert_eq!(health.capacity, (SECTOR_SIZE * SECTORS) as u32);
+    assert_eq!((health.used, health.free), (0, 3 * sector_data));
+    assert_eq!(health.erases[..], [0; SECTORS]);
+
+    block_on(store.save(1, 0, 2, 500)).unwrap();
+    block_on(store.save(1, 0, 2, 600)).unwrap();
+    block_on(store.save_preset(3, &[1; 30])).unwrap();
+    block_on(store.save_preset(3, &[2; 10])).unwrap();
+    block_on(store.save_preset(4, &[])).unwrap();
+    block_on(store.stage_preset(3, &[3; 10])).unwrap();
+    block_on(store.discard_staged(1 << 3)).unwrap();
+
+    let health = block_on(store.health());
+    let count = |live, stale| ItemCount { live, stale };
+    assert_eq!(health.config, count(1, 1));
+    assert_eq!(health.blobs, count(2, 1));
+    assert_eq!(health.staged, count(0, 1));
+    assert_eq!(health.other, count(1, 0));
+    // Header + key + value, word aligned
+    let item = |len: u32| 8 + (2 + len).next_multiple_of(4);
+    let used = 2 * item(2) + item(30) + 2 * item(10) + item(0) + item(2);
+    assert_eq!(health.used, used);
+    assert_eq!(health.free, 3 * sector_data - used);
+    assert!(!health.corrupt);
+}
+
+/// `ConfigStore::health` parses sequential-storage's on-flash layout itself
+/// (the crate is pinned for it): fail here if an upgrade changes it.
+#[test]
+fn flash_layout_matches_health_parser() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    block_on(store.save_preset(3, &[0xA5; 6])).unwrap();
+    block_on(store.stage_preset(3, &[0x5A; 6])).unwrap();
+    block_on(store.remove_staged(3)).unwrap();
+
+    let data = flash.0.borrow().data.clone();
+    // Sector start marker in the first word, end marker word still erased
+    assert!(data[..4].iter().map(|b| b.count_zeros()).sum::<u32>() >= 4);
+    assert_eq!(data[SECTOR_SIZE - 4..SECTOR_SIZE], [0xFF; 4]);
+    // First item right after the marker: data CRC, length (LE), length CRC,
+    // then the key (LE) and the value, word aligned
+    let header = &data[4..12];
+    assert_ne!(header[..4], [0; 4]);
+    assert_eq!(u16::from_le_bytes([header[4], header[5]]), 2 + 6);
+    assert_eq!(data[12..14], 0x8003u16.to_le_bytes());
+    assert_eq!(data[14..20], [0xA5; 6]);
+    // A removed item keeps its length and has its data CRC zeroed
+    let next = 12 + 8usize.next_multiple_of(4);
+    assert_eq!(u16::from_le_bytes([data[next + 4], data[next + 5]]), 2 + 6);
+    assert_eq!(data[next..next + 4], [0; 4]);
+    assert_eq!(data[next + 8..next + 10], 0x8103u16.to_le_bytes());
+}
+
+#[test]
+fn erase_counts_survive_reboot_and_factory_reset() {
+    let flash = RamFlash::new();
+    let mut store = open(&flash);
+    // Rewriting one blob fills sectors until old ones are reclaimed
+    for i in 0..100u8 {
+        block_on(store.save_preset(0, &[i; 250])).unwrap();
+    }
+    let erases = block_on(store.health()).erases;
+    assert!(erases.iter().all(|&n| n > 0));
+    block_on(store.save_erase_counts()).unwrap();
+
+    let mut store = open(&flash);
+    assert_eq!(block_on(store.health()).erases, erases);
+    assert_eq!(preset(&mut store, 0), Some(vec![99; 250]));
+
+    block_on(store.erase_all()).unwrap();
+    let mut store = open(&flash);
+    let after = block_on(store.health()).erases;
+    assert!(after.iter().zip(&erases).all(|(a, e)| *a == e + 1));
+}
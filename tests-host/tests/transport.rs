@@ -0,0 +1,69 @@
+// Host-side tests for src/transport.rs
+
+#[path = "../../src/transport.rs"]
+mod transport;
+
+use transport::{
+    mmc_command, mmc_locate, parse_mmc, parse_song_position, song_position, MmcCommand,
+    TransportEvent, TransportMatch, MMC_ALL_DEVICES,
+};
+
+#[test]
+fn mmc_command_bytes() {
+    assert_eq!(
+        mmc_command(MMC_ALL_DEVICES, MmcCommand::Play)[..],
+        [0xF0, 0x7F, 0x7F, 0x06, 0x02, 0xF7]
+    );
+    assert_eq!(
+        mmc_command(0x10, MmcCommand::RecordStrobe)[..],
+        [0xF0, 0x7F, 0x10, 0x06, 0x06, 0xF7]
+    );
+}
+
+#[test]
+fn mmc_locate_bytes() {
+    assert_eq!(
+        mmc_locate(MMC_ALL_DEVICES, 1, 2, 3, 4)[..],
+        [0xF0, 0x7F, 0x7F, 0x06, 0x44, 0x06, 0x01, 0x61, 2, 3, 4, 0, 0xF7]
+    );
+}
+
+#[test]
+fn song_position_roundtrip() {
+    assert_eq!(song_position(0), [0xF2, 0, 0]);
+    assert_eq!(song_position(200), [0xF2, 200 & 0x7F, 1]);
+    assert_eq!(parse_song_position(&song_position(1000)), Some(1000));
+    assert_eq!(parse_song_position(&[0xB0, 1, 2]), None);
+}
+
+#[test]
+fn parse_mmc_filters_device() {
+    let play = mmc_command(0x10, MmcCommand::Play);
+    assert_eq!(
+        parse_mmc(&play, 0x10),
+        Some(TransportEvent::Mmc(MmcCommand::Play))
+    );
+    assert_eq!(parse_mmc(&play, 0x11), None);
+    // Broadcast is accepted by every device ID
+    let stop = mmc_command(MMC_ALL_DEVICES, MmcCommand::Stop);
+    assert_eq!(
+        parse_mmc(&stop, 0x11),
+        Some(TransportEvent::Mmc(MmcCommand::Stop))
+    );
+    let locate = mmc_locate(MMC_ALL_DEVICES, 0, 0, 0, 0);
+    assert_eq!(parse_mmc(&locate, 0x11), Some(TransportEvent::Locate));
+    // Unknown command, non-MMC SysEx
+    assert_eq!(parse_mmc(&[0xF0, 0x7F, 0x7F, 0x06, 0x7E, 0xF7], 0x7F), None);
+    assert_eq!(parse_mmc(&[0xF0, 0x7E, 0x7F, 0x06, 0x02, 0xF7], 0x7F), None);
+}
+
+#[test]
+fn trigger_matching() {
+    let any = TransportMatch::SongPosition(None);
+    let bar2 = TransportMatch::SongPosition(Some(16));
+    assert!(any.matches(&TransportEvent::SongPosition(5)));
+    assert!(bar2.matches(&TransportEvent::SongPosition(16)));
+    assert!(!bar2.matches(&TransportEvent::SongPosition(0)));
+    assert!(TransportMatch::Mmc(MmcCommand::Play).matches(&TransportEvent::Mmc(MmcCommand::Play)));
+    assert!(!TransportMatch::Mmc(MmcCommand::Play).matches(&TransportEvent::Locate));
+}